@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use crate::cli::ResolvedArgs;
+use crate::config::SshBackend;
+use crate::inventory::types::Host;
+use crate::metrics::{HostMetrics, HostStatus};
+use crate::ssh;
+
+/// `ansimon check`: connect to every selected host once, print a table of
+/// the results and a summary. Returns whether no more than `allow_failures`
+/// hosts were unreachable.
+pub async fn run(hosts: &[Host], args: Arc<ResolvedArgs>, allow_failures: usize) -> Result<bool> {
+    if args.ssh_multiplexing {
+        ssh::prepare_control_dir()?;
+    }
+    let results = match args.ssh_backend {
+        SshBackend::Openssh => ssh::check_hosts(hosts, args.clone(), Arc::new(ssh::OpenSsh)).await,
+        #[cfg(feature = "native-ssh")]
+        SshBackend::Native => {
+            ssh::check_hosts(hosts, args.clone(), Arc::new(ssh::Native::default())).await
+        }
+        #[cfg(not(feature = "native-ssh"))]
+        SshBackend::Native => unreachable!("ssh::backend refuses native in this build"),
+    };
+    if args.ssh_multiplexing {
+        // Don't leave masters running for ControlPersist after exiting
+        ssh::close_masters(hosts, &args).await;
+    }
+
+    let mut rows: Vec<(&Host, Option<&HostMetrics>)> = hosts
+        .iter()
+        .map(|h| (h, results.iter().find(|r| r.host_name == h.name)))
+        .collect();
+    rows.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+    print_table(&rows, &args);
+
+    let failed = results.iter().filter(|r| r.status != HostStatus::Up).count();
+    let disabled = hosts.len() - results.len();
+    let mut summary = format!("{} of {} host(s) reachable", results.len() - failed, results.len());
+    if disabled > 0 {
+        summary.push_str(&format!(", {disabled} disabled"));
+    }
+    if failed > 0 {
+        summary.push_str(&format!(", {failed} failed"));
+        if allow_failures > 0 {
+            summary.push_str(&format!(" ({allow_failures} allowed)"));
+        }
+    }
+    println!("{summary}");
+    Ok(failed <= allow_failures)
+}
+
+fn print_table(rows: &[(&Host, Option<&HostMetrics>)], args: &ResolvedArgs) {
+    let addresses: Vec<String> = rows
+        .iter()
+        .map(|(host, _)| {
+            if host.is_local() {
+                "local".to_string()
+            } else if host.collects_locally() {
+                "local command".to_string()
+            } else {
+                format!("{}:{}", host.effective_host(), args.port_for(host))
+            }
+        })
+        .collect();
+    let name_width = rows.iter().map(|(h, _)| h.name.len()).max().unwrap_or(0).max(4);
+    let addr_width = addresses.iter().map(String::len).max().unwrap_or(0).max(7);
+
+    println!(
+        "{:<name_width$}  {:<addr_width$}  {:<12}  {:>7}  RESULT",
+        "HOST", "ADDRESS", "USER", "LATENCY"
+    );
+    for ((host, result), address) in rows.iter().zip(&addresses) {
+        let latency = result
+            .and_then(|r| r.ssh_latency_ms)
+            .map(|ms| format!("{ms}ms"))
+            .unwrap_or_else(|| "-".to_string());
+        let outcome = match result {
+            None => "disabled".to_string(),
+            Some(r) if r.status == HostStatus::Up => "ok".to_string(),
+            Some(r) => {
+                let error = r.error.as_deref().unwrap_or("unreachable");
+                // ssh's stderr can span lines; keep one row per host
+                format!("FAILED: {}", error.lines().next().unwrap_or(""))
+            }
+        };
+        println!(
+            "{:<name_width$}  {:<addr_width$}  {:<12}  {latency:>7}  {outcome}",
+            host.name,
+            address,
+            args.user_for(host).unwrap_or("-"),
+        );
+    }
+}
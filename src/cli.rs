@@ -1,49 +1,190 @@
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+
+use std::collections::HashMap;
+
+use crate::config::{CustomMetric, HostKeyChecking, PrimaryGroupRule, SshBackend, SshSettings};
+use crate::inventory::types::Host;
 
 /// Ansimon - TUI monitor for Ansible inventories
 #[derive(Parser, Debug, Clone)]
 #[command(name = "ansimon", version, about)]
 pub struct Args {
-    /// Path to Ansible inventory file (INI or YAML)
-    #[arg(short, long)]
+    /// Ansible inventory file (INI, YAML, JSON or executable dynamic inventory script),
+    /// directory, or a comma-separated list of them
+    #[arg(short, long, global = true)]
     pub inventory: Option<String>,
 
-    /// Limit to subset of hosts (supports glob patterns, groups, exclusion with !)
-    #[arg(short, long)]
+    /// Limit to subset of hosts (supports glob patterns, groups, ~regex, exclusion with !)
+    #[arg(short, long, global = true)]
     pub limit: Option<String>,
 
+    /// Monitor the hosts of this group (repeatable; --limit narrows it)
+    #[arg(short, long, global = true)]
+    pub group: Vec<String>,
+
     /// Poll interval in seconds
     #[arg(long)]
     pub interval: Option<u64>,
 
     /// SSH user (overrides inventory)
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub user: Option<String>,
 
     /// Path to SSH private key
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub key: Option<String>,
 
     /// SSH port (overrides inventory)
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub port: Option<u16>,
 
     /// Maximum concurrent SSH connections
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub forks: Option<usize>,
+
+    /// Run the metrics command with sudo -n on every host (like ansible_become)
+    #[arg(short, long = "become", global = true)]
+    pub become_sudo: bool,
+
+    /// Prompt for an SSH password at startup, used for every host (needs sshpass)
+    #[arg(long, global = true)]
+    pub ask_pass: bool,
+
+    /// Refuse to start if any inventory line fails to parse
+    #[arg(long, global = true)]
+    pub strict: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Print the resolved inventory (after --limit) instead of starting the TUI
+    Inventory {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = DumpFormat::Table)]
+        format: DumpFormat,
+
+        #[command(subcommand)]
+        action: Option<InventoryCommand>,
+    },
+    /// Connect to every selected host once and report which are reachable;
+    /// exits 1 if more hosts fail than allowed
+    Check {
+        /// Number of unreachable hosts to tolerate before exiting 1
+        #[arg(long, default_value_t = 0)]
+        allow_failures: usize,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum InventoryCommand {
+    /// Summarize the inventory and report problems; exits 1 if any are found
+    Check,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    Table,
+    Json,
+    Yaml,
 }
 
 /// Resolved args after merging CLI + config + defaults
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ResolvedArgs {
     pub inventory: String,
     pub limit: Option<String>,
+    pub groups: Vec<String>,
     pub interval: u64,
+    /// `--user`, `--key` and `--port`: these beat inventory vars
     pub user: Option<String>,
     pub key: Option<String>,
     pub port: Option<u16>,
     pub forks: usize,
     pub ssh_timeout: u64,
+    pub command_timeout: u64,
+    pub jump_host: Option<String>,
+    pub ssh_multiplexing: bool,
+    pub ssh_backend: SshBackend,
+    pub sudo: bool,
+    /// Password from --ask-pass, kept in memory only
+    pub ssh_password: Option<String>,
+    pub stagger: bool,
+    pub max_connections_per_sec: u32,
+    pub retry_attempts: u32,
+    pub retry_delay_ms: u64,
     pub warning_threshold: f64,
     pub critical_threshold: f64,
+    pub load_warning_per_cpu: f64,
+    pub load_critical_per_cpu: f64,
+    pub temp_warning: f64,
+    pub temp_critical: f64,
+    pub entropy_warning: u32,
+    pub entropy_critical: u32,
+    pub scroll_lines: usize,
+    /// Column labels from the config's `visible_columns`
+    pub visible_columns: Vec<String>,
+    pub alert_on_transition: bool,
+    pub bar_charts: bool,
+    pub custom_metrics: Vec<CustomMetric>,
+    pub primary_group: PrimaryGroupRule,
+    pub host_key_checking: HostKeyChecking,
+    pub known_hosts_file: Option<String>,
+    pub ssh_config_file: Option<String>,
+    pub ignore_user_ssh_config: bool,
+    /// Filter presets from the config, sorted by name
+    pub filter_presets: Vec<(String, String)>,
+    pub strict: bool,
+    /// Top-level `user`, `port`, `key` and `proxy_command` from the config
+    pub config_ssh: SshSettings,
+    /// The config's per-group SSH settings, by group name
+    pub group_ssh: HashMap<String, SshSettings>,
+}
+
+impl ResolvedArgs {
+    /// The config's SSH settings for a host's group: its primary group's
+    /// entry, else the entry of the first of its groups that has one.
+    fn group_settings(&self, host: &Host) -> Option<&SshSettings> {
+        std::iter::once(host.display_group())
+            .chain(host.groups.iter().map(String::as_str))
+            .find_map(|group| self.group_ssh.get(group))
+    }
+
+    /// SSH user for a host: `--user`, its inventory var, its group's config
+    /// entry, then the config's default.
+    pub fn user_for<'a>(&'a self, host: &'a Host) -> Option<&'a str> {
+        self.user
+            .as_deref()
+            .or(host.ansible_user.as_deref())
+            .or_else(|| self.group_settings(host)?.user.as_deref())
+            .or(self.config_ssh.user.as_deref())
+    }
+
+    /// SSH port for a host, with the same precedence as `user_for`.
+    pub fn port_for(&self, host: &Host) -> u16 {
+        self.port
+            .or(host.ansible_port)
+            .or_else(|| self.group_settings(host)?.port)
+            .or(self.config_ssh.port)
+            .unwrap_or(22)
+    }
+
+    /// ProxyCommand for a host: its inventory var, its group's config entry,
+    /// then the config's default. An empty value means connect directly.
+    pub fn proxy_command_for<'a>(&'a self, host: &'a Host) -> Option<&'a str> {
+        host.proxy_command()
+            .or_else(|| self.group_settings(host)?.proxy_command.as_deref())
+            .or(self.config_ssh.proxy_command.as_deref())
+            .filter(|p| !p.is_empty())
+    }
+
+    /// Private key from the config for a host without `--key` or an
+    /// inventory key: its group's entry, else the config's default.
+    pub fn config_key_for(&self, host: &Host) -> Option<&str> {
+        self.group_settings(host)
+            .and_then(|s| s.key.as_deref())
+            .or(self.config_ssh.key.as_deref())
+    }
 }
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 
@@ -10,10 +11,118 @@ pub struct Config {
     pub interval: u64,
     pub forks: usize,
     pub ssh_timeout: u64,
+    /// Seconds a poll's command may run on the host, on top of ssh_timeout
+    pub command_timeout: u64,
     pub user: Option<String>,
     pub key: Option<String>,
     pub port: Option<u16>,
+    /// SSH user/port/key/proxy command for the hosts of an inventory group
+    pub groups: HashMap<String, SshSettings>,
     pub thresholds: Thresholds,
+    /// Rows moved per mouse wheel notch
+    pub scroll_lines: usize,
+    /// Table columns shown at startup, by header label; empty for the default
+    pub visible_columns: Vec<String>,
+    /// Ring the terminal bell when a host goes down or critical
+    pub alert_on_transition: bool,
+    /// Draw CPU and memory usage as bars in the table
+    pub bar_charts: bool,
+    /// User-defined shell snippets run on each host alongside the built-in metrics
+    pub custom_metrics: Vec<CustomMetric>,
+    /// Bastion(s) every host is reached through, as ssh's ProxyJump
+    pub jump_host: Option<String>,
+    /// ssh ProxyCommand for every host, e.g. through a SOCKS proxy
+    pub proxy_command: Option<String>,
+    /// Reuse one SSH connection per host across polls (ControlMaster)
+    #[serde(alias = "ssh_multiplex")]
+    pub ssh_multiplexing: bool,
+    /// How hosts are reached: the `ssh` binary, or in-process sessions
+    pub ssh_backend: SshBackend,
+    /// Spread each cycle's connection starts over up to 5 seconds
+    pub stagger: bool,
+    /// Most new SSH connections opened per second; 0 for no limit
+    pub max_connections_per_sec: u32,
+    /// Extra attempts after a failed SSH poll before a host is marked down
+    #[serde(alias = "retries")]
+    pub retry_attempts: u32,
+    /// Delay before the first retry; doubled for each further attempt
+    pub retry_delay_ms: u64,
+    /// How the group shown for a host in several groups is chosen
+    pub primary_group: PrimaryGroupRule,
+    /// Named filter strings, picked with Tab in filter mode or `--limit preset:NAME`
+    pub filter_presets: HashMap<String, String>,
+    /// How ssh treats unknown and changed host keys
+    pub host_key_checking: HostKeyChecking,
+    /// known_hosts file used instead of ssh's default
+    pub known_hosts_file: Option<String>,
+    /// ssh_config file passed to ssh with `-F`
+    pub ssh_config_file: Option<String>,
+    /// Pass `-F /dev/null` so ssh reads no config file at all
+    pub ignore_user_ssh_config: bool,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HostKeyChecking {
+    /// Only connect to hosts whose key is already known
+    Strict,
+    /// Record keys of new hosts, refuse changed ones
+    #[default]
+    AcceptNew,
+    /// Don't check host keys, nor record them
+    Off,
+}
+
+impl HostKeyChecking {
+    /// Parse an inventory var: a mode name, or an Ansible-style boolean
+    /// (`ansible_host_key_checking=false` turns checking off).
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "strict" | "true" | "yes" => Some(HostKeyChecking::Strict),
+            "accept-new" => Some(HostKeyChecking::AcceptNew),
+            "off" | "false" | "no" => Some(HostKeyChecking::Off),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SshBackend {
+    /// Run the OpenSSH client for every poll
+    #[default]
+    Openssh,
+    /// Keep one libssh2 session per host open across polls (`native-ssh`)
+    Native,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PrimaryGroupRule {
+    /// First group by name
+    #[default]
+    Sorted,
+    /// Most deeply nested group, ties broken by name
+    Deepest,
+}
+
+/// SSH settings for the hosts of a group, from the config's `groups:` map.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct SshSettings {
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    pub key: Option<String>,
+    pub proxy_command: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct CustomMetric {
+    pub name: String,
+    /// Shell snippet; its trimmed stdout becomes the metric's value
+    pub command: String,
+    pub unit: String,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -21,6 +130,16 @@ pub struct Config {
 pub struct Thresholds {
     pub warning: f64,
     pub critical: f64,
+    /// 1-minute load per CPU above which a host is in warning
+    pub load_warning_per_cpu: f64,
+    /// 1-minute load per CPU above which a host is critical
+    pub load_critical_per_cpu: f64,
+    /// Hottest thermal zone, in °C
+    pub temp_warning: f64,
+    pub temp_critical: f64,
+    /// Available entropy (bits) below which a host is in warning / critical
+    pub entropy_warning: u32,
+    pub entropy_critical: u32,
 }
 
 impl Default for Config {
@@ -30,10 +149,31 @@ impl Default for Config {
             interval: 10,
             forks: 10,
             ssh_timeout: 5,
+            command_timeout: 15,
             user: None,
             key: None,
             port: None,
+            groups: HashMap::new(),
             thresholds: Thresholds::default(),
+            scroll_lines: 3,
+            visible_columns: Vec::new(),
+            alert_on_transition: false,
+            bar_charts: false,
+            custom_metrics: Vec::new(),
+            jump_host: None,
+            proxy_command: None,
+            ssh_multiplexing: false,
+            ssh_backend: SshBackend::default(),
+            stagger: false,
+            max_connections_per_sec: 0,
+            retry_attempts: 1,
+            retry_delay_ms: 500,
+            primary_group: PrimaryGroupRule::default(),
+            filter_presets: HashMap::new(),
+            host_key_checking: HostKeyChecking::default(),
+            known_hosts_file: None,
+            ssh_config_file: None,
+            ignore_user_ssh_config: false,
         }
     }
 }
@@ -43,6 +183,12 @@ impl Default for Thresholds {
         Self {
             warning: 60.0,
             critical: 85.0,
+            load_warning_per_cpu: 1.5,
+            load_critical_per_cpu: 3.0,
+            temp_warning: 70.0,
+            temp_critical: 90.0,
+            entropy_warning: 512,
+            entropy_critical: 128,
         }
     }
 }
@@ -62,10 +208,120 @@ forks: 10
 # SSH connection timeout in seconds
 ssh_timeout: 5
 
+# Seconds the metrics command may run once connected (on top of
+# ssh_timeout) before the poll is killed and the host marked down, e.g. when
+# its shell hangs on a wedged NFS home directory
+command_timeout: 15
+
+# Reach every host through this jump host (ssh ProxyJump syntax, e.g.
+# "admin@bastion:2222" or "jump1,jump2"). Override per host or group with
+# the jump_host inventory var; set it to "" to connect directly.
+# jump_host: bastion.example.com
+
+# Reach every host through this ssh ProxyCommand, e.g. a SOCKS proxy opened
+# with "ssh -D 1080". Passed to ssh as one argument, spaces and all. Override
+# per group below, or per host or group with the ansible_ssh_proxy_command
+# inventory var; set it to "" to connect directly.
+# proxy_command: nc -x localhost:1080 %h %p
+
+# Reuse one SSH connection per host across polls (ControlMaster).
+# Sockets live in $XDG_RUNTIME_DIR/ansimon (or a private directory under
+# /tmp) and are closed on exit.
+ssh_multiplexing: false
+
+# How hosts are reached: "openssh" runs the ssh client for every poll;
+# "native" keeps one authenticated session per host open in-process
+# (libssh2) and runs each poll in a channel on it, so no ssh binary is
+# needed. native logs in with the password, the key file, or the agent's and
+# default keys; it doesn't read ssh_config and doesn't support jump hosts,
+# ProxyCommand or ansible_ssh_*_args. Only builds with --features native-ssh
+# have it.
+ssh_backend: openssh
+
+# Spread the start of each cycle's connections evenly over the interval
+# (at most 5 seconds) instead of opening them all at once, so a bastion's
+# MaxStartups limit isn't hit
+stagger: false
+
+# Open at most this many new SSH connections per second, however many forks
+# are free, so a bastion's MaxStartups doesn't drop connections and leave
+# hosts showing as down. Applies to refreshes too. 0 means no limit.
+max_connections_per_sec: 0
+
+# Retry a poll that failed on a network error (timeout, refused, reset,
+# unreachable) this many times before marking a host down, waiting
+# retry_delay_ms, then twice as long, and so on between attempts.
+# Authentication failures are never retried.
+retry_attempts: 1
+retry_delay_ms: 500
+
+# Host key checking: "strict" (only known hosts), "accept-new" (record new
+# hosts' keys, refuse changed ones) or "off" (no checks, keys not recorded;
+# for throwaway lab machines). Override per host with the
+# ansimon_host_key_checking inventory var.
+host_key_checking: accept-new
+
+# known_hosts file to use instead of ssh's default (uncomment to set)
+# known_hosts_file: ~/.ssh/known_hosts_ansimon
+
+# ssh reads ~/.ssh/config (and /etc/ssh/ssh_config) for every poll, so a
+# "Host *" stanza meant for interactive use applies to ansimon too. Point
+# ssh at another file with ssh_config_file (passed as -F), or at none with
+# ignore_user_ssh_config. Override per host or group with the
+# ansimon_ssh_config_file inventory var ("" for ssh's default).
+# ssh_config_file: ~/.ssh/config_ansimon
+ignore_user_ssh_config: false
+
+# Rows moved per mouse wheel notch
+scroll_lines: 3
+
+# Table columns shown at startup, by header label: St, Host, Address, Group,
+# CPU, Mem, Disk, IOw, Swap, Load, Temp, Fail, Net, TCP, Lat, Up. They keep
+# their usual order. Unset shows St, Host, Group, CPU, Mem, Disk, IOw and
+# Swap. c switches between these, all columns and a compact set at runtime,
+# and w adds Load, Net, TCP and Lat on wide terminals.
+# visible_columns: [St, Host, CPU, Mem, Load, Lat]
+
+# Ring the terminal bell when a host goes from up to down, or a metric
+# crosses its critical threshold. Alerts are listed with A either way.
+alert_on_transition: false
+
+# Show CPU and memory usage in the table as bars (████░░  45%) rather than
+# severity text (OK 45%)
+bar_charts: false
+
+# Group shown for hosts in several groups: "sorted" (first by name)
+# or "deepest" (most nested, e.g. "web" over its parent "prod")
+primary_group: sorted
+
 # Severity thresholds (percentage)
 thresholds:
   warning: 60
   critical: 85
+  # 1-minute load average, as a multiple of the CPU count
+  load_warning_per_cpu: 1.5
+  load_critical_per_cpu: 3.0
+  # Hottest thermal zone, in °C
+  temp_warning: 70
+  temp_critical: 90
+  # Available entropy in bits (lower is worse)
+  entropy_warning: 512
+  entropy_critical: 128
+
+# Filter presets: press Tab in filter mode (/) to cycle through them, or
+# pass --limit preset:NAME. As a limit, the value is an Ansible pattern.
+# filter_presets:
+#   prod-db: db
+#   web: web
+
+# Custom metrics: shell snippets run on every host, shown in the detail panel
+# custom_metrics:
+#   - name: redis
+#     command: redis-cli ping
+#     unit: ""
+#   - name: open_files
+#     command: cut -f1 /proc/sys/fs/file-nr
+#     unit: files
 
 # Default SSH user (uncomment to set)
 # user: root
@@ -75,6 +331,17 @@ thresholds:
 
 # Default SSH private key path (uncomment to set)
 # key: ~/.ssh/id_rsa
+
+# SSH user, port, key and proxy_command for the hosts of an inventory
+# group. Precedence: command line, then inventory vars, then these, then the
+# defaults above. A host in several groups listed here uses its primary
+# group's entry if it has one, else the entry of its first listed group.
+# groups:
+#   legacy:
+#     user: root
+#     port: 2222
+#     key: ~/.ssh/legacy
+#     proxy_command: nc -x localhost:1080 %h %p
 "#;
 
 impl Config {
@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::cli::{DumpFormat, ResolvedArgs};
+use crate::inventory;
+use crate::inventory::types::Host;
+
+/// A host as ansimon will connect to it: inventory vars resolved and CLI
+/// overrides applied.
+#[derive(Serialize)]
+struct HostDump<'a> {
+    name: &'a str,
+    address: &'a str,
+    port: u16,
+    user: Option<&'a str>,
+    key: Option<&'a str>,
+    connection: &'a str,
+    groups: &'a [String],
+    primary_group: &'a str,
+    vars: BTreeMap<&'a str, &'a str>,
+}
+
+impl<'a> HostDump<'a> {
+    fn new(host: &'a Host, args: &'a ResolvedArgs) -> Self {
+        Self {
+            name: &host.name,
+            address: host.effective_host(),
+            port: args.port_for(host),
+            user: args.user_for(host),
+            key: args
+                .key
+                .as_deref()
+                .or(host.ansible_ssh_private_key_file.as_deref())
+                .or_else(|| args.config_key_for(host)),
+            connection: if host.is_local() {
+                "local"
+            } else if host.collects_locally() {
+                "command"
+            } else {
+                "ssh"
+            },
+            groups: &host.groups,
+            primary_group: host.display_group(),
+            vars: host
+                .vars
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect(),
+        }
+    }
+}
+
+/// Print the selected hosts for `ansimon inventory`, sorted by name so the
+/// output is stable enough to diff.
+pub fn print_hosts(hosts: &[Host], args: &ResolvedArgs, format: DumpFormat) -> Result<()> {
+    let mut dumps: Vec<HostDump> = hosts.iter().map(|h| HostDump::new(h, args)).collect();
+    dumps.sort_by(|a, b| a.name.cmp(b.name));
+
+    match format {
+        DumpFormat::Json => println!("{}", serde_json::to_string_pretty(&dumps)?),
+        DumpFormat::Yaml => print!("{}", serde_yaml::to_string(&dumps)?),
+        DumpFormat::Table => print_table(&dumps),
+    }
+    Ok(())
+}
+
+/// `ansimon inventory check`: print group sizes and any problems found in
+/// the inventory. Returns whether it is free of problems.
+pub fn check_inventory(args: &ResolvedArgs) -> Result<bool> {
+    let inv = inventory::load_inventories(&args.inventory)?;
+    let hosts = inventory::select_hosts(&inv, &args.inventory, args.limit.as_deref(), &args.groups)?;
+    let selected: Vec<&str> = hosts.iter().map(|h| h.name.as_str()).collect();
+
+    let mut groups: Vec<(&str, usize)> = inv
+        .groups
+        .keys()
+        .map(|name| {
+            let mut members = inv.hosts_in_group(name);
+            members.sort();
+            members.dedup();
+            let count = members.iter().filter(|h| selected.contains(&h.as_str())).count();
+            (name.as_str(), count)
+        })
+        .collect();
+    groups.sort();
+    let width = groups.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+
+    println!("Inventory: {}", args.inventory);
+    println!("Hosts: {}", hosts.len());
+    println!("Groups:");
+    for (name, count) in &groups {
+        println!("  {name:<width$}  {count} host(s)");
+    }
+
+    let mut problems = inventory::structure_warnings(&inv);
+    if let Some(limit) = args.limit.as_deref() {
+        problems.extend(inventory::limit::pattern_warnings(limit));
+    }
+    problems.extend(
+        inv.undefined_children()
+            .into_iter()
+            .map(|(parent, child)| format!("Group {parent}: child group {child} is undefined or empty")),
+    );
+
+    let by_name: Vec<&str> = hosts
+        .iter()
+        .filter(|h| h.ansible_host.is_none() && h.uses_ssh())
+        .map(|h| h.name.as_str())
+        .collect();
+    if !by_name.is_empty() {
+        println!(
+            "No ansible_host, connecting by name ({}): {}",
+            by_name.len(),
+            by_name.join(", ")
+        );
+    }
+
+    if problems.is_empty() {
+        println!("OK");
+    } else {
+        println!("Problems ({}):", problems.len());
+        for problem in &problems {
+            println!("  {problem}");
+        }
+    }
+    Ok(problems.is_empty())
+}
+
+fn print_table(dumps: &[HostDump]) {
+    let name_width = dumps.iter().map(|d| d.name.len()).max().unwrap_or(0).max(4);
+    let addr_width = dumps.iter().map(|d| d.address.len()).max().unwrap_or(0).max(7);
+
+    println!(
+        "{:<name_width$}  {:<addr_width$}  {:>5}  {:<12}  GROUPS",
+        "HOST", "ADDRESS", "PORT", "USER"
+    );
+    for d in dumps {
+        println!(
+            "{:<name_width$}  {:<addr_width$}  {:>5}  {:<12}  {}",
+            d.name,
+            d.address,
+            d.port,
+            d.user.unwrap_or("-"),
+            d.groups.join(",")
+        );
+        if let Some(key) = d.key {
+            println!("    key: {key}");
+        }
+        if d.connection != "ssh" {
+            println!("    connection: {}", d.connection);
+        }
+        for (k, v) in &d.vars {
+            println!("    {k}={v}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli_overrides_and_sorted_vars() {
+        let mut host = Host::new("web01");
+        host.apply_host_var("ansible_user", "deploy");
+        host.apply_host_var("zone", "b");
+        host.apply_host_var("rack", "a");
+        host.apply_host_var("env", "prod");
+        let args = ResolvedArgs {
+            port: Some(2222),
+            ..Default::default()
+        };
+
+        let dump = HostDump::new(&host, &args);
+        let json = serde_json::to_value(&dump).unwrap();
+        assert_eq!(json["address"], "web01");
+        assert_eq!(json["port"], 2222);
+        assert_eq!(json["user"], "deploy");
+
+        // serde_json::Value sorts keys itself, so check the dump and its text
+        let keys: Vec<&str> = dump.vars.keys().copied().collect();
+        assert_eq!(keys, ["env", "rack", "zone"]);
+        let text = serde_json::to_string(&dump).unwrap();
+        let position = |key: &str| text.find(&format!("\"{key}\"")).unwrap();
+        assert!(position("env") < position("rack"));
+        assert!(position("rack") < position("zone"));
+    }
+}
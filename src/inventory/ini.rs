@@ -1,6 +1,7 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
 use std::collections::HashMap;
 
+use super::range;
 use super::types::{Group, Host, Inventory};
 
 #[derive(Debug, PartialEq)]
@@ -23,14 +24,20 @@ pub fn parse_ini(content: &str) -> Result<Inventory> {
         .groups
         .insert("ungrouped".to_string(), Group::new("ungrouped"));
 
-    for line in content.lines() {
+    for (idx, line) in content.lines().enumerate() {
         let line = line.trim();
+        let line_no = Some(idx + 1);
 
         // Skip empty lines and comments
         if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
             continue;
         }
 
+        if line.starts_with('[') && !line.ends_with(']') {
+            inventory.warn(line_no, format!("Malformed section header: {line}"));
+            continue;
+        }
+
         // Section header
         if line.starts_with('[') && line.ends_with(']') {
             let header = &line[1..line.len() - 1];
@@ -57,60 +64,92 @@ pub fn parse_ini(content: &str) -> Result<Inventory> {
                     _ => "ungrouped".to_string(),
                 };
 
-                let (host_name, vars) = parse_host_line(line)
-                    .with_context(|| format!("Failed to parse host line: {line}"))?;
-
-                let host = inventory
-                    .hosts
-                    .entry(host_name.clone())
-                    .or_insert_with(|| Host::new(&host_name));
+                let (host_name, vars) = match parse_host_line(line) {
+                    Ok(parsed) => parsed,
+                    Err(e) => {
+                        inventory.warn(line_no, format!("Failed to parse host line: {e}"));
+                        continue;
+                    }
+                };
 
+                let mut bad_vars = Vec::new();
                 for (k, v) in &vars {
-                    host.apply_host_var(k, v);
+                    if let Err(e) = Host::check_var(k, v) {
+                        bad_vars.push(format!("{host_name}: {e}"));
+                    }
                 }
-
-                if !host.groups.contains(&group_name) {
-                    host.groups.push(group_name.clone());
+                for message in bad_vars {
+                    inventory.warn(line_no, message);
                 }
 
-                if let Some(group) = inventory.groups.get_mut(&group_name) {
-                    if !group.hosts.contains(&host_name) {
-                        group.hosts.push(host_name.clone());
+                let host_names = match range::expand_hosts(&host_name) {
+                    Ok(names) => names,
+                    Err(e) => {
+                        inventory.warn(line_no, e.to_string());
+                        continue;
                     }
-                }
+                };
+
+                for host_name in host_names {
+                    let host = inventory
+                        .hosts
+                        .entry(host_name.clone())
+                        .or_insert_with(|| Host::new(&host_name));
 
-                // Also add to "all"
-                if group_name != "all" {
-                    if let Some(all) = inventory.groups.get_mut("all") {
-                        if !all.hosts.contains(&host_name) {
-                            all.hosts.push(host_name.clone());
+                    for (k, v) in &vars {
+                        if Host::check_var(k, v).is_ok() {
+                            host.apply_host_var(k, v);
                         }
                     }
-                }
-            }
-            Section::GroupVars(group_name) => {
-                if let Some((key, value)) = line.split_once('=') {
-                    let key = key.trim();
-                    let value = value.trim();
 
-                    if let Some(group) = inventory.groups.get_mut(group_name) {
-                        group.vars.insert(key.to_string(), value.to_string());
+                    if !host.groups.contains(&group_name) {
+                        host.groups.push(group_name.clone());
                     }
 
-                    // Apply group vars to existing hosts in this group
-                    let host_names: Vec<String> = inventory
-                        .groups
-                        .get(group_name)
-                        .map(|g| g.hosts.clone())
-                        .unwrap_or_default();
+                    let mut duplicate = false;
+                    if let Some(group) = inventory.groups.get_mut(&group_name) {
+                        if group.hosts.contains(&host_name) {
+                            duplicate = true;
+                        } else {
+                            group.hosts.push(host_name.clone());
+                        }
+                    }
+                    if duplicate {
+                        inventory.warn(
+                            line_no,
+                            format!("Duplicate host {host_name} in [{group_name}]"),
+                        );
+                    }
 
-                    for host_name in host_names {
-                        if let Some(host) = inventory.hosts.get_mut(&host_name) {
-                            host.apply_group_var(key, value);
+                    // Also add to "all"
+                    if group_name != "all" {
+                        if let Some(all) = inventory.groups.get_mut("all") {
+                            if !all.hosts.contains(&host_name) {
+                                all.hosts.push(host_name.clone());
+                            }
                         }
                     }
                 }
             }
+            Section::GroupVars(group_name) => {
+                let Some((key, value)) = line.split_once('=') else {
+                    inventory.warn(
+                        line_no,
+                        format!("Expected key=value in [{group_name}:vars]: {line}"),
+                    );
+                    continue;
+                };
+                let key = key.trim();
+                let value = value.trim();
+
+                if let Err(e) = Host::check_var(key, value) {
+                    inventory.warn(line_no, format!("{group_name}: {e}"));
+                    continue;
+                }
+                if let Some(group) = inventory.groups.get_mut(group_name) {
+                    group.vars.insert(key.to_string(), value.to_string());
+                }
+            }
             Section::GroupChildren(group_name) => {
                 let child_name = line.trim().to_string();
                 if let Some(group) = inventory.groups.get_mut(group_name) {
@@ -128,6 +167,7 @@ pub fn parse_ini(content: &str) -> Result<Inventory> {
         }
     }
 
+    inventory.resolve_vars();
     Ok(inventory)
 }
 
@@ -143,7 +183,7 @@ fn parse_section_header(header: &str) -> Section {
 
 fn parse_host_line(line: &str) -> Result<(String, HashMap<String, String>)> {
     let mut vars = HashMap::new();
-    let parts: Vec<&str> = line.split_whitespace().collect();
+    let parts = split_host_line(line)?;
 
     if parts.is_empty() {
         anyhow::bail!("Empty host line");
@@ -160,9 +200,52 @@ fn parse_host_line(line: &str) -> Result<(String, HashMap<String, String>)> {
     Ok((host_name, vars))
 }
 
+/// Split a host line into words the way Ansible does (shlex with comments):
+/// quoted text stays in one word with the quotes removed, and a `#` that
+/// starts a word outside quotes begins a comment running to end of line.
+fn split_host_line(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '#' if !in_word => break,
+            '\'' | '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some(ch) if ch == c => break,
+                        Some(ch) => current.push(ch),
+                        None => anyhow::bail!("Unterminated quote"),
+                    }
+                }
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                current.push(c);
+            }
+        }
+    }
+
+    if in_word {
+        words.push(current);
+    }
+
+    Ok(words)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::inventory::types::LocalFormat;
 
     #[test]
     fn test_basic_ini() {
@@ -191,6 +274,25 @@ ansible_user=deploy
         );
     }
 
+    #[test]
+    fn test_ipv6_addresses() {
+        let content = r#"
+[web]
+web01 ansible_host=2001:db8::10
+web02 ansible_host=[2001:0db8:0000:0000:0000:0000:0000:0020] ansible_port=2222
+2001:db8::30 ansible_user=deploy
+"#;
+        let inv = parse_ini(content).unwrap();
+        assert!(inv.warnings.is_empty());
+        assert_eq!(inv.hosts["web01"].ansible_host.as_deref(), Some("2001:db8::10"));
+        assert_eq!(inv.hosts["web02"].ssh_address(), "2001:0db8:0000:0000:0000:0000:0000:0020");
+        assert_eq!(inv.hosts["web02"].display_address(), "2001:db8::20");
+        assert_eq!(inv.hosts["web02"].ansible_port, Some(2222));
+        let bare = &inv.hosts["2001:db8::30"];
+        assert_eq!(bare.effective_host(), "2001:db8::30");
+        assert_eq!(bare.ansible_user.as_deref(), Some("deploy"));
+    }
+
     #[test]
     fn test_children() {
         let content = r#"
@@ -209,4 +311,284 @@ db
         assert_eq!(prod.children.len(), 2);
         assert!(prod.children.contains(&"web".to_string()));
     }
+
+    #[test]
+    fn test_children_cycle_terminates() {
+        let content = r#"
+[a]
+host-a
+
+[b]
+host-b
+
+[a:children]
+b
+
+[b:children]
+a
+"#;
+        let inv = parse_ini(content).unwrap();
+        let mut hosts = inv.hosts_in_group("a");
+        hosts.sort();
+        assert_eq!(hosts, vec!["host-a", "host-b"]);
+        assert_eq!(inv.group_cycles(), vec![vec!["a", "b", "a"]]);
+    }
+
+    #[test]
+    fn test_child_group_vars_override_parent() {
+        // Parent vars come after the child's in the file but must still lose
+        let content = r#"
+[web]
+web01
+web02 ansible_user=root
+
+[web:vars]
+ansible_user=deploy
+
+[prod:children]
+web
+
+[prod:vars]
+ansible_user=admin
+region=eu
+"#;
+        let inv = parse_ini(content).unwrap();
+        assert_eq!(inv.hosts["web01"].ansible_user.as_deref(), Some("deploy"));
+        assert_eq!(inv.hosts["web01"].vars.get("region").map(|s| s.as_str()), Some("eu"));
+        // Host vars beat every group
+        assert_eq!(inv.hosts["web02"].ansible_user.as_deref(), Some("root"));
+    }
+
+    #[test]
+    fn test_sibling_groups_ordered_by_name() {
+        // Same depth: like Ansible, the group sorting last by name wins
+        let content = r#"
+[beta]
+app01
+
+[alpha]
+app01
+
+[beta:vars]
+ansible_port=2201
+
+[alpha:vars]
+ansible_port=2202
+"#;
+        let inv = parse_ini(content).unwrap();
+        assert_eq!(inv.hosts["app01"].ansible_port, Some(2201));
+    }
+
+    #[test]
+    fn test_all_vars_have_lowest_precedence() {
+        let content = r#"
+[all:vars]
+ansible_user=nobody
+ansible_port=2022
+
+[web]
+web01
+
+[web:vars]
+ansible_user=deploy
+"#;
+        let inv = parse_ini(content).unwrap();
+        assert_eq!(inv.hosts["web01"].ansible_user.as_deref(), Some("deploy"));
+        assert_eq!(inv.hosts["web01"].ansible_port, Some(2022));
+    }
+
+    #[test]
+    fn test_legacy_ssh_aliases() {
+        let content = r#"
+[web]
+web01 ansible_ssh_host=10.0.0.1 ansible_ssh_port=2222
+web02
+
+[web:vars]
+ansible_ssh_host=10.0.0.99
+ansible_port=2200
+"#;
+        let inv = parse_ini(content).unwrap();
+        assert_eq!(inv.hosts["web01"].effective_host(), "10.0.0.1");
+        // Host-level alias beats the group's canonical name
+        assert_eq!(inv.hosts["web01"].ansible_port, Some(2222));
+        assert_eq!(inv.hosts["web02"].effective_host(), "10.0.0.99");
+        assert_eq!(inv.hosts["web02"].ansible_port, Some(2200));
+    }
+
+    #[test]
+    fn test_quoted_values_and_inline_comments() {
+        let content = r#"
+[web]
+web01 ansible_host=10.0.0.1  # primary
+web02 ansible_ssh_common_args="-o ProxyCommand=ssh -W %h:%p jump" role='front end'
+web03 note="rack #4" ansible_port=2222
+"#;
+        let inv = parse_ini(content).unwrap();
+        let web01 = &inv.hosts["web01"];
+        assert_eq!(web01.ansible_host.as_deref(), Some("10.0.0.1"));
+        assert!(web01.vars.is_empty());
+
+        let web02 = &inv.hosts["web02"];
+        assert_eq!(
+            web02.vars.get("ansible_ssh_common_args").map(|s| s.as_str()),
+            Some("-o ProxyCommand=ssh -W %h:%p jump")
+        );
+        assert_eq!(web02.vars.get("role").map(|s| s.as_str()), Some("front end"));
+
+        // A `#` inside quotes is part of the value, not a comment
+        let web03 = &inv.hosts["web03"];
+        assert_eq!(web03.vars.get("note").map(|s| s.as_str()), Some("rack #4"));
+        assert_eq!(web03.ansible_port, Some(2222));
+    }
+
+    #[test]
+    fn test_primary_group_rules() {
+        use crate::config::PrimaryGroupRule;
+
+        let content = r#"
+[app]
+web01
+
+[web]
+web01
+
+[prod:children]
+web
+"#;
+        let mut inv = parse_ini(content).unwrap();
+        inv.assign_primary_groups(PrimaryGroupRule::Sorted);
+        assert_eq!(inv.hosts["web01"].display_group(), "app");
+        assert_eq!(inv.hosts["web01"].other_group_count(), 1);
+
+        // web sits under prod, so it's deeper than app
+        inv.assign_primary_groups(PrimaryGroupRule::Deepest);
+        assert_eq!(inv.hosts["web01"].display_group(), "web");
+
+        // Equal depth falls back to name order
+        let mut inv = parse_ini("[b]\nh1\n[a]\nh1\n").unwrap();
+        inv.assign_primary_groups(PrimaryGroupRule::Deepest);
+        assert_eq!(inv.hosts["h1"].display_group(), "a");
+    }
+
+    #[test]
+    fn test_bad_lines_become_warnings() {
+        let content = r#"
+[web
+web01 ansible_port=abc
+web02 ansible_host=10.0.0.2
+
+[db]
+db01 ansible_host=10.0.0.10
+"#;
+        let inv = parse_ini(content).unwrap();
+        assert!(inv.hosts.contains_key("web02"));
+        assert!(inv.hosts.contains_key("db01"));
+        // The bad port is dropped, the host itself is kept
+        assert_eq!(inv.hosts["web01"].ansible_port, None);
+        assert_eq!(inv.warnings.len(), 2);
+        assert_eq!(inv.warnings[0].line, Some(2));
+        assert!(inv.warnings[1].to_string().starts_with("line 3: web01: Invalid ansible_port"));
+    }
+
+    #[test]
+    fn test_local_collector_vars() {
+        let content = r#"
+[edge]
+edge01 ansible_host=10.8.0.5 ansimon_collector=local ansimon_local_command="curl -s {host}:9100"
+edge02 ansimon_collector=push
+
+[edge:vars]
+ansimon_local_format=prometheus
+"#;
+        let inv = parse_ini(content).unwrap();
+        let edge01 = &inv.hosts["edge01"];
+        assert!(edge01.collects_locally());
+        assert!(!edge01.uses_ssh());
+        assert_eq!(
+            edge01.local_command().as_deref(),
+            Some("curl -s 10.8.0.5:9100")
+        );
+        assert_eq!(edge01.local_format(), LocalFormat::Prometheus);
+        assert!(!inv.hosts["edge02"].collects_locally());
+        assert!(inv.warnings[0].to_string().contains("Invalid ansimon_collector: push"));
+    }
+
+    #[test]
+    fn test_vars_inherited_through_all_and_children() {
+        let content = r#"
+[all:vars]
+ansible_user=ops
+
+[prod:vars]
+ansible_port=2222
+
+[prod:children]
+web
+
+[web]
+web01
+"#;
+        let inv = parse_ini(content).unwrap();
+        let web01 = &inv.hosts["web01"];
+        assert_eq!(web01.ansible_user.as_deref(), Some("ops"));
+        assert_eq!(web01.ansible_port, Some(2222));
+    }
+
+    #[test]
+    fn test_host_ranges() {
+        let content = r#"
+[web]
+web[01:03].example.com ansible_user=deploy
+db-[c:a]
+"#;
+        let inv = parse_ini(content).unwrap();
+        assert_eq!(inv.groups["web"].hosts.len(), 3);
+        assert_eq!(
+            inv.hosts["web02.example.com"].ansible_user.as_deref(),
+            Some("deploy")
+        );
+        assert_eq!(inv.warnings.len(), 1);
+        assert_eq!(inv.warnings[0].line, Some(4));
+    }
+
+    #[test]
+    fn test_duplicates_and_undefined_children() {
+        let content = r#"
+[web]
+web01
+web02
+web01
+
+[prod:children]
+web
+staging
+"#;
+        let inv = parse_ini(content).unwrap();
+        assert_eq!(inv.warnings.len(), 1);
+        assert_eq!(inv.warnings[0].line, Some(5));
+        assert_eq!(
+            inv.undefined_children(),
+            vec![("prod".to_string(), "staging".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_host_range_width_and_step() {
+        let content = r#"
+[web]
+web[01:10]
+
+[even]
+node[0:10:2]
+"#;
+        let inv = parse_ini(content).unwrap();
+        assert_eq!(inv.groups["web"].hosts.first().map(|h| h.as_str()), Some("web01"));
+        assert_eq!(inv.groups["web"].hosts.last().map(|h| h.as_str()), Some("web10"));
+        assert_eq!(inv.groups["web"].hosts.len(), 10);
+        assert_eq!(
+            inv.groups["even"].hosts,
+            vec!["node0", "node2", "node4", "node6", "node8", "node10"]
+        );
+    }
 }
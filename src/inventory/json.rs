@@ -0,0 +1,162 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use super::types::{Group, Host, Inventory};
+
+/// Parse an inventory in the JSON format of `ansible-inventory --list`, which
+/// is also what dynamic inventory scripts emit for `--list`.
+///
+/// Each top-level key is a group, either as a plain list of hosts or as an
+/// object with `hosts`, `children` and `vars`. Per-host variables live under
+/// `_meta.hostvars`.
+pub fn parse_json(content: &str) -> Result<Inventory> {
+    let root: Value = serde_json::from_str(content).context("Failed to parse inventory JSON")?;
+    let root_map = root
+        .as_object()
+        .context("Inventory JSON must be an object at the top level")?;
+
+    let mut inventory = Inventory::new();
+    inventory
+        .groups
+        .insert("all".to_string(), Group::new("all"));
+    inventory
+        .groups
+        .insert("ungrouped".to_string(), Group::new("ungrouped"));
+
+    // 1. Groups, their direct hosts and children
+    for (group_name, group_value) in root_map {
+        if group_name == "_meta" {
+            continue;
+        }
+        ensure_group(&mut inventory, group_name);
+
+        let (hosts, children) = match group_value {
+            Value::Array(hosts) => (Some(hosts), None),
+            Value::Object(map) => (
+                map.get("hosts").and_then(|v| v.as_array()),
+                map.get("children").and_then(|v| v.as_array()),
+            ),
+            _ => (None, None),
+        };
+
+        for host_name in hosts.into_iter().flatten().filter_map(|v| v.as_str()) {
+            add_host(&mut inventory, group_name, host_name);
+        }
+
+        for child_name in children.into_iter().flatten().filter_map(|v| v.as_str()) {
+            ensure_group(&mut inventory, child_name);
+            if let Some(group) = inventory.groups.get_mut(group_name) {
+                if !group.children.contains(&child_name.to_string()) {
+                    group.children.push(child_name.to_string());
+                }
+            }
+        }
+    }
+
+    // 2. Host vars from _meta.hostvars — recorded as host-level so group vars
+    //    can never overwrite them. Hosts only listed here land in "ungrouped".
+    if let Some(hostvars) = root_map
+        .get("_meta")
+        .and_then(|m| m.get("hostvars"))
+        .and_then(|h| h.as_object())
+    {
+        for (host_name, vars) in hostvars {
+            if !inventory.hosts.contains_key(host_name) {
+                add_host(&mut inventory, "ungrouped", host_name);
+            }
+            if let (Some(host), Some(vars)) = (inventory.hosts.get_mut(host_name), vars.as_object()) {
+                for (k, v) in vars {
+                    host.apply_host_var(k, &value_to_string(v));
+                }
+            }
+        }
+    }
+
+    // 3. Group vars, applied to hosts by precedence
+    for (group_name, group_value) in root_map {
+        let Some(vars) = group_value.get("vars").and_then(|v| v.as_object()) else {
+            continue;
+        };
+        if let Some(group) = inventory.groups.get_mut(group_name) {
+            for (k, v) in vars {
+                group.vars.insert(k.clone(), value_to_string(v));
+            }
+        }
+    }
+
+    inventory.resolve_vars();
+    Ok(inventory)
+}
+
+fn ensure_group(inventory: &mut Inventory, name: &str) {
+    if !inventory.groups.contains_key(name) {
+        inventory.groups.insert(name.to_string(), Group::new(name));
+    }
+}
+
+fn add_host(inventory: &mut Inventory, group_name: &str, host_name: &str) {
+    let host = inventory
+        .hosts
+        .entry(host_name.to_string())
+        .or_insert_with(|| Host::new(host_name));
+
+    if !host.groups.contains(&group_name.to_string()) {
+        host.groups.push(group_name.to_string());
+    }
+
+    if let Some(group) = inventory.groups.get_mut(group_name) {
+        if !group.hosts.contains(&host_name.to_string()) {
+            group.hosts.push(host_name.to_string());
+        }
+    }
+
+    if group_name != "all" {
+        if let Some(all) = inventory.groups.get_mut("all") {
+            if !all.hosts.contains(&host_name.to_string()) {
+                all.hosts.push(host_name.to_string());
+            }
+        }
+    }
+}
+
+fn value_to_string(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_inventory() {
+        let content = r#"{
+            "_meta": {
+                "hostvars": {
+                    "web01": { "ansible_host": "10.0.0.1" },
+                    "db01": { "ansible_host": "10.0.0.10", "ansible_port": 2222 }
+                }
+            },
+            "web": { "hosts": ["web01"], "vars": { "ansible_user": "deploy" } },
+            "db": ["db01"],
+            "prod": { "children": ["web", "db"], "vars": { "region": "eu" } }
+        }"#;
+        let inv = parse_json(content).unwrap();
+        assert_eq!(inv.hosts.len(), 2);
+        assert_eq!(inv.hosts["web01"].ansible_host.as_deref(), Some("10.0.0.1"));
+        assert_eq!(inv.hosts["web01"].ansible_user.as_deref(), Some("deploy"));
+        assert_eq!(inv.hosts["db01"].ansible_port, Some(2222));
+        assert_eq!(inv.hosts["db01"].vars.get("region").map(|s| s.as_str()), Some("eu"));
+        assert!(inv.groups["prod"].children.contains(&"web".to_string()));
+    }
+
+    #[test]
+    fn test_hostvars_only_host_is_ungrouped() {
+        let content = r#"{ "_meta": { "hostvars": { "lonely": {} } } }"#;
+        let inv = parse_json(content).unwrap();
+        assert!(inv.groups["ungrouped"].hosts.contains(&"lonely".to_string()));
+        assert!(inv.groups["all"].hosts.contains(&"lonely".to_string()));
+    }
+}
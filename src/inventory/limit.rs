@@ -1,35 +1,56 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+
 use super::types::Inventory;
 
 /// Apply Ansible-style --limit pattern to filter hosts.
 ///
 /// Supported patterns:
 /// - `hostname` - exact match
-/// - `web*` - glob pattern
+/// - `web*` - glob pattern over group names and host names
 /// - `groupname` - all hosts in group (checked first)
 /// - `host1,host2` - union (comma-separated)
 /// - `!pattern` - exclude hosts matching pattern
 /// - `&pattern` - intersection (only hosts also matching pattern)
-pub fn apply_limit(inventory: &Inventory, limit: &str) -> Vec<String> {
+/// - `~regex` - regular expression matched against host and group names
+/// - `@file` - read patterns from a file, one per line (e.g. a retry file)
+/// - `group[i]`, `group[i:j]` - positional subset of a group (inclusive,
+///   negative indices count from the end)
+///
+/// A pattern made only of `!` and `&` parts starts from every host, so
+/// `!databases` selects everything outside that group.
+pub fn apply_limit(inventory: &Inventory, limit: &str) -> Result<Vec<String>> {
     let parts: Vec<&str> = limit.split(',').map(|s| s.trim()).collect();
 
     let mut included: Vec<String> = Vec::new();
     let mut excluded: Vec<String> = Vec::new();
     let mut intersections: Vec<Vec<String>> = Vec::new();
+    let mut has_positive = false;
 
     for part in parts {
         if part.is_empty() {
             continue;
         }
 
-        if let Some(pattern) = part.strip_prefix('!') {
-            excluded.extend(resolve_pattern(inventory, pattern));
+        if let Some(path) = part.strip_prefix('@') {
+            has_positive = true;
+            for pattern in read_limit_file(path)? {
+                included.extend(resolve_pattern(inventory, &pattern)?);
+            }
+        } else if let Some(pattern) = part.strip_prefix('!') {
+            excluded.extend(resolve_pattern(inventory, pattern)?);
         } else if let Some(pattern) = part.strip_prefix('&') {
-            intersections.push(resolve_pattern(inventory, pattern));
+            intersections.push(resolve_pattern(inventory, pattern)?);
         } else {
-            included.extend(resolve_pattern(inventory, part));
+            has_positive = true;
+            included.extend(resolve_pattern(inventory, part)?);
         }
     }
 
+    if !has_positive && (!excluded.is_empty() || !intersections.is_empty()) {
+        included = inventory.hosts.keys().cloned().collect();
+    }
+
     // Remove duplicates from included
     included.sort();
     included.dedup();
@@ -42,35 +63,201 @@ pub fn apply_limit(inventory: &Inventory, limit: &str) -> Vec<String> {
         included.retain(|h| intersection.contains(h));
     }
 
-    included
+    Ok(included)
+}
+
+/// Check that every `--group` name exists in the inventory and turn them
+/// into a limit pattern selecting their union.
+pub fn group_pattern(inventory: &Inventory, groups: &[String]) -> Result<String> {
+    for group in groups {
+        if inventory.groups.contains_key(group) {
+            continue;
+        }
+        match closest_group(inventory, group) {
+            Some(suggestion) => {
+                anyhow::bail!("Unknown group: {group} (did you mean {suggestion}?)")
+            }
+            None => anyhow::bail!("Unknown group: {group}"),
+        }
+    }
+
+    Ok(groups.join(","))
 }
 
-fn resolve_pattern(inventory: &Inventory, pattern: &str) -> Vec<String> {
+/// The known group name nearest to `name`, if any is close enough to be a
+/// plausible typo.
+fn closest_group<'a>(inventory: &'a Inventory, name: &str) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(2);
+    inventory
+        .groups
+        .keys()
+        .map(|g| (edit_distance(name, g), g))
+        .filter(|(d, _)| *d <= max_distance)
+        .min()
+        .map(|(_, g)| g.as_str())
+}
+
+/// Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + usize::from(ca != *cb);
+            row.push(substitute.min(prev[j + 1] + 1).min(row[j] + 1));
+        }
+        prev = row;
+    }
+    prev[b.len()]
+}
+
+/// Read a limit file: one pattern per line, skipping blanks and `#` comments
+/// (whole-line or trailing).
+fn read_limit_file(path: &str) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read limit file: {path}"))?;
+    Ok(content
+        .lines()
+        .map(|l| l.split('#').next().unwrap_or("").trim())
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_string())
+        .collect())
+}
+
+fn resolve_pattern(inventory: &Inventory, pattern: &str) -> Result<Vec<String>> {
+    if let Some(expr) = pattern.strip_prefix('~') {
+        return resolve_regex(inventory, expr);
+    }
+
+    if let Some(hosts) = resolve_subscript(inventory, pattern) {
+        return Ok(hosts);
+    }
+
     // Check if pattern is a group name first
-    if let Some(group) = inventory.groups.get(pattern) {
-        let mut hosts = group.hosts.clone();
+    if inventory.groups.contains_key(pattern) {
         // Include children recursively
-        for child in &group.children {
-            hosts.extend(inventory.hosts_in_group(child));
-        }
+        let mut hosts = inventory.hosts_in_group(pattern);
         hosts.sort();
         hosts.dedup();
-        return hosts;
+        return Ok(hosts);
     }
 
     // Check for exact host match
     if inventory.hosts.contains_key(pattern) {
-        return vec![pattern.to_string()];
+        return Ok(vec![pattern.to_string()]);
+    }
+
+    // Glob matching: hosts of every matching group, then matching host names
+    let mut hosts: Vec<String> = Vec::new();
+    for group_name in inventory
+        .groups
+        .keys()
+        .filter(|g| glob_match::glob_match(pattern, g))
+    {
+        hosts.extend(inventory.hosts_in_group(group_name));
+    }
+    hosts.extend(
+        inventory
+            .hosts
+            .keys()
+            .filter(|h| glob_match::glob_match(pattern, h))
+            .cloned(),
+    );
+    hosts.sort();
+    hosts.dedup();
+    Ok(hosts)
+}
+
+/// Resolve `group[i]` / `group[i:j]`. Returns `None` if the pattern has no
+/// subscript or doesn't name a group, so it can fall through to glob matching.
+fn resolve_subscript(inventory: &Inventory, pattern: &str) -> Option<Vec<String>> {
+    let (name, rest) = pattern.split_once('[')?;
+    let subscript = rest.strip_suffix(']')?;
+    if !inventory.groups.contains_key(name) {
+        return None;
+    }
+
+    // Group hosts in definition order, children after direct members
+    let mut hosts: Vec<String> = Vec::new();
+    for host in inventory.hosts_in_group(name) {
+        if !hosts.contains(&host) {
+            hosts.push(host);
+        }
+    }
+    let len = hosts.len() as i64;
+    let index = |s: &str, default: i64| -> Option<i64> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Some(default);
+        }
+        let i: i64 = s.parse().ok()?;
+        Some(if i < 0 { len + i } else { i })
+    };
+
+    let (start, end) = match subscript.split_once(':') {
+        Some((a, b)) => (index(a, 0)?, index(b, len - 1)?),
+        None => {
+            let i = index(subscript, 0)?;
+            (i, i)
+        }
+    };
+
+    let start = start.max(0);
+    let end = end.min(len - 1);
+    if start > end {
+        return Some(Vec::new());
+    }
+    Some(hosts[start as usize..=end as usize].to_vec())
+}
+
+/// Resolve a `~regex` pattern. Like Ansible, the expression is anchored at the
+/// start of the name and matches both group names (selecting their hosts) and
+/// host names.
+/// Warnings about parts of a limit pattern that can never match, such as a
+/// `~regex` that doesn't compile. Those parts select no hosts.
+pub fn pattern_warnings(limit: &str) -> Vec<String> {
+    let mut patterns: Vec<String> = Vec::new();
+    for part in limit.split(',').map(|s| s.trim()) {
+        match part.strip_prefix('@') {
+            Some(path) => patterns.extend(read_limit_file(path).unwrap_or_default()),
+            None => patterns.push(part.to_string()),
+        }
     }
 
-    // Glob matching
-    let all_hosts: Vec<String> = inventory.hosts.keys().cloned().collect();
-    all_hosts
-        .into_iter()
-        .filter(|h| glob_match::glob_match(pattern, h))
+    patterns
+        .iter()
+        .filter_map(|p| p.trim_start_matches(['!', '&']).strip_prefix('~'))
+        .filter_map(|expr| {
+            compile_regex(expr)
+                .err()
+                .map(|e| format!("Invalid regex in limit pattern ~{expr}, it matches nothing: {e}"))
+        })
         .collect()
 }
 
+fn compile_regex(expr: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!("^(?:{expr})"))
+}
+
+/// Hosts in groups, or with names, matching `expr`. An invalid regex matches
+/// nothing; `pattern_warnings` reports it.
+fn resolve_regex(inventory: &Inventory, expr: &str) -> Result<Vec<String>> {
+    let Ok(re) = compile_regex(expr) else {
+        return Ok(Vec::new());
+    };
+
+    let mut hosts: Vec<String> = Vec::new();
+    for group_name in inventory.groups.keys().filter(|g| re.is_match(g)) {
+        hosts.extend(inventory.hosts_in_group(group_name));
+    }
+    hosts.extend(inventory.hosts.keys().filter(|h| re.is_match(h)).cloned());
+
+    hosts.sort();
+    hosts.dedup();
+    Ok(hosts)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -96,14 +283,25 @@ cache01
     #[test]
     fn test_group_limit() {
         let inv = test_inventory();
-        let result = apply_limit(&inv, "web");
+        let result = apply_limit(&inv, "web").unwrap();
         assert_eq!(result, vec!["web01", "web02", "web03"]);
     }
 
+    #[test]
+    fn test_ipv6_host_names() {
+        let inv = parse_ini("[v6]\n2001:db8::10\n2001:db8::11\nfd00::1\n").unwrap();
+        assert_eq!(apply_limit(&inv, "2001:db8::10").unwrap(), vec!["2001:db8::10"]);
+        assert_eq!(
+            apply_limit(&inv, "2001:db8::*").unwrap(),
+            vec!["2001:db8::10", "2001:db8::11"]
+        );
+        assert_eq!(apply_limit(&inv, "v6,!fd00::1").unwrap().len(), 2);
+    }
+
     #[test]
     fn test_glob_limit() {
         let inv = test_inventory();
-        let result = apply_limit(&inv, "web*");
+        let result = apply_limit(&inv, "web*").unwrap();
         assert!(result.contains(&"web01".to_string()));
         assert!(result.contains(&"web02".to_string()));
         assert!(!result.contains(&"db01".to_string()));
@@ -112,7 +310,7 @@ cache01
     #[test]
     fn test_exclusion() {
         let inv = test_inventory();
-        let result = apply_limit(&inv, "all,!db");
+        let result = apply_limit(&inv, "all,!db").unwrap();
         assert!(result.contains(&"web01".to_string()));
         assert!(!result.contains(&"db01".to_string()));
     }
@@ -120,7 +318,127 @@ cache01
     #[test]
     fn test_exact_host() {
         let inv = test_inventory();
-        let result = apply_limit(&inv, "web01");
+        let result = apply_limit(&inv, "web01").unwrap();
         assert_eq!(result, vec!["web01"]);
     }
+
+    #[test]
+    fn test_regex_limit() {
+        let inv = test_inventory();
+        let result = apply_limit(&inv, r"~web0[12]").unwrap();
+        assert_eq!(result, vec!["web01", "web02"]);
+    }
+
+    #[test]
+    fn test_regex_with_exclusion() {
+        let inv = test_inventory();
+        let result = apply_limit(&inv, "~web.*,!web03").unwrap();
+        assert_eq!(result, vec!["web01", "web02"]);
+    }
+
+    #[test]
+    fn test_regex_matches_group_names() {
+        let inv = test_inventory();
+        let result = apply_limit(&inv, "~(db|cache)$").unwrap();
+        assert_eq!(result, vec!["cache01", "db01", "db02"]);
+    }
+
+    #[test]
+    fn test_invalid_regex_matches_nothing() {
+        let inv = test_inventory();
+        assert!(apply_limit(&inv, "~web(").unwrap().is_empty());
+        assert_eq!(apply_limit(&inv, "db01,~web(").unwrap(), vec!["db01"]);
+
+        let warnings = pattern_warnings("db01,!~web(");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("Invalid regex in limit pattern ~web("));
+        assert!(pattern_warnings("~web0[12],db01").is_empty());
+    }
+
+    #[test]
+    fn test_limit_file() {
+        let inv = test_inventory();
+        let path = std::env::temp_dir().join(format!("ansimon-limit-{}.retry", std::process::id()));
+        std::fs::write(&path, "# failed hosts\nweb01\n\nweb03  # flaky\ncache\n").unwrap();
+
+        let result = apply_limit(&inv, &format!("@{}", path.display())).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(result, vec!["cache01", "web01", "web03"]);
+    }
+
+    #[test]
+    fn test_missing_limit_file_errors() {
+        let inv = test_inventory();
+        let err = apply_limit(&inv, "@/nonexistent/ansimon.retry").unwrap_err();
+        assert!(err.to_string().contains("/nonexistent/ansimon.retry"));
+    }
+
+    #[test]
+    fn test_subscript_index() {
+        let inv = test_inventory();
+        assert_eq!(apply_limit(&inv, "web[0]").unwrap(), vec!["web01"]);
+        assert_eq!(apply_limit(&inv, "web[-1]").unwrap(), vec!["web03"]);
+        assert!(apply_limit(&inv, "web[5]").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_subscript_slice() {
+        let inv = test_inventory();
+        // Ansible slices are inclusive of the end index
+        assert_eq!(apply_limit(&inv, "web[0:1]").unwrap(), vec!["web01", "web02"]);
+        assert_eq!(apply_limit(&inv, "web[1:]").unwrap(), vec!["web02", "web03"]);
+        assert_eq!(apply_limit(&inv, "web[:-2]").unwrap(), vec!["web01", "web02"]);
+        assert_eq!(
+            apply_limit(&inv, "web[0:1],db[-1]").unwrap(),
+            vec!["db02", "web01", "web02"]
+        );
+    }
+
+    #[test]
+    fn test_group_pattern_unions_groups() {
+        let inv = test_inventory();
+        let groups = vec!["web".to_string(), "cache".to_string()];
+        let pattern = group_pattern(&inv, &groups).unwrap();
+        assert_eq!(
+            apply_limit(&inv, &pattern).unwrap(),
+            vec!["cache01", "web01", "web02", "web03"]
+        );
+    }
+
+    #[test]
+    fn test_exclusion_only_starts_from_all() {
+        let inv = test_inventory();
+        assert_eq!(apply_limit(&inv, "!web").unwrap(), vec!["cache01", "db01", "db02"]);
+        assert_eq!(apply_limit(&inv, "&db,!db01").unwrap(), vec!["db02"]);
+    }
+
+    #[test]
+    fn test_unknown_group_suggests_closest() {
+        let inv = test_inventory();
+        let err = group_pattern(&inv, &["webb".to_string()]).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown group: webb (did you mean web?)");
+
+        let err = group_pattern(&inv, &["frontend".to_string()]).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown group: frontend");
+    }
+
+    #[test]
+    fn test_glob_matches_group_names() {
+        let content = r#"
+[prod_web]
+web01
+
+[prod_db]
+db01
+
+[staging_web]
+stg-web01
+
+[prod_edge:children]
+prod_web
+"#;
+        let inv = parse_ini(content).unwrap();
+        let result = apply_limit(&inv, "prod_*").unwrap();
+        assert_eq!(result, vec!["db01", "web01"]);
+    }
 }
@@ -1,26 +1,283 @@
 pub mod ini;
+pub mod json;
 pub mod limit;
+pub mod range;
 pub mod types;
 pub mod yaml;
 
 use anyhow::{Context, Result};
+use std::io::Read;
 use std::path::Path;
-use types::Inventory;
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+use types::{Host, Inventory};
 
-/// Load an Ansible inventory file, auto-detecting format (INI vs YAML).
+use crate::config::PrimaryGroupRule;
+
+/// Load the inventory and return the hosts to monitor, selecting the
+/// `--group` names and applying an Ansible-style `--limit` pattern when
+/// given, along with any warnings about the inventory's structure. Lines or
+/// entries that fail to parse are skipped with a warning, unless `strict`
+/// is set.
+pub fn load_hosts(
+    path: &str,
+    limit: Option<&str>,
+    groups: &[String],
+    primary_group: PrimaryGroupRule,
+    strict: bool,
+) -> Result<(Vec<Host>, Vec<String>)> {
+    let mut inv = load_inventories(path)?;
+    inv.assign_primary_groups(primary_group);
+
+    if strict {
+        if let Some(first) = inv.warnings.first() {
+            anyhow::bail!(
+                "Invalid inventory {path} ({} problem(s)), first: {first}",
+                inv.warnings.len()
+            );
+        }
+    }
+
+    let mut warnings = structure_warnings(&inv);
+    warnings.extend(limit.map(limit::pattern_warnings).unwrap_or_default());
+    let hosts = select_hosts(&inv, path, limit, groups)?;
+    Ok((hosts, warnings))
+}
+
+/// Parse warnings plus problems with how groups fit together.
+pub fn structure_warnings(inv: &Inventory) -> Vec<String> {
+    let mut warnings: Vec<String> = inv.warnings.iter().map(|w| w.to_string()).collect();
+    warnings.extend(
+        inv.group_cycles()
+            .iter()
+            .map(|cycle| format!("Group children cycle: {}", cycle.join(" -> "))),
+    );
+    warnings
+}
+
+/// The hosts chosen by `--group` and `--limit`, or every host when neither
+/// is given. As with Ansible, `--limit` narrows the `--group` selection
+/// rather than adding to it. Selecting nothing is an error.
+pub fn select_hosts(
+    inv: &Inventory,
+    path: &str,
+    limit: Option<&str>,
+    groups: &[String],
+) -> Result<Vec<Host>> {
+    let mut selected = if groups.is_empty() {
+        None
+    } else {
+        Some(limit::apply_limit(inv, &limit::group_pattern(inv, groups)?)?)
+    };
+    if let Some(limit) = limit {
+        let limited = limit::apply_limit(inv, limit)?;
+        match &mut selected {
+            Some(names) => names.retain(|name| limited.contains(name)),
+            None => selected = Some(limited),
+        }
+    }
+
+    let hosts: Vec<Host> = if let Some(host_names) = selected {
+        if host_names.is_empty() {
+            match limit {
+                Some(limit) if !groups.is_empty() => anyhow::bail!(
+                    "No hosts in group(s) {} matched the limit pattern: {limit}",
+                    groups.join(",")
+                ),
+                Some(limit) => anyhow::bail!("No hosts matched the limit pattern: {limit}"),
+                None => anyhow::bail!("No hosts in group(s): {}", groups.join(",")),
+            }
+        }
+        host_names
+            .iter()
+            .filter_map(|name| inv.hosts.get(name).cloned())
+            .collect()
+    } else {
+        inv.all_hosts().into_iter().cloned().collect()
+    };
+
+    if hosts.is_empty() {
+        anyhow::bail!("No hosts found in inventory: {path}");
+    }
+
+    Ok(hosts)
+}
+
+/// Load a comma-separated list of inventory sources, like `-i a.ini,b.yml`,
+/// and merge them in order. A directory source loads every file in it, in
+/// name order, as Ansible does.
+pub fn load_inventories(spec: &str) -> Result<Inventory> {
+    let mut paths = Vec::new();
+    for source in spec.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        if Path::new(source).is_dir() {
+            paths.extend(directory_sources(source)?);
+        } else {
+            paths.push(source.to_string());
+        }
+    }
+
+    let mut merged = Inventory::new();
+    let several = paths.len() > 1;
+    for path in &paths {
+        let mut inv =
+            load_inventory(path).with_context(|| format!("Failed to load inventory from: {path}"))?;
+        if several {
+            for warning in &mut inv.warnings {
+                warning.source = Some(path.clone());
+            }
+        }
+        merged.merge(inv);
+    }
+
+    if paths.is_empty() {
+        anyhow::bail!("No inventory files found in: {spec}");
+    }
+    if several {
+        // Group vars from one file can apply to hosts defined in another
+        merged.resolve_vars();
+    }
+    Ok(merged)
+}
+
+/// Inventory files in a directory, sorted by name. Hidden files,
+/// subdirectories (such as group_vars) and Ansible's default ignored
+/// extensions (including `.ini` and `.cfg`) are skipped.
+fn directory_sources(dir: &str) -> Result<Vec<String>> {
+    const IGNORED_SUFFIXES: &[&str] = &[
+        "~", ".bak", ".orig", ".swp", ".pyc", ".pyo", ".rpm", ".md", ".txt", ".rst", ".ini",
+        ".cfg", ".retry",
+    ];
+
+    let mut paths: Vec<String> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read inventory directory: {dir}"))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            !name.starts_with('.') && !IGNORED_SUFFIXES.iter().any(|s| name.ends_with(s))
+        })
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+/// Load an Ansible inventory file, auto-detecting format (INI, YAML or JSON).
+/// Executable files are treated as dynamic inventory scripts.
 pub fn load_inventory(path: &str) -> Result<Inventory> {
+    if is_executable(path) {
+        return load_dynamic(path);
+    }
+
     let content =
         std::fs::read_to_string(path).with_context(|| format!("Failed to read inventory: {path}"))?;
 
     let content = content.trim();
 
-    if is_yaml(path, content) {
+    if is_json(path, content) {
+        json::parse_json(content).context("Failed to parse JSON inventory")
+    } else if is_yaml(path, content) {
         yaml::parse_yaml(content).context("Failed to parse YAML inventory")
     } else {
         ini::parse_ini(content).context("Failed to parse INI inventory")
     }
 }
 
+/// Longest a dynamic inventory script may run, so a hung script can't
+/// block startup or a reload forever.
+const SCRIPT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Run a dynamic inventory script with `--list` and parse its JSON output.
+fn load_dynamic(path: &str) -> Result<Inventory> {
+    let output = run_script(path, SCRIPT_TIMEOUT)?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!(
+            "Inventory script {path} exited with {}: {}",
+            output.status,
+            stderr.trim()
+        );
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    json::parse_json(&stdout).context("Failed to parse dynamic inventory output")
+}
+
+/// Run `path --list`, killing it if it runs longer than `timeout`.
+fn run_script(path: &str, timeout: Duration) -> Result<Output> {
+    let mut child = Command::new(script_path(path))
+        .arg("--list")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run inventory script: {path}"))?;
+
+    // Drain both pipes while waiting, so a chatty script can't fill one and stall
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            anyhow::bail!(
+                "Inventory script {path} timed out after {}s",
+                timeout.as_secs_f64()
+            );
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+/// Read a child's pipe to its end on a thread.
+fn drain(pipe: Option<impl Read + Send + 'static>) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+/// The path to run a script by. A bare file name would be looked up on
+/// `$PATH`, so it is made relative to the working directory instead.
+fn script_path(path: &str) -> String {
+    if path.contains('/') {
+        path.to_string()
+    } else {
+        format!("./{path}")
+    }
+}
+
+fn is_executable(path: &str) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::metadata(path)
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+fn is_json(path: &str, content: &str) -> bool {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("json") => true,
+        _ => content.starts_with('{'),
+    }
+}
+
 fn is_yaml(path: &str, content: &str) -> bool {
     let ext = Path::new(path)
         .extension()
@@ -38,3 +295,97 @@ fn is_yaml(path: &str, content: &str) -> bool {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("ansimon-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_multiple_sources_merge() {
+        let dir = temp_dir("multi");
+        let a = dir.join("a.hosts");
+        let b = dir.join("b.yml");
+        std::fs::write(&a, "[web]\nweb01 ansible_user=alice\nweb02\n\n[web:vars]\nansible_port=2222\n").unwrap();
+        std::fs::write(
+            &b,
+            "all:\n  children:\n    web:\n      hosts:\n        web01:\n          ansible_user: bob\n    db:\n      hosts:\n        db01:\n",
+        )
+        .unwrap();
+
+        let inv = load_inventories(&format!("{},{}", a.display(), b.display())).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(inv.all_hosts().len(), 3);
+        // The later file's host var wins; group vars still reach every member
+        assert_eq!(inv.hosts["web01"].ansible_user.as_deref(), Some("bob"));
+        assert_eq!(inv.hosts["web01"].ansible_port, Some(2222));
+        assert_eq!(inv.hosts["web02"].ansible_port, Some(2222));
+        assert_eq!(inv.hosts_in_group("db"), vec!["db01"]);
+    }
+
+    #[test]
+    fn test_directory_source() {
+        let dir = temp_dir("dir");
+        std::fs::write(dir.join("10-web"), "[web]\nweb01\n").unwrap();
+        std::fs::write(dir.join("20-db"), "[db]\ndb01\n").unwrap();
+        std::fs::write(dir.join("notes.md"), "[docs]\nnot-a-host\n").unwrap();
+        std::fs::write(dir.join(".hidden"), "[x]\nhidden01\n").unwrap();
+        std::fs::create_dir_all(dir.join("group_vars")).unwrap();
+
+        let inv = load_inventories(&dir.display().to_string()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<&str> = inv.all_hosts().iter().map(|h| h.name.as_str()).collect();
+        assert_eq!(names, vec!["db01", "web01"]);
+    }
+
+    #[test]
+    fn test_dynamic_script() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("dynamic");
+        let script = |name: &str, body: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            path.display().to_string()
+        };
+        let listed = script("listed.sh", r#"echo '{"web": {"hosts": ["web01"]}}'"#);
+        let hung = script("hung.sh", "exec sleep 10");
+
+        let inv = load_inventory(&listed).unwrap();
+        assert_eq!(inv.hosts_in_group("web"), vec!["web01"]);
+        let err = run_script(&hung, Duration::from_millis(200)).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(err.to_string().contains("timed out after 0.2s"), "{err}");
+
+        // A bare name runs from the working directory, not from $PATH
+        assert_eq!(script_path("inventory.py"), "./inventory.py");
+        assert_eq!(script_path("inv/aws.py"), "inv/aws.py");
+    }
+
+    #[test]
+    fn test_limit_narrows_groups() {
+        let inv = ini::parse_ini("[web]\nweb01\nweb02\nweb03\n\n[db]\ndb01\n").unwrap();
+        let names = |limit: Option<&str>, groups: &[&str]| -> Result<Vec<String>> {
+            let groups: Vec<String> = groups.iter().map(|g| g.to_string()).collect();
+            let hosts = select_hosts(&inv, "hosts", limit, &groups)?;
+            Ok(hosts.into_iter().map(|h| h.name).collect())
+        };
+
+        assert_eq!(names(Some("!web02"), &["web"]).unwrap(), vec!["web01", "web03"]);
+        assert_eq!(names(Some("web01,db01"), &["web"]).unwrap(), vec!["web01"]);
+        assert_eq!(names(Some("!web"), &[]).unwrap(), vec!["db01"]);
+        let err = names(Some("db01"), &["web"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "No hosts in group(s) web matched the limit pattern: db01"
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use anyhow::Result;
+
+/// Expand Ansible host range patterns such as `web[01:20].example.com`,
+/// `db-[a:f]` or `node[0:100:10]`. Names without a range expand to themselves.
+///
+/// Numeric ranges are zero-padded to the width of the lower bound when it has
+/// a leading zero. Letter ranges expand inclusively in ASCII order. Several
+/// ranges in one name expand to every combination.
+pub fn expand_hosts(pattern: &str) -> Result<Vec<String>> {
+    let Some(open) = pattern.find('[') else {
+        return Ok(vec![pattern.to_string()]);
+    };
+    let Some(close) = pattern[open..].find(']').map(|i| open + i) else {
+        anyhow::bail!("Unclosed host range in: {pattern}");
+    };
+
+    let head = &pattern[..open];
+    let range = &pattern[open + 1..close];
+    let tails = expand_hosts(&pattern[close + 1..])?;
+
+    let mut hosts = Vec::new();
+    for value in expand_range(range).map_err(|e| anyhow::anyhow!("{e} in: {pattern}"))? {
+        for tail in &tails {
+            hosts.push(format!("{head}{value}{tail}"));
+        }
+    }
+    Ok(hosts)
+}
+
+/// Expand the inside of one `[begin:end]` or `[begin:end:step]` range.
+fn expand_range(range: &str) -> Result<Vec<String>> {
+    let parts: Vec<&str> = range.split(':').collect();
+    let (begin, end, step) = match parts.as_slice() {
+        [begin, end] => (*begin, *end, "1"),
+        [begin, end, step] => (*begin, *end, *step),
+        _ => anyhow::bail!("Host range must be [begin:end] or [begin:end:step], got [{range}]"),
+    };
+
+    let step: usize = step
+        .parse()
+        .ok()
+        .filter(|s| *s > 0)
+        .ok_or_else(|| anyhow::anyhow!("Invalid step in host range [{range}]"))?;
+    // Like Ansible, an empty lower bound means 0
+    let begin = if begin.is_empty() { "0" } else { begin };
+
+    if let (Ok(lo), Ok(hi)) = (begin.parse::<u64>(), end.parse::<u64>()) {
+        if lo > hi {
+            anyhow::bail!("Host range [{range}] runs backwards");
+        }
+        let width = if begin.starts_with('0') { begin.len() } else { 0 };
+        return Ok((lo..=hi)
+            .step_by(step)
+            .map(|n| format!("{n:0width$}"))
+            .collect());
+    }
+
+    match (single_letter(begin), single_letter(end)) {
+        (Some(lo), Some(hi)) if lo.is_ascii_lowercase() == hi.is_ascii_lowercase() => {
+            if lo > hi {
+                anyhow::bail!("Host range [{range}] runs backwards");
+            }
+            Ok((lo..=hi).step_by(step).map(|c| c.to_string()).collect())
+        }
+        _ => anyhow::bail!("Host range [{range}] must be two numbers or two letters of the same case"),
+    }
+}
+
+fn single_letter(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphabetic() => Some(c),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_name() {
+        assert_eq!(expand_hosts("web01").unwrap(), vec!["web01"]);
+    }
+
+    #[test]
+    fn test_numeric_padding_widths() {
+        for width in 1..=4 {
+            let lo = format!("{:0width$}", 1);
+            let hi = format!("{:0width$}", 12);
+            let hosts = expand_hosts(&format!("web[{lo}:{hi}].example.com")).unwrap();
+            assert_eq!(hosts.len(), 12);
+            for (i, host) in hosts.iter().enumerate() {
+                let expected_width = if width > 1 { width } else { 0 };
+                assert_eq!(*host, format!("web{:0expected_width$}.example.com", i + 1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_steps() {
+        for step in 1..=12 {
+            let hosts = expand_hosts(&format!("n[0:100:{step}]")).unwrap();
+            assert_eq!(hosts.len(), 100 / step + 1);
+            assert_eq!(hosts[0], "n0");
+            assert_eq!(hosts[1], format!("n{step}"));
+        }
+        assert_eq!(expand_hosts("n[:20:10]").unwrap(), vec!["n0", "n10", "n20"]);
+    }
+
+    #[test]
+    fn test_letters_and_multiple_ranges() {
+        assert_eq!(expand_hosts("db-[a:c]").unwrap(), vec!["db-a", "db-b", "db-c"]);
+        assert_eq!(expand_hosts("x[a:e:2]").unwrap(), vec!["xa", "xc", "xe"]);
+        assert_eq!(
+            expand_hosts("r[1:2]-[a:b]").unwrap(),
+            vec!["r1-a", "r1-b", "r2-a", "r2-b"]
+        );
+    }
+
+    #[test]
+    fn test_malformed_ranges() {
+        for bad in ["h[5:1]", "h[a:3]", "h[a:C]", "h[1:5:0]", "h[1]", "h[1:5"] {
+            assert!(expand_hosts(bad).is_err(), "{bad} should fail");
+        }
+    }
+}
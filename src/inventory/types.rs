@@ -1,17 +1,39 @@
 use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
 
-#[derive(Debug, Clone)]
+use serde::Serialize;
+
+use crate::config::{HostKeyChecking, PrimaryGroupRule};
+
+/// Swap usage thresholds (percent) unless overridden per host with
+/// `ansimon_swap_warning` / `ansimon_swap_critical`.
+pub const DEFAULT_SWAP_WARNING: f64 = 50.0;
+pub const DEFAULT_SWAP_CRITICAL: f64 = 80.0;
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Host {
     pub name: String,
     pub ansible_host: Option<String>,
     pub ansible_port: Option<u16>,
     pub ansible_user: Option<String>,
     pub ansible_ssh_private_key_file: Option<String>,
+    pub ansible_connection: Option<String>,
+    /// Per-host SSH connect timeout in seconds, overriding the global one
+    pub ssh_timeout: Option<u64>,
+    /// SSH password from `ansible_password` / `ansible_ssh_pass`; never
+    /// serialized so inventory dumps can't leak it
+    #[serde(skip)]
+    pub ansible_password: Option<String>,
     pub groups: Vec<String>,
+    /// Group shown in the table and used for sorting, chosen by
+    /// `Inventory::assign_primary_groups`
+    pub primary_group: Option<String>,
     pub vars: HashMap<String, String>,
-    /// Keys set directly on the host definition (not inherited from groups).
-    /// These take precedence and cannot be overwritten by group vars.
-    host_level_vars: HashSet<String>,
+    /// Vars set directly on the host definition (not inherited from groups),
+    /// by canonical key. These take precedence and cannot be overwritten by
+    /// group vars.
+    #[serde(skip)]
+    host_level_vars: HashMap<String, String>,
 }
 
 impl Host {
@@ -22,9 +44,13 @@ impl Host {
             ansible_port: None,
             ansible_user: None,
             ansible_ssh_private_key_file: None,
+            ansible_connection: None,
+            ssh_timeout: None,
+            ansible_password: None,
             groups: Vec::new(),
+            primary_group: None,
             vars: HashMap::new(),
-            host_level_vars: HashSet::new(),
+            host_level_vars: HashMap::new(),
         }
     }
 
@@ -32,43 +58,285 @@ impl Host {
         self.ansible_host.as_deref().unwrap_or(&self.name)
     }
 
-    pub fn effective_port(&self) -> u16 {
-        self.ansible_port.unwrap_or(22)
+    /// The address to hand to ssh. A bracketed IPv6 address, as written in
+    /// URLs (`[2001:db8::10]`), loses its brackets: ssh wants it bare.
+    pub fn ssh_address(&self) -> &str {
+        let host = self.effective_host();
+        host.strip_prefix('[')
+            .and_then(|h| h.strip_suffix(']'))
+            .filter(|h| h.parse::<IpAddr>().is_ok())
+            .unwrap_or(host)
     }
 
-    fn set_var(&mut self, key: &str, value: &str) {
+    /// The effective host as an IP address, if it is one.
+    pub fn ip_address(&self) -> Option<IpAddr> {
+        self.ssh_address().parse().ok()
+    }
+
+    /// The address as shown in the table: IPs in canonical form (compressed
+    /// IPv6, e.g. `2001:db8::10`), names as written.
+    pub fn display_address(&self) -> String {
+        match self.ip_address() {
+            Some(ip) => ip.to_string(),
+            None => self.effective_host().to_string(),
+        }
+    }
+
+    /// The primary group if one was assigned, otherwise the first group.
+    pub fn display_group(&self) -> &str {
+        self.primary_group
+            .as_deref()
+            .or(self.groups.first().map(|g| g.as_str()))
+            .unwrap_or("")
+    }
+
+    /// Direct groups other than the displayed one, ignoring `all`/`ungrouped`.
+    pub fn other_group_count(&self) -> usize {
+        let primary = self.display_group();
+        self.groups
+            .iter()
+            .filter(|g| !is_implicit_group(g) && g.as_str() != primary)
+            .count()
+    }
+
+    /// Swap (warning, critical) thresholds in percent for this host.
+    pub fn swap_thresholds(&self) -> (f64, f64) {
+        let var = |key: &str, default: f64| {
+            self.vars
+                .get(key)
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        };
+        (
+            var("ansimon_swap_warning", DEFAULT_SWAP_WARNING),
+            var("ansimon_swap_critical", DEFAULT_SWAP_CRITICAL),
+        )
+    }
+
+    /// `ansimon_disabled=true` keeps a host in the table without polling it.
+    pub fn is_disabled(&self) -> bool {
+        self.vars
+            .get("ansimon_disabled")
+            .and_then(|v| parse_bool(v))
+            .unwrap_or(false)
+    }
+
+    /// Poll interval in seconds from `ansimon_interval`, overriding the global one.
+    pub fn poll_interval(&self) -> Option<u64> {
+        self.vars.get("ansimon_interval").and_then(|v| v.parse().ok())
+    }
+
+    /// Mount point whose usage is reported as Disk, from `ansimon_disk_path`.
+    pub fn disk_path(&self) -> &str {
+        self.vars
+            .get("ansimon_disk_path")
+            .map(|p| p.as_str())
+            .unwrap_or("/")
+    }
+
+    /// Jump host(s) from `ansimon_jump_host` / `jump_host`, passed to ssh as
+    /// `ProxyJump`. `Some("")` means the host is reached directly even when a
+    /// global jump host is configured.
+    pub fn jump_host(&self) -> Option<&str> {
+        self.vars.get("ansimon_jump_host").map(|j| j.as_str())
+    }
+
+    /// `ProxyCommand` from `ansible_ssh_proxy_command` /
+    /// `ansible_proxy_command`. `Some("")` means the host is reached directly
+    /// even when the config sets a proxy command.
+    pub fn proxy_command(&self) -> Option<&str> {
+        self.vars.get("ansible_proxy_command").map(|p| p.as_str())
+    }
+
+    /// ssh_config file from `ansimon_ssh_config_file`; `Some("")` means
+    /// ssh's own default even when the config names another file.
+    pub fn ssh_config_file(&self) -> Option<&str> {
+        self.vars.get("ansimon_ssh_config_file").map(|f| f.as_str())
+    }
+
+    /// Whether the metrics command should run under sudo (`ansible_become`).
+    pub fn wants_become(&self) -> bool {
+        self.vars
+            .get("ansible_become")
+            .and_then(|v| parse_bool(v))
+            .unwrap_or(false)
+    }
+
+    /// Host key checking from `ansimon_host_key_checking` (or Ansible's
+    /// `ansible_host_key_checking`), overriding the config setting.
+    pub fn host_key_checking(&self) -> Option<HostKeyChecking> {
+        self.vars
+            .get("ansimon_host_key_checking")
+            .and_then(|v| HostKeyChecking::parse(v))
+    }
+
+    /// Target user for become, from `ansible_become_user` (root when unset).
+    pub fn become_user(&self) -> Option<&str> {
+        self.vars.get("ansible_become_user").map(|u| u.as_str())
+    }
+
+    /// Whether this host should be polled locally instead of over SSH.
+    /// Matches Ansible: explicit `ansible_connection=local`, or a bare
+    /// `localhost` entry without an `ansible_host`.
+    pub fn is_local(&self) -> bool {
+        match self.ansible_connection.as_deref() {
+            Some(conn) => conn == "local",
+            None => self.name == "localhost" && self.ansible_host.is_none(),
+        }
+    }
+
+    /// Whether metrics come from a command run on this machine instead of
+    /// over SSH (`ansimon_collector=local`), for hosts ansimon can't reach.
+    pub fn collects_locally(&self) -> bool {
+        self.vars.get("ansimon_collector").is_some_and(|c| c == "local")
+    }
+
+    /// Whether ansimon connects to this host over SSH at all.
+    pub fn uses_ssh(&self) -> bool {
+        !self.is_local() && !self.collects_locally()
+    }
+
+    /// The command that gathers this host's metrics locally, from
+    /// `ansimon_local_command` with `{host}` replaced by the host's address.
+    pub fn local_command(&self) -> Option<String> {
+        self.vars
+            .get("ansimon_local_command")
+            .map(|cmd| cmd.replace("{host}", self.ssh_address()))
+    }
+
+    /// Output format of the local command, from `ansimon_local_format`.
+    pub fn local_format(&self) -> LocalFormat {
+        self.vars
+            .get("ansimon_local_format")
+            .and_then(|f| LocalFormat::parse(f))
+            .unwrap_or_default()
+    }
+
+    /// Map legacy `ansible_ssh_*` aliases (and ansimon's short spellings) to
+    /// one canonical name, so precedence treats both as the same var.
+    fn canonical_key(key: &str) -> &str {
         match key {
+            "ansible_ssh_host" => "ansible_host",
+            "ansible_ssh_port" => "ansible_port",
+            "ansible_ssh_user" => "ansible_user",
+            "ansible_ssh_pass" => "ansible_password",
+            "ansimon_ssh_timeout" => "ansible_ssh_connect_timeout",
+            "jump_host" => "ansimon_jump_host",
+            "ansible_ssh_proxy_command" => "ansible_proxy_command",
+            "ansible_host_key_checking" | "ansible_ssh_host_key_checking" => {
+                "ansimon_host_key_checking"
+            }
+            other => other,
+        }
+    }
+
+    fn set_var(&mut self, key: &str, value: &str) {
+        match Self::canonical_key(key) {
             "ansible_host" => self.ansible_host = Some(value.to_string()),
             "ansible_port" => {
                 if let Ok(p) = value.parse() {
                     self.ansible_port = Some(p);
                 }
             }
-            "ansible_user" | "ansible_ssh_user" => self.ansible_user = Some(value.to_string()),
+            "ansible_user" => self.ansible_user = Some(value.to_string()),
             "ansible_ssh_private_key_file" => {
                 self.ansible_ssh_private_key_file = Some(value.to_string())
             }
-            _ => {
-                self.vars.insert(key.to_string(), value.to_string());
+            "ansible_connection" => self.ansible_connection = Some(value.to_string()),
+            "ansible_password" => self.ansible_password = Some(value.to_string()),
+            "ansible_ssh_connect_timeout" => {
+                if let Ok(t) = value.parse() {
+                    self.ssh_timeout = Some(t);
+                }
+            }
+            canonical => {
+                self.vars.insert(canonical.to_string(), value.to_string());
+            }
+        }
+    }
+
+    /// Check that a var's value is usable before applying it. Unknown vars
+    /// are stored verbatim and always pass.
+    pub fn check_var(key: &str, value: &str) -> anyhow::Result<()> {
+        match Self::canonical_key(key) {
+            "ansible_port" if value.parse::<u16>().is_err() => {
+                anyhow::bail!("Invalid {key}: {value}")
+            }
+            "ansible_ssh_connect_timeout" if value.parse::<u64>().is_err() => {
+                anyhow::bail!("Invalid {key}: {value}")
+            }
+            "ansimon_swap_warning" | "ansimon_swap_critical" if value.parse::<f64>().is_err() => {
+                anyhow::bail!("Invalid {key}: {value}")
+            }
+            "ansimon_disabled" | "ansible_become" if parse_bool(value).is_none() => {
+                anyhow::bail!("Invalid {key}: {value}")
+            }
+            "ansimon_host_key_checking" if HostKeyChecking::parse(value).is_none() => {
+                anyhow::bail!("Invalid {key}: {value}")
             }
+            "ansimon_interval" if !value.parse::<u64>().is_ok_and(|i| i > 0) => {
+                anyhow::bail!("Invalid {key}: {value}")
+            }
+            "ansimon_collector" if value != "ssh" && value != "local" => {
+                anyhow::bail!("Invalid {key}: {value} (expected ssh or local)")
+            }
+            "ansimon_local_format" if LocalFormat::parse(value).is_none() => {
+                anyhow::bail!("Invalid {key}: {value} (expected ansimon or prometheus)")
+            }
+            _ => Ok(()),
         }
     }
 
     /// Apply a var from a host definition. Records it so group vars can't overwrite it.
     pub fn apply_host_var(&mut self, key: &str, value: &str) {
         self.set_var(key, value);
-        self.host_level_vars.insert(key.to_string());
+        self.host_level_vars
+            .insert(Self::canonical_key(key).to_string(), value.to_string());
     }
 
     /// Apply a var from a group. Skips if the key was set at host level.
     pub fn apply_group_var(&mut self, key: &str, value: &str) {
-        if !self.host_level_vars.contains(key) {
+        if !self.host_level_vars.contains_key(Self::canonical_key(key)) {
             self.set_var(key, value);
         }
     }
 }
 
-#[derive(Debug, Clone)]
+/// What a host's local command prints.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LocalFormat {
+    /// The sections ansimon's own metrics script prints
+    #[default]
+    Ansimon,
+    /// Prometheus text exposition, as served by node_exporter
+    Prometheus,
+}
+
+impl LocalFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "ansimon" => Some(LocalFormat::Ansimon),
+            "prometheus" | "node_exporter" => Some(LocalFormat::Prometheus),
+            _ => None,
+        }
+    }
+}
+
+/// `all` and `ungrouped` exist in every inventory and say nothing about a host.
+fn is_implicit_group(name: &str) -> bool {
+    name == "all" || name == "ungrouped"
+}
+
+/// Parse a boolean var the way Ansible does (`true`/`yes`/`on`/`1` and their opposites).
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 #[allow(dead_code)]
 pub struct Group {
     pub name: String,
@@ -88,32 +356,296 @@ impl Group {
     }
 }
 
-#[derive(Debug, Clone, Default)]
+/// A problem found while parsing that was skipped instead of failing the
+/// whole inventory.
+#[derive(Debug, Clone, Serialize)]
+pub struct InventoryWarning {
+    /// File the warning came from, when several inventories are loaded
+    pub source: Option<String>,
+    /// 1-based line number, for line-oriented formats
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl std::fmt::Display for InventoryWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(source) = &self.source {
+            write!(f, "{source}: ")?;
+        }
+        match self.line {
+            Some(line) => write!(f, "line {line}: {}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct Inventory {
     pub hosts: HashMap<String, Host>,
     pub groups: HashMap<String, Group>,
+    pub warnings: Vec<InventoryWarning>,
 }
 
 impl Inventory {
+    /// Fold a later inventory source into this one: hosts, groups and their
+    /// members are unioned, and the later source's host and group vars win
+    /// ties. Call `resolve_vars` once everything is merged.
+    pub fn merge(&mut self, other: Inventory) {
+        for (name, other_group) in other.groups {
+            let group = self
+                .groups
+                .entry(name)
+                .or_insert_with_key(|name| Group::new(name));
+            for host in other_group.hosts {
+                if !group.hosts.contains(&host) {
+                    group.hosts.push(host);
+                }
+            }
+            for child in other_group.children {
+                if !group.children.contains(&child) {
+                    group.children.push(child);
+                }
+            }
+            group.vars.extend(other_group.vars);
+        }
+
+        for (name, other_host) in other.hosts {
+            match self.hosts.get_mut(&name) {
+                Some(host) => {
+                    for group in other_host.groups {
+                        if !host.groups.contains(&group) {
+                            host.groups.push(group);
+                        }
+                    }
+                    for (k, v) in &other_host.host_level_vars {
+                        host.apply_host_var(k, v);
+                    }
+                }
+                None => {
+                    self.hosts.insert(name, other_host);
+                }
+            }
+        }
+
+        self.warnings.extend(other.warnings);
+    }
+
     pub fn new() -> Self {
         Self::default()
     }
 
+    pub fn warn(&mut self, line: Option<usize>, message: impl Into<String>) {
+        self.warnings.push(InventoryWarning {
+            source: None,
+            line,
+            message: message.into(),
+        });
+    }
+
     pub fn all_hosts(&self) -> Vec<&Host> {
         let mut hosts: Vec<&Host> = self.hosts.values().collect();
         hosts.sort_by(|a, b| a.name.cmp(&b.name));
         hosts
     }
 
+    /// Hosts in a group and, recursively, its children. Each group is visited
+    /// at most once, so a `children` cycle can't recurse forever.
     pub fn hosts_in_group(&self, group_name: &str) -> Vec<String> {
         let mut result = Vec::new();
+        let mut visited = HashSet::new();
+        self.collect_group_hosts(group_name, &mut visited, &mut result);
+        result
+    }
+
+    fn collect_group_hosts(
+        &self,
+        group_name: &str,
+        visited: &mut HashSet<String>,
+        result: &mut Vec<String>,
+    ) {
+        if !visited.insert(group_name.to_string()) {
+            return;
+        }
         if let Some(group) = self.groups.get(group_name) {
             result.extend(group.hosts.clone());
             for child in &group.children {
-                result.extend(self.hosts_in_group(child));
+                self.collect_group_hosts(child, visited, result);
             }
         }
-        result
+    }
+
+    /// Apply group vars to every host, following Ansible's precedence:
+    /// groups are ordered by depth below `all` (parents before children, ties
+    /// broken by name) so deeper groups win, and host vars always win.
+    /// Parsers only record vars on groups and call this once at the end.
+    pub fn resolve_vars(&mut self) {
+        let depths = self.group_depths();
+
+        let mut host_groups: HashMap<String, Vec<&String>> = HashMap::new();
+        for name in self.groups.keys() {
+            for host in self.hosts_in_group(name) {
+                let groups = host_groups.entry(host).or_default();
+                if !groups.contains(&name) {
+                    groups.push(name);
+                }
+            }
+        }
+
+        let mut assignments: Vec<(String, Vec<(String, String)>)> = Vec::new();
+        for (host, mut groups) in host_groups {
+            groups.sort_by_key(|g| (depths.get(*g).copied().unwrap_or(0), *g));
+            let vars = groups
+                .iter()
+                .filter_map(|g| self.groups.get(*g))
+                .flat_map(|g| {
+                    let mut vars: Vec<_> = g.vars.iter().collect();
+                    vars.sort();
+                    vars.into_iter().map(|(k, v)| (k.clone(), v.clone()))
+                })
+                .collect();
+            assignments.push((host, vars));
+        }
+
+        for (host, vars) in assignments {
+            if let Some(host) = self.hosts.get_mut(&host) {
+                for (k, v) in &vars {
+                    host.apply_group_var(k, v);
+                }
+            }
+        }
+    }
+
+    /// `(parent, child)` pairs where a `children` entry names a group with no
+    /// hosts, children or vars: usually a typo or a group defined nowhere.
+    pub fn undefined_children(&self) -> Vec<(String, String)> {
+        let mut pairs: Vec<(String, String)> = self
+            .groups
+            .values()
+            .flat_map(|parent| {
+                parent
+                    .children
+                    .iter()
+                    .filter(|child| {
+                        self.groups.get(*child).map_or(true, |g| {
+                            g.hosts.is_empty() && g.children.is_empty() && g.vars.is_empty()
+                        })
+                    })
+                    .map(|child| (parent.name.clone(), child.clone()))
+            })
+            .collect();
+        pairs.sort();
+        pairs
+    }
+
+    /// Pick each host's primary group among its direct groups, skipping
+    /// `all`/`ungrouped` unless the host has nothing else.
+    pub fn assign_primary_groups(&mut self, rule: PrimaryGroupRule) {
+        let depths = self.group_depths();
+        for host in self.hosts.values_mut() {
+            let mut candidates: Vec<&String> =
+                host.groups.iter().filter(|g| !is_implicit_group(g)).collect();
+            candidates.sort();
+            let primary = match rule {
+                PrimaryGroupRule::Sorted => candidates.first().copied(),
+                PrimaryGroupRule::Deepest => candidates
+                    .iter()
+                    .copied()
+                    .rev()
+                    .max_by_key(|g| depths.get(*g).copied().unwrap_or(0)),
+            };
+            host.primary_group = primary.or(host.groups.first()).cloned();
+        }
+    }
+
+    /// Depth of each group below `all`: `all` is 0, groups without another
+    /// parent are 1, and a child is one deeper than its deepest parent.
+    fn group_depths(&self) -> HashMap<String, usize> {
+        let mut parents: HashMap<&str, Vec<&str>> = HashMap::new();
+        for group in self.groups.values() {
+            for child in &group.children {
+                if group.name != "all" {
+                    parents.entry(child.as_str()).or_default().push(&group.name);
+                }
+            }
+        }
+
+        fn depth<'a>(
+            name: &'a str,
+            parents: &HashMap<&'a str, Vec<&'a str>>,
+            depths: &mut HashMap<String, usize>,
+            visiting: &mut HashSet<&'a str>,
+        ) -> usize {
+            if name == "all" {
+                return 0;
+            }
+            if let Some(d) = depths.get(name) {
+                return *d;
+            }
+            // A parent we're already below is a cycle; don't follow it
+            if !visiting.insert(name) {
+                return 0;
+            }
+            let d = parents
+                .get(name)
+                .into_iter()
+                .flatten()
+                .map(|p| depth(p, parents, depths, visiting))
+                .max()
+                .unwrap_or(0)
+                + 1;
+            visiting.remove(name);
+            depths.insert(name.to_string(), d);
+            d
+        }
+
+        let mut depths = HashMap::new();
+        let mut visiting = HashSet::new();
+        for name in self.groups.keys() {
+            depth(name, &parents, &mut depths, &mut visiting);
+        }
+        depths
+    }
+
+    /// Find `children` cycles, each returned as the path of group names that
+    /// leads back to its first element (e.g. `["a", "b", "a"]`).
+    pub fn group_cycles(&self) -> Vec<Vec<String>> {
+        let mut names: Vec<&String> = self.groups.keys().collect();
+        names.sort();
+
+        let mut done = HashSet::new();
+        let mut stack = Vec::new();
+        let mut cycles = Vec::new();
+        for name in names {
+            self.walk_cycles(name, &mut stack, &mut done, &mut cycles);
+        }
+        cycles
+    }
+
+    fn walk_cycles(
+        &self,
+        name: &str,
+        stack: &mut Vec<String>,
+        done: &mut HashSet<String>,
+        cycles: &mut Vec<Vec<String>>,
+    ) {
+        if let Some(pos) = stack.iter().position(|g| g == name) {
+            let mut cycle = stack[pos..].to_vec();
+            cycle.push(name.to_string());
+            cycles.push(cycle);
+            return;
+        }
+        if done.contains(name) {
+            return;
+        }
+
+        stack.push(name.to_string());
+        if let Some(group) = self.groups.get(name) {
+            for child in &group.children {
+                self.walk_cycles(child, stack, done, cycles);
+            }
+        }
+        stack.pop();
+        done.insert(name.to_string());
     }
 
     #[allow(dead_code)]
@@ -1,6 +1,7 @@
 use anyhow::{Context, Result};
 use serde_yaml::Value;
 
+use super::range;
 use super::types::{Group, Host, Inventory};
 
 pub fn parse_yaml(content: &str) -> Result<Inventory> {
@@ -16,8 +17,8 @@ pub fn parse_yaml(content: &str) -> Result<Inventory> {
 
     if let Value::Mapping(root_map) = &root {
         // Handle top-level "all" group or treat entire doc as group definitions
-        if let Some(all_value) = root_map.get(&Value::String("all".to_string())) {
-            parse_group_value(&mut inventory, "all", all_value)?;
+        if let Some(all_value) = root_map.get(Value::String("all".to_string())) {
+            parse_group_value(&mut inventory, "all", all_value, &mut Vec::new())?;
         } else {
             // Each top-level key is a group
             for (key, value) in root_map {
@@ -27,78 +28,116 @@ pub fn parse_yaml(content: &str) -> Result<Inventory> {
                             .groups
                             .insert(group_name.clone(), Group::new(group_name));
                     }
-                    parse_group_value(&mut inventory, group_name, value)?;
+                    parse_group_value(&mut inventory, group_name, value, &mut Vec::new())?;
                 }
             }
         }
     }
 
+    inventory.resolve_vars();
     Ok(inventory)
 }
 
-fn parse_group_value(inventory: &mut Inventory, group_name: &str, value: &Value) -> Result<()> {
+fn parse_group_value(
+    inventory: &mut Inventory,
+    group_name: &str,
+    value: &Value,
+    ancestors: &mut Vec<String>,
+) -> Result<()> {
     if let Value::Mapping(map) = value {
-        // 1. Process children FIRST so descendant hosts exist before vars are applied
-        if let Some(children_value) = map.get(&Value::String("children".to_string())) {
-            if let Value::Mapping(children_map) = children_value {
-                for (child_key, child_value) in children_map {
-                    if let Value::String(child_name) = child_key {
-                        if !inventory.groups.contains_key(child_name) {
-                            inventory
-                                .groups
-                                .insert(child_name.clone(), Group::new(child_name));
-                        }
+        ancestors.push(group_name.to_string());
 
-                        if let Some(group) = inventory.groups.get_mut(group_name) {
-                            if !group.children.contains(child_name) {
-                                group.children.push(child_name.clone());
-                            }
+        // 1. Process children
+        if let Some(Value::Mapping(children_map)) = map.get(Value::String("children".to_string())) {
+            for (child_key, child_value) in children_map {
+                if let Value::String(child_name) = child_key {
+                    if !inventory.groups.contains_key(child_name) {
+                        inventory
+                            .groups
+                            .insert(child_name.clone(), Group::new(child_name));
+                    }
+
+                    if let Some(group) = inventory.groups.get_mut(group_name) {
+                        if !group.children.contains(child_name) {
+                            group.children.push(child_name.clone());
                         }
+                    }
 
-                        parse_group_value(inventory, child_name, child_value)?;
+                    // A child naming one of its own ancestors is a cycle: keep the
+                    // link so it gets reported, but don't descend into it again
+                    if !ancestors.contains(child_name) {
+                        parse_group_value(inventory, child_name, child_value, ancestors)?;
                     }
                 }
             }
         }
 
         // 2. Process direct hosts
-        if let Some(hosts_value) = map.get(&Value::String("hosts".to_string())) {
-            if let Value::Mapping(hosts_map) = hosts_value {
-                for (host_key, host_vars) in hosts_map {
-                    if let Value::String(host_name) = host_key {
-                        let host = inventory
-                            .hosts
-                            .entry(host_name.clone())
-                            .or_insert_with(|| Host::new(host_name));
+        if let Some(Value::Mapping(hosts_map)) = map.get(Value::String("hosts".to_string())) {
+            for (host_key, host_vars) in hosts_map {
+                let Value::String(host_name) = host_key else {
+                    inventory.warn(
+                        None,
+                        format!("{group_name}: host name must be a string, got {host_key:?}"),
+                    );
+                    continue;
+                };
+                if !matches!(host_vars, Value::Mapping(_) | Value::Null) {
+                    inventory.warn(
+                        None,
+                        format!("{group_name}: vars of host {host_name} must be a mapping"),
+                    );
+                    continue;
+                }
 
-                        if !host.groups.contains(&group_name.to_string()) {
-                            host.groups.push(group_name.to_string());
-                        }
+                let host_names = match range::expand_hosts(host_name) {
+                    Ok(names) => names,
+                    Err(e) => {
+                        inventory.warn(None, format!("{group_name}: {e}"));
+                        continue;
+                    }
+                };
 
-                        // Parse host variables — these are recorded as host-level
-                        // so group vars can never overwrite them
-                        if let Value::Mapping(vars_map) = host_vars {
-                            for (var_key, var_val) in vars_map {
-                                if let Value::String(k) = var_key {
-                                    let v = value_to_string(var_val);
-                                    host.apply_host_var(k, &v);
+                for host_name in &host_names {
+                    let host = inventory
+                        .hosts
+                        .entry(host_name.clone())
+                        .or_insert_with(|| Host::new(host_name));
+
+                    if !host.groups.contains(&group_name.to_string()) {
+                        host.groups.push(group_name.to_string());
+                    }
+
+                    // Parse host variables — these are recorded as host-level
+                    // so group vars can never overwrite them
+                    let mut bad_vars = Vec::new();
+                    if let Value::Mapping(vars_map) = host_vars {
+                        for (var_key, var_val) in vars_map {
+                            if let Value::String(k) = var_key {
+                                let v = value_to_string(var_val);
+                                match Host::check_var(k, &v) {
+                                    Ok(()) => host.apply_host_var(k, &v),
+                                    Err(e) => bad_vars.push(format!("{host_name}: {e}")),
                                 }
                             }
                         }
+                    }
+                    for message in bad_vars {
+                        inventory.warn(None, message);
+                    }
 
-                        // Add to group
-                        if let Some(group) = inventory.groups.get_mut(group_name) {
-                            if !group.hosts.contains(host_name) {
-                                group.hosts.push(host_name.clone());
-                            }
+                    // Add to group
+                    if let Some(group) = inventory.groups.get_mut(group_name) {
+                        if !group.hosts.contains(host_name) {
+                            group.hosts.push(host_name.clone());
                         }
+                    }
 
-                        // Add to "all"
-                        if group_name != "all" {
-                            if let Some(all) = inventory.groups.get_mut("all") {
-                                if !all.hosts.contains(host_name) {
-                                    all.hosts.push(host_name.clone());
-                                }
+                    // Add to "all"
+                    if group_name != "all" {
+                        if let Some(all) = inventory.groups.get_mut("all") {
+                            if !all.hosts.contains(host_name) {
+                                all.hosts.push(host_name.clone());
                             }
                         }
                     }
@@ -106,30 +145,24 @@ fn parse_group_value(inventory: &mut Inventory, group_name: &str, value: &Value)
             }
         }
 
-        // 3. Process vars LAST - apply to direct hosts AND all descendant hosts
-        //    Host-level vars are protected and won't be overwritten
-        if let Some(vars_value) = map.get(&Value::String("vars".to_string())) {
-            if let Value::Mapping(vars_map) = vars_value {
-                // Collect all hosts: direct + via children (recursively)
-                let host_names = inventory.hosts_in_group(group_name);
-
-                for (var_key, var_val) in vars_map {
-                    if let Value::String(k) = var_key {
-                        let v = value_to_string(var_val);
-
-                        if let Some(group) = inventory.groups.get_mut(group_name) {
-                            group.vars.insert(k.clone(), v.clone());
-                        }
-
-                        for host_name in &host_names {
-                            if let Some(host) = inventory.hosts.get_mut(host_name) {
-                                host.apply_group_var(k, &v);
-                            }
-                        }
+        // 3. Record group vars; they're applied to hosts by precedence once
+        //    the whole inventory is parsed
+        if let Some(Value::Mapping(vars_map)) = map.get(Value::String("vars".to_string())) {
+            for (var_key, var_val) in vars_map {
+                if let Value::String(k) = var_key {
+                    let v = value_to_string(var_val);
+                    if let Err(e) = Host::check_var(k, &v) {
+                        inventory.warn(None, format!("{group_name}: {e}"));
+                        continue;
+                    }
+                    if let Some(group) = inventory.groups.get_mut(group_name) {
+                        group.vars.insert(k.clone(), v);
                     }
                 }
             }
         }
+
+        ancestors.pop();
     }
 
     Ok(())
@@ -180,6 +213,26 @@ all:
         );
     }
 
+    #[test]
+    fn test_ipv6_addresses() {
+        let content = r#"
+all:
+  hosts:
+    web01:
+      ansible_host: 2001:db8::10
+    web02:
+      ansible_host: "[2001:db8::20]"
+    2001:db8::30:
+      ansible_port: 2222
+"#;
+        let inv = parse_yaml(content).unwrap();
+        assert!(inv.warnings.is_empty());
+        assert_eq!(inv.hosts["web01"].ansible_host.as_deref(), Some("2001:db8::10"));
+        assert_eq!(inv.hosts["web02"].ssh_address(), "2001:db8::20");
+        assert_eq!(inv.hosts["2001:db8::30"].effective_host(), "2001:db8::30");
+        assert_eq!(inv.hosts["2001:db8::30"].ansible_port, Some(2222));
+    }
+
     #[test]
     fn test_host_vars_override_group_vars() {
         let content = r#"
@@ -270,4 +323,80 @@ cloud:
         // parent group var propagates
         assert_eq!(host.vars.get("region").map(|s| s.as_str()), Some("us-east"));
     }
+
+    #[test]
+    fn test_legacy_ssh_aliases() {
+        let content = r#"
+all:
+  children:
+    db:
+      vars:
+        ansible_ssh_port: 2201
+      hosts:
+        db01:
+          ansible_ssh_host: 192.168.1.10
+        db02:
+          ansible_ssh_host: 192.168.1.11
+          ansible_ssh_port: 2222
+"#;
+        let inv = parse_yaml(content).unwrap();
+        assert_eq!(inv.hosts["db01"].effective_host(), "192.168.1.10");
+        assert_eq!(inv.hosts["db01"].ansible_port, Some(2201));
+        assert_eq!(inv.hosts["db02"].effective_host(), "192.168.1.11");
+        assert_eq!(inv.hosts["db02"].ansible_port, Some(2222));
+    }
+
+    #[test]
+    fn test_bad_nodes_become_warnings() {
+        let content = r#"
+all:
+  children:
+    web:
+      hosts:
+        web01:
+          ansible_port: not-a-port
+        web02: [oops]
+        web03:
+"#;
+        let inv = parse_yaml(content).unwrap();
+        assert!(inv.hosts.contains_key("web01"));
+        assert!(inv.hosts.contains_key("web03"));
+        assert!(!inv.hosts.contains_key("web02"));
+        assert_eq!(inv.warnings.len(), 2);
+    }
+
+    #[test]
+    fn test_swap_thresholds_from_vars() {
+        let content = r#"
+all:
+  children:
+    db:
+      vars:
+        ansimon_swap_warning: 1
+        ansimon_swap_critical: 10
+      hosts:
+        db01:
+        laptop01:
+          ansimon_swap_critical: 95
+"#;
+        let inv = parse_yaml(content).unwrap();
+        assert_eq!(inv.hosts["db01"].swap_thresholds(), (1.0, 10.0));
+        assert_eq!(inv.hosts["laptop01"].swap_thresholds(), (1.0, 95.0));
+        assert_eq!(Host::new("plain").swap_thresholds(), (50.0, 80.0));
+    }
+
+    #[test]
+    fn test_host_ranges() {
+        let content = r#"
+all:
+  children:
+    web:
+      hosts:
+        web[1:3]:
+          ansible_port: 2222
+"#;
+        let inv = parse_yaml(content).unwrap();
+        assert_eq!(inv.hosts.len(), 3);
+        assert_eq!(inv.hosts["web3"].ansible_port, Some(2222));
+    }
 }
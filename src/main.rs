@@ -1,5 +1,7 @@
+mod check;
 mod cli;
 mod config;
+mod dump;
 mod inventory;
 mod metrics;
 mod ssh;
@@ -7,60 +9,176 @@ mod tui;
 
 use std::sync::Arc;
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 use clap::Parser;
 
-use cli::{Args, ResolvedArgs};
-use config::Config;
-use inventory::limit::apply_limit;
+use cli::{Args, Command, InventoryCommand, ResolvedArgs};
+use config::{Config, SshSettings};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli_args = Args::parse();
+    let command = cli_args.command.clone();
     let config = Config::load();
 
     // Merge: CLI > config > defaults
-    let args = ResolvedArgs {
+    let mut args = ResolvedArgs {
         inventory: cli_args
             .inventory
             .or(Some(config.inventory))
             .unwrap_or_else(|| "/etc/ansible/hosts".to_string()),
-        limit: cli_args.limit,
+        limit: resolve_preset(cli_args.limit, &config.filter_presets)?,
+        groups: cli_args.group,
         interval: cli_args.interval.unwrap_or(config.interval),
-        user: cli_args.user.or(config.user),
-        key: cli_args.key.or(config.key),
-        port: cli_args.port.or(config.port),
+        user: cli_args.user,
+        key: cli_args.key,
+        port: cli_args.port,
+        config_ssh: SshSettings {
+            user: config.user,
+            port: config.port,
+            key: config.key,
+            proxy_command: config.proxy_command,
+        },
+        group_ssh: config.groups,
         forks: cli_args.forks.unwrap_or(config.forks),
         ssh_timeout: config.ssh_timeout,
+        command_timeout: config.command_timeout,
+        jump_host: config.jump_host,
+        ssh_multiplexing: config.ssh_multiplexing,
+        ssh_backend: ssh::backend(config.ssh_backend)?,
+        sudo: cli_args.become_sudo,
+        ssh_password: None,
+        stagger: config.stagger,
+        max_connections_per_sec: config.max_connections_per_sec,
+        retry_attempts: config.retry_attempts,
+        retry_delay_ms: config.retry_delay_ms,
         warning_threshold: config.thresholds.warning,
         critical_threshold: config.thresholds.critical,
+        load_warning_per_cpu: config.thresholds.load_warning_per_cpu,
+        load_critical_per_cpu: config.thresholds.load_critical_per_cpu,
+        temp_warning: config.thresholds.temp_warning,
+        temp_critical: config.thresholds.temp_critical,
+        entropy_warning: config.thresholds.entropy_warning,
+        entropy_critical: config.thresholds.entropy_critical,
+        scroll_lines: config.scroll_lines.max(1),
+        visible_columns: config.visible_columns,
+        alert_on_transition: config.alert_on_transition,
+        bar_charts: config.bar_charts,
+        custom_metrics: config.custom_metrics,
+        primary_group: config.primary_group,
+        host_key_checking: config.host_key_checking,
+        known_hosts_file: config.known_hosts_file,
+        ssh_config_file: config.ssh_config_file,
+        ignore_user_ssh_config: config.ignore_user_ssh_config,
+        filter_presets: {
+            let mut presets: Vec<_> = config.filter_presets.into_iter().collect();
+            presets.sort();
+            presets
+        },
+        strict: cli_args.strict,
     };
 
-    // Load inventory
-    let inv = inventory::load_inventory(&args.inventory)
-        .with_context(|| format!("Failed to load inventory from: {}", args.inventory))?;
-
-    // Get hosts, apply --limit if specified
-    let hosts: Vec<inventory::types::Host> = if let Some(ref limit) = args.limit {
-        let host_names = apply_limit(&inv, limit);
-        if host_names.is_empty() {
-            anyhow::bail!("No hosts matched the limit pattern: {limit}");
+    for label in &args.visible_columns {
+        if tui::app::Column::from_label(label).is_none() {
+            let known: Vec<&str> = tui::app::Column::ALL.iter().map(|c| c.label()).collect();
+            eprintln!(
+                "Warning: unknown column {label:?} in visible_columns (known: {})",
+                known.join(", ")
+            );
         }
-        host_names
-            .iter()
-            .filter_map(|name| inv.hosts.get(name).cloned())
-            .collect()
-    } else {
-        inv.all_hosts().into_iter().cloned().collect()
-    };
+    }
+
+    if let Some(Command::Inventory {
+        action: Some(InventoryCommand::Check),
+        ..
+    }) = command
+    {
+        let ok = dump::check_inventory(&args)?;
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    // Load inventory, apply --group and --limit if specified
+    let (hosts, warnings) = inventory::load_hosts(
+        &args.inventory,
+        args.limit.as_deref(),
+        &args.groups,
+        args.primary_group,
+        args.strict,
+    )?;
+    for warning in &warnings {
+        eprintln!("Warning: {warning}");
+    }
+
+    if let Some(Command::Inventory { format, .. }) = command {
+        return dump::print_hosts(&hosts, &args, format);
+    }
 
-    if hosts.is_empty() {
-        anyhow::bail!("No hosts found in inventory: {}", args.inventory);
+    if cli_args.ask_pass {
+        args.ssh_password = Some(prompt_password("SSH password: ")?);
+    }
+
+    if let Some(Command::Check { allow_failures }) = command {
+        let ok = check::run(&hosts, Arc::new(args), allow_failures).await?;
+        std::process::exit(if ok { 0 } else { 1 });
     }
 
     let num_hosts = hosts.len();
     eprintln!("Ansimon starting with {num_hosts} host(s)...");
 
     let args = Arc::new(args);
-    tui::run(hosts, args).await
+    tui::run(hosts, warnings, args).await
+}
+
+/// Replace a `preset:NAME` limit with that filter preset's value.
+fn resolve_preset(
+    limit: Option<String>,
+    presets: &std::collections::HashMap<String, String>,
+) -> Result<Option<String>> {
+    let Some(name) = limit.as_deref().and_then(|l| l.strip_prefix("preset:")) else {
+        return Ok(limit);
+    };
+    match presets.get(name) {
+        Some(value) => Ok(Some(value.clone())),
+        None => {
+            let mut names: Vec<&str> = presets.keys().map(|k| k.as_str()).collect();
+            names.sort();
+            if names.is_empty() {
+                anyhow::bail!("Unknown filter preset '{name}': no filter_presets in the config");
+            }
+            anyhow::bail!("Unknown filter preset '{name}' (available: {})", names.join(", "))
+        }
+    }
+}
+
+/// Read a password from the terminal without echoing it.
+fn prompt_password(prompt: &str) -> Result<String> {
+    use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+    use std::io::Write;
+
+    eprint!("{prompt}");
+    std::io::stderr().flush()?;
+    crossterm::terminal::enable_raw_mode()?;
+
+    let mut password = String::new();
+    let result = loop {
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Enter => break Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    break Err(anyhow::anyhow!("Password prompt cancelled"))
+                }
+                KeyCode::Char(c) => password.push(c),
+                KeyCode::Backspace => {
+                    password.pop();
+                }
+                _ => {}
+            },
+            Ok(_) => {}
+            Err(e) => break Err(e.into()),
+        }
+    };
+
+    crossterm::terminal::disable_raw_mode()?;
+    eprintln!();
+    result.map(|()| password)
 }
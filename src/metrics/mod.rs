@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::time::Instant;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -6,6 +7,8 @@ pub enum HostStatus {
     Connecting,
     Up,
     Down,
+    /// Skipped with `ansimon_disabled` in the inventory
+    Disabled,
 }
 
 impl HostStatus {
@@ -15,11 +18,58 @@ impl HostStatus {
             HostStatus::Connecting => "[..]",
             HostStatus::Up => "[UP]",
             HostStatus::Down => "[DN]",
+            HostStatus::Disabled => "[DS]",
         }
     }
 }
 
+/// Why an SSH poll failed, recognized from ssh's stderr.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SshErrorKind {
+    Auth,
+    Timeout,
+    Refused,
+    Unreachable,
+    /// The host's key differs from the known one: possibly an attack
+    HostKeyChanged,
+    /// The host name didn't resolve
+    Dns,
+    /// ssh connected, but the metrics command exited with an error
+    Command,
+    /// The metrics command ran, but its output couldn't be parsed
+    Parse,
+}
+
+impl SshErrorKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SshErrorKind::Auth => "Authentication failed",
+            SshErrorKind::Timeout => "Timed out",
+            SshErrorKind::Refused => "Connection refused",
+            SshErrorKind::Unreachable => "Host unreachable",
+            SshErrorKind::HostKeyChanged => "HOST KEY CHANGED",
+            SshErrorKind::Dns => "Host name not resolved",
+            SshErrorKind::Command => "Remote command failed",
+            SshErrorKind::Parse => "Unparseable output",
+        }
+    }
+
+    /// One-letter code shown in the status column.
+    pub fn code(&self) -> char {
+        match self {
+            SshErrorKind::Auth => 'A',
+            SshErrorKind::Timeout => 'T',
+            SshErrorKind::Refused => 'R',
+            SshErrorKind::Unreachable => 'U',
+            SshErrorKind::HostKeyChanged => 'K',
+            SshErrorKind::Dns => 'D',
+            SshErrorKind::Command => 'C',
+            SshErrorKind::Parse => 'P',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Severity {
     Ok,
     Warning,
@@ -59,7 +109,28 @@ pub fn human_bytes(n: u64) -> String {
     }
 }
 
+/// Inode usage of a single mounted filesystem.
 #[derive(Debug, Clone)]
+pub struct InodeMount {
+    pub mount: String,
+    pub used_pct: f64,
+}
+
+impl InodeMount {
+    pub fn inode_severity(&self, warning: f64, critical: f64) -> Severity {
+        Severity::from_percent(self.used_pct, warning, critical)
+    }
+}
+
+/// Throughput of a single network interface.
+#[derive(Debug, Clone)]
+pub struct NetInterface {
+    pub name: String,
+    pub rx_bytes_sec: u64,
+    pub tx_bytes_sec: u64,
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct Metrics {
     pub cpu_percent: f64,
     pub mem_used_gb: f64,
@@ -76,11 +147,50 @@ pub struct Metrics {
     pub swap_total_gb: f64,
     pub net_rx_bytes_sec: u64,
     pub net_tx_bytes_sec: u64,
+    pub net_interfaces: Vec<NetInterface>,
+    /// Packet errors and drops since the previous poll, summed over non-lo
+    /// interfaces
+    pub net_rx_errors: u64,
+    pub net_rx_drops: u64,
+    pub net_tx_errors: u64,
+    pub net_tx_drops: u64,
     pub tcp_conns: u32,
+    /// UDP, raw and IP fragment sockets in use
+    pub udp_conns: u32,
+    pub raw_sockets: u32,
+    pub frag_inuse: u32,
+    pub tcp_established: u32,
+    pub tcp_time_wait: u32,
+    pub tcp_close_wait: u32,
     pub procs_running: u32,
     pub procs_total: u32,
+    pub procs_zombie: u32,
+    pub procs_dstate: u32,
     pub disk_read_bytes_sec: u64,
     pub disk_write_bytes_sec: u64,
+    pub inode_mounts: Vec<InodeMount>,
+    pub systemd_failed_units: u32,
+    pub kernel_version: Option<String>,
+    /// Kernel name from `uname -s`, e.g. "Linux" or "Darwin"
+    pub os_type: Option<String>,
+    /// Distribution ID and version from /etc/os-release, e.g. "ubuntu 22.04",
+    /// or the macOS/BSD release, e.g. "macos 14.4"
+    pub os_info: Option<String>,
+    /// Thermal zone temperatures, in °C
+    pub temps_celsius: Vec<f64>,
+    pub max_temp_celsius: Option<f64>,
+    /// System-wide file handles: allocated, limit and usage
+    pub fd_allocated: u64,
+    pub fd_max: u64,
+    pub fd_used_pct: f64,
+    /// Bits in the kernel entropy pool
+    pub entropy_avail: Option<u32>,
+    /// How long the metrics command ran on the host
+    pub command_runtime_ms: Option<u64>,
+    /// Output of each configured custom metric, keyed by name
+    pub custom: HashMap<String, String>,
+    /// sudo's error when become was requested but the metrics ran unprivileged
+    pub become_error: Option<String>,
 }
 
 impl Metrics {
@@ -100,6 +210,40 @@ impl Metrics {
         Severity::from_percent(self.disk_percent, warning, critical)
     }
 
+    /// Severity of the 1-minute load average relative to the CPU count.
+    pub fn load_severity(&self, warning_per_cpu: f64, critical_per_cpu: f64) -> Severity {
+        let per_cpu = self.load_1 / self.num_cpus.max(1) as f64;
+        if per_cpu > critical_per_cpu {
+            Severity::Critical
+        } else if per_cpu > warning_per_cpu {
+            Severity::Warning
+        } else {
+            Severity::Ok
+        }
+    }
+
+    /// Severity of the hottest thermal zone. Hosts without sensors are Ok.
+    pub fn temp_severity(&self, warning: f64, critical: f64) -> Severity {
+        match self.max_temp_celsius {
+            Some(t) if t > critical => Severity::Critical,
+            Some(t) if t > warning => Severity::Warning,
+            _ => Severity::Ok,
+        }
+    }
+
+    /// Severity of the entropy pool; unlike the other metrics, lower is worse.
+    pub fn entropy_severity(&self, warning: u32, critical: u32) -> Severity {
+        match self.entropy_avail {
+            Some(e) if e < critical => Severity::Critical,
+            Some(e) if e < warning => Severity::Warning,
+            _ => Severity::Ok,
+        }
+    }
+
+    pub fn fd_severity(&self, warning: f64, critical: f64) -> Severity {
+        Severity::from_percent(self.fd_used_pct, warning, critical)
+    }
+
     pub fn mem_percent(&self) -> f64 {
         if self.mem_total_gb > 0.0 {
             self.mem_used_gb / self.mem_total_gb * 100.0
@@ -108,16 +252,9 @@ impl Metrics {
         }
     }
 
-    pub fn swap_severity(&self) -> Severity {
+    pub fn swap_severity(&self, warning: f64, critical: f64) -> Severity {
         if self.swap_total_gb > 0.0 {
-            let pct = self.swap_used_gb / self.swap_total_gb * 100.0;
-            if pct > 80.0 {
-                Severity::Critical
-            } else if pct > 50.0 {
-                Severity::Warning
-            } else {
-                Severity::Ok
-            }
+            Severity::from_percent(self.swap_used_gb / self.swap_total_gb * 100.0, warning, critical)
         } else {
             Severity::Ok
         }
@@ -158,22 +295,41 @@ impl Metrics {
         self.swap_total_gb > 0.01
     }
 
-    pub fn swap_display(&self) -> String {
+    pub fn swap_display(&self, warning: f64, critical: f64) -> String {
         if !self.has_swap() {
             "N/A".to_string()
         } else {
             format!(
                 "{} {:.1}/{:.0}G",
-                self.swap_severity().indicator(),
+                self.swap_severity(warning, critical).indicator(),
                 self.swap_used_gb,
                 self.swap_total_gb
             )
         }
     }
 
+    pub fn has_net_faults(&self) -> bool {
+        self.net_rx_errors + self.net_rx_drops + self.net_tx_errors + self.net_tx_drops > 0
+    }
+
+    /// The `n` busiest interfaces by combined RX+TX throughput.
+    pub fn top_interfaces(&self, n: usize) -> Vec<&NetInterface> {
+        let mut ifaces: Vec<&NetInterface> = self.net_interfaces.iter().collect();
+        ifaces.sort_by_key(|i| std::cmp::Reverse(i.rx_bytes_sec + i.tx_bytes_sec));
+        ifaces.truncate(n);
+        ifaces
+    }
+
     pub fn tcp_display(&self) -> String {
         format!("{}", self.tcp_conns)
     }
+
+    pub fn tcp_states_display(&self) -> String {
+        format!(
+            "(est: {}, tw: {}, cw: {})",
+            self.tcp_established, self.tcp_time_wait, self.tcp_close_wait
+        )
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -183,10 +339,55 @@ pub struct HostMetrics {
     pub metrics: Option<Metrics>,
     pub last_updated: Option<Instant>,
     pub error: Option<String>,
+    pub error_kind: Option<SshErrorKind>,
     pub ssh_latency_ms: Option<u64>,
+    /// ProxyJump used to reach the host, if any
+    pub jump_host: Option<String>,
+    /// Whether the poll reused an existing SSH master connection
+    pub multiplexed: bool,
+    /// ssh_config file in effect for the connection, if it went over SSH
+    pub ssh_config: Option<String>,
+    /// Failed polls since the last successful one
+    pub consecutive_failures: u32,
+    /// When the host last polled successfully
+    pub last_success: Option<Instant>,
+    /// When the current run of failed polls started
+    pub down_since: Option<Instant>,
 }
 
 impl HostMetrics {
+    /// Status column text: the status, or a one-letter code like `[A]` for a
+    /// recognized failure.
+    pub fn status_indicator(&self) -> String {
+        match (self.status, self.error_kind) {
+            (HostStatus::Down, Some(kind)) => format!("[{}]", kind.code()),
+            (status, _) => status.indicator().to_string(),
+        }
+    }
+
+    /// Carry the failure history over from the host's previous result and
+    /// update it with this one. The poller builds each result from scratch.
+    pub fn track_history(&mut self, previous: &HostMetrics) {
+        let at = self.last_updated.unwrap_or_else(Instant::now);
+        match self.status {
+            HostStatus::Up => {
+                self.consecutive_failures = 0;
+                self.last_success = Some(at);
+                self.down_since = None;
+            }
+            HostStatus::Down => {
+                self.consecutive_failures = previous.consecutive_failures + 1;
+                self.last_success = previous.last_success;
+                self.down_since = previous.down_since.or(Some(at));
+            }
+            _ => {
+                self.consecutive_failures = previous.consecutive_failures;
+                self.last_success = previous.last_success;
+                self.down_since = previous.down_since;
+            }
+        }
+    }
+
     pub fn new(host_name: &str) -> Self {
         Self {
             host_name: host_name.to_string(),
@@ -194,7 +395,68 @@ impl HostMetrics {
             metrics: None,
             last_updated: None,
             error: None,
+            error_kind: None,
             ssh_latency_ms: None,
+            jump_host: None,
+            multiplexed: false,
+            ssh_config: None,
+            consecutive_failures: 0,
+            last_success: None,
+            down_since: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// A result with `status`, as the poller reports it `secs` after `start`.
+    fn result(status: HostStatus, start: Instant, secs: u64) -> HostMetrics {
+        let mut metrics = HostMetrics::new("web01");
+        metrics.status = status;
+        metrics.last_updated = Some(start + Duration::from_secs(secs));
+        metrics
+    }
+
+    #[test]
+    fn test_track_history() {
+        let start = Instant::now();
+        let at = |secs| Some(start + Duration::from_secs(secs));
+
+        let mut up = result(HostStatus::Up, start, 0);
+        up.track_history(&HostMetrics::new("web01"));
+        assert_eq!((up.consecutive_failures, up.last_success, up.down_since), (0, at(0), None));
+
+        // Down since the first failure, keeping the last success
+        let mut down = result(HostStatus::Down, start, 10);
+        down.track_history(&up);
+        assert_eq!(
+            (down.consecutive_failures, down.last_success, down.down_since),
+            (1, at(0), at(10))
+        );
+        let mut still_down = result(HostStatus::Down, start, 20);
+        still_down.track_history(&down);
+        assert_eq!(
+            (still_down.consecutive_failures, still_down.last_success, still_down.down_since),
+            (2, at(0), at(10))
+        );
+
+        // A refresh in progress changes nothing
+        let mut connecting = result(HostStatus::Connecting, start, 25);
+        connecting.track_history(&still_down);
+        assert_eq!(
+            (connecting.consecutive_failures, connecting.last_success, connecting.down_since),
+            (2, at(0), at(10))
+        );
+
+        // Recovering clears the failures
+        let mut recovered = result(HostStatus::Up, start, 30);
+        recovered.track_history(&connecting);
+        assert_eq!(
+            (recovered.consecutive_failures, recovered.last_success, recovered.down_since),
+            (0, at(30), None)
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use anyhow::Result;
+
+/// Split a string into words using POSIX shell quoting rules.
+///
+/// Handles single quotes (literal), double quotes (with `\` escaping `"`, `\`,
+/// `$` and `` ` ``) and backslash escapes outside quotes, which is what
+/// Ansible's `shlex.split` does for `ansible_ssh_*_args`.
+pub fn shell_split(input: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut chars = input.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(ch) => current.push(ch),
+                        None => anyhow::bail!("Unterminated single quote in: {input}"),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(ch @ ('"' | '\\' | '$' | '`')) => current.push(ch),
+                            Some(ch) => {
+                                current.push('\\');
+                                current.push(ch);
+                            }
+                            None => anyhow::bail!("Unterminated double quote in: {input}"),
+                        },
+                        Some(ch) => current.push(ch),
+                        None => anyhow::bail!("Unterminated double quote in: {input}"),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                if let Some(ch) = chars.next() {
+                    current.push(ch);
+                }
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                current.push(c);
+            }
+        }
+    }
+
+    if in_word {
+        words.push(current);
+    }
+
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_words() {
+        assert_eq!(
+            shell_split("-o ProxyJump=jump.example.com").unwrap(),
+            vec!["-o", "ProxyJump=jump.example.com"]
+        );
+        assert!(shell_split("   ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_quoted_values_keep_spaces() {
+        assert_eq!(
+            shell_split(r#"-o 'ProxyCommand=ssh -W %h:%p jump' -o "User=a b""#).unwrap(),
+            vec!["-o", "ProxyCommand=ssh -W %h:%p jump", "-o", "User=a b"]
+        );
+        assert_eq!(
+            shell_split(r#"-o ProxyCommand="ssh -W %h:%p jump""#).unwrap(),
+            vec!["-o", "ProxyCommand=ssh -W %h:%p jump"]
+        );
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert_eq!(shell_split(r"a\ b c").unwrap(), vec!["a b", "c"]);
+        assert_eq!(shell_split(r#""say \"hi\"""#).unwrap(), vec![r#"say "hi""#]);
+        assert_eq!(shell_split("''").unwrap(), vec![""]);
+    }
+
+    #[test]
+    fn test_unterminated_quote() {
+        assert!(shell_split("-o 'ProxyJump=x").is_err());
+        assert!(shell_split(r#"-o "ProxyJump=x"#).is_err());
+    }
+}
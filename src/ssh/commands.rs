@@ -1,46 +1,255 @@
 use anyhow::{Context, Result};
 
-use crate::metrics::Metrics;
-
-/// Single remote command that collects all metrics from a Linux host.
-/// Uses section markers for robust parsing. Two-sample reads (stat, net/dev,
-/// diskstats) are grouped around a single `sleep 1` for delta calculation.
-pub fn metrics_command() -> &'static str {
-    concat!(
-        "echo '===STAT1'; cat /proc/stat | head -1; ",
-        "echo '===NETDEV1'; cat /proc/net/dev; ",
-        "echo '===DISKSTATS1'; cat /proc/diskstats; ",
-        "sleep 1; ",
-        "echo '===STAT2'; cat /proc/stat | head -1; ",
-        "echo '===NETDEV2'; cat /proc/net/dev; ",
-        "echo '===DISKSTATS2'; cat /proc/diskstats; ",
-        "echo '===MEMINFO'; cat /proc/meminfo | head -20; ",
-        "echo '===DF'; df -P / | tail -1; ",
-        "echo '===LOADAVG'; cat /proc/loadavg; ",
-        "echo '===UPTIME'; cat /proc/uptime; ",
-        "echo '===NPROC'; nproc; ",
-        "echo '===SOCKSTAT'; cat /proc/net/sockstat"
+use crate::config::CustomMetric;
+use crate::metrics::{InodeMount, Metrics, NetInterface};
+
+/// Single remote command that collects all metrics from a host.
+/// Uses section markers for robust parsing. The `===OS` section holds
+/// `uname -s`, which picks the Linux (/proc) or BSD (sysctl) half of the
+/// script. On Linux, CPU, network and disk rates come from counter deltas
+/// against the previous poll's sample; with `baseline` (a host's first poll)
+/// the command also takes a sample one second earlier to compare against.
+/// `disk_path` is the mount reported in the DF section. Custom metrics are
+/// appended as `===CUSTOM_<name>` sections. A final `===FINISHED` uptime
+/// reading times the command on the host, so its runtime can be told apart
+/// from SSH latency.
+///
+/// The script sticks to what BusyBox (Alpine, OpenWrt) also provides, and a
+/// section whose file or tool is missing is left empty rather than failing
+/// the whole command.
+pub fn metrics_command(disk_path: &str, custom: &[CustomMetric], baseline: bool) -> String {
+    // Without -P (old BusyBox) a long device name wraps df's line, but the
+    // last line still ends with the percentage and mount
+    let disk_path = shell_quote(disk_path);
+    let df = section(
+        "DF",
+        &format!("{{ df -P {disk_path} 2>/dev/null || df {disk_path}; }} | tail -n 1"),
+    );
+
+    let mut command = section("OS", "uname -s");
+    command.push_str(&format!(
+        "case \"$(uname -s)\" in {}) {};; *) {};; esac; ",
+        BSD_SYSTEMS.join("|"),
+        bsd_script(&df),
+        linux_script(&df, baseline)
+    ));
+
+    for metric in custom {
+        // Subshell so a failing or exiting snippet can't abort the rest
+        command.push_str(&format!(
+            "echo {}; ({}) 2>/dev/null; ",
+            shell_quote(&format!("===CUSTOM_{}", metric.name)),
+            metric.command
+        ));
+    }
+    // BSDs have no /proc/uptime; their FINISHED section stays empty
+    command.push_str("echo '===FINISHED'; cat /proc/uptime 2>/dev/null || true");
+
+    command
+}
+
+/// `uname -s` of the systems collected with the BSD half of the script.
+const BSD_SYSTEMS: &[&str] = &["Darwin", "FreeBSD", "OpenBSD", "NetBSD", "DragonFly"];
+
+/// The Linux half of the metrics script, reading /proc.
+fn linux_script(df: &str, baseline: bool) -> String {
+    let mut command = String::new();
+    if baseline {
+        command.push_str(&counter_script(BASELINE));
+        command.push_str("sleep 1; ");
+    }
+    command.push_str(&counter_script(""));
+    command.push_str(&section("MEMINFO", "head -n 20 /proc/meminfo"));
+    command.push_str(df);
+    for (name, script) in [
+        ("INODES", "df -Pi 2>/dev/null"),
+        ("LOADAVG", "cat /proc/loadavg"),
+        ("PROCSTATES", "grep -h '^State:[[:space:]]*[ZD]' /proc/[0-9]*/status 2>/dev/null"),
+        ("NPROC", "nproc 2>/dev/null || grep -c '^processor' /proc/cpuinfo"),
+        ("UNAME", "uname -r"),
+        ("OSRELEASE", "grep -E '^(ID|VERSION_ID)=' /etc/os-release 2>/dev/null"),
+        ("THERMALS", "cat /sys/class/thermal/thermal_zone*/temp 2>/dev/null"),
+        ("FILENR", "cat /proc/sys/fs/file-nr 2>/dev/null"),
+        ("ENTROPY", "cat /proc/sys/kernel/random/entropy_avail 2>/dev/null"),
+        ("SYSTEMD_FAILED", "systemctl --failed --no-legend --no-pager 2>/dev/null | wc -l"),
+        ("SOCKSTAT", "cat /proc/net/sockstat 2>/dev/null"),
+        (
+            "TCPSTATES",
+            "cat /proc/net/tcp /proc/net/tcp6 2>/dev/null | awk '{print $4}' | sort | uniq -c",
+        ),
+    ] {
+        command.push_str(&section(name, script));
+    }
+    command
+}
+
+/// Kernel settings read by the BSD half of the script. Each is read on its
+/// own, as an unknown name makes some sysctl(8)s skip the rest.
+const BSD_SYSCTLS: &[&str] = &[
+    "hw.ncpu",
+    "hw.memsize",
+    "hw.physmem",
+    "hw.pagesize",
+    "vm.loadavg",
+    "kern.boottime",
+    "vm.swapusage",
+    "vm.stats.vm.v_free_count",
+    "vm.stats.vm.v_inactive_count",
+    "kern.num_files",
+    "kern.openfiles",
+    "kern.maxfiles",
+];
+
+/// The BSD half of the metrics script, for macOS and the BSDs: sysctl for
+/// most values, vm_stat (macOS) for memory and swapinfo (FreeBSD) for swap.
+/// iostat samples CPU usage over one second; network rates come from
+/// `netstat -ib` byte counters against the previous poll.
+fn bsd_script(df: &str) -> String {
+    let mut command = String::new();
+    for (name, script) in [
+        ("UNAME", "uname -r".to_string()),
+        (
+            "OSRELEASE",
+            "if [ \"$(uname -s)\" = Darwin ]; \
+             then printf 'ID=macos\\nVERSION_ID=%s\\n' \"$(sw_vers -productVersion)\"; \
+             else printf 'ID=%s\\nVERSION_ID=%s\\n' \"$(uname -s | tr A-Z a-z)\" \
+             \"$(freebsd-version 2>/dev/null || uname -r)\"; fi"
+                .to_string(),
+        ),
+        (
+            "SYSCTL",
+            format!("for oid in {}; do sysctl \"$oid\" 2>/dev/null; done", BSD_SYSCTLS.join(" ")),
+        ),
+        ("NOW", "date +%s".to_string()),
+        ("VMSTAT", "vm_stat 2>/dev/null".to_string()),
+        ("SWAPINFO", "swapinfo -k 2>/dev/null".to_string()),
+        ("IOSTAT", "iostat -c 2 -w 1 2>/dev/null".to_string()),
+        ("NETSTAT", "netstat -ib 2>/dev/null".to_string()),
+        (
+            "TCPSTATES",
+            "netstat -an -p tcp 2>/dev/null | awk '$1 ~ /^tcp/ {print $NF}' | sort | uniq -c"
+                .to_string(),
+        ),
+        ("PSSTATES", "ps -ax -o stat= | cut -c1 | sort | uniq -c".to_string()),
+    ] {
+        command.push_str(&section(name, &script));
+    }
+    command.push_str(df);
+    command
+}
+
+/// One section of the metrics script: its marker, then `script`. A failing
+/// script leaves the section empty, for the parser to fill with defaults.
+fn section(name: &str, script: &str) -> String {
+    format!("echo '==={name}'; {script} || true; ")
+}
+
+/// Section name suffix of the baseline sample taken on a host's first poll.
+const BASELINE: &str = "_BASE";
+
+/// Read the counters rates are computed from into sections whose names end
+/// in `suffix`. The uptime timestamps the sample.
+fn counter_script(suffix: &str) -> String {
+    [
+        section(&format!("UPTIME{suffix}"), "cat /proc/uptime"),
+        section(&format!("STAT{suffix}"), "head -n 1 /proc/stat"),
+        section(&format!("NETDEV{suffix}"), "cat /proc/net/dev 2>/dev/null"),
+        section(&format!("DISKSTATS{suffix}"), "cat /proc/diskstats 2>/dev/null"),
+    ]
+    .concat()
+}
+
+/// Raw counters from one poll, kept by the poller so the next poll of the
+/// host can compute rates without sleeping on the remote side.
+#[derive(Debug, Clone)]
+pub struct CounterSample {
+    /// Seconds since boot when the sample was taken
+    uptime: f64,
+    /// Fields of the aggregate `cpu` line of /proc/stat, in jiffies
+    cpu: Vec<u64>,
+    net: Vec<IfaceCounters>,
+    /// (device, sectors read, sectors written) for whole disks
+    disks: Vec<(String, u64, u64)>,
+}
+
+/// Counters of one network interface from /proc/net/dev.
+#[derive(Debug, Clone)]
+struct IfaceCounters {
+    name: String,
+    rx_bytes: u64,
+    tx_bytes: u64,
+    /// RX errors, RX drops, TX errors, TX drops
+    faults: [u64; 4],
+}
+
+/// Rates derived from two counter samples.
+#[derive(Debug, Default)]
+struct Rates {
+    cpu_percent: f64,
+    iowait_percent: f64,
+    net_rx_bytes_sec: u64,
+    net_tx_bytes_sec: u64,
+    net_interfaces: Vec<NetInterface>,
+    /// RX errors, RX drops, TX errors, TX drops between the samples
+    net_faults: [u64; 4],
+    disk_read_bytes_sec: u64,
+    disk_write_bytes_sec: u64,
+}
+
+/// Wrap the metrics command to run under `sudo -n`. When sudo can't run
+/// without a password the command runs unprivileged instead, and sudo's
+/// complaint is reported in a `===BECOME` section.
+pub fn become_command(command: &str, user: Option<&str>) -> String {
+    let sudo = match user {
+        Some(user) => format!("sudo -n -u {}", shell_quote(user)),
+        None => "sudo -n".to_string(),
+    };
+    format!(
+        "if become_err=$({sudo} true 2>&1); then {sudo} sh -c {}; \
+         else echo '===BECOME'; echo \"$become_err\"; {command}; fi",
+        shell_quote(command)
     )
 }
 
-/// Parse the output of the metrics command into a Metrics struct.
+/// Quote a string for safe use as a single POSIX shell word.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Parse the output of the metrics command into a Metrics struct, along with
+/// the counter sample to pass as `previous` when parsing the host's next poll.
+/// Rates are computed against the command's own baseline sample when it took
+/// one, else against `previous`; with neither (or after a reboot) they are 0.
 /// Uses section markers for robust, order-independent parsing.
-pub fn parse_metrics_output(output: &str) -> Result<Metrics> {
+pub fn parse_metrics_output(
+    output: &str,
+    previous: Option<&CounterSample>,
+) -> Result<(Metrics, CounterSample)> {
     let sections = parse_sections(output);
+    let os_type = sections
+        .get("OS")
+        .and_then(|s| s.lines().next())
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty());
+    if os_type.as_deref().is_some_and(|os| BSD_SYSTEMS.contains(&os)) {
+        return parse_bsd_output(&sections, os_type, previous);
+    }
 
-    let stat1 = sections.get("STAT1").context("Missing ===STAT1 section")?;
-    let stat2 = sections.get("STAT2").context("Missing ===STAT2 section")?;
     let meminfo = sections.get("MEMINFO").context("Missing ===MEMINFO section")?;
     let df = sections.get("DF").context("Missing ===DF section")?;
     let loadavg = sections.get("LOADAVG").context("Missing ===LOADAVG section")?;
-    let uptime = sections.get("UPTIME").context("Missing ===UPTIME section")?;
-    let nproc = sections.get("NPROC").context("Missing ===NPROC section")?;
 
-    // CPU + IO wait from stat delta
-    let stat1_line = stat1.lines().next().unwrap_or("");
-    let stat2_line = stat2.lines().next().unwrap_or("");
-    let (cpu_percent, iowait_percent) =
-        parse_cpu_delta(stat1_line, stat2_line).context("Failed to parse CPU")?;
+    // CPU, network and disk rates from counter deltas
+    let sample = parse_sample(&sections, "")?;
+    let baseline = if sections.contains_key(format!("STAT{BASELINE}").as_str()) {
+        Some(parse_sample(&sections, BASELINE)?)
+    } else {
+        None
+    };
+    let rates = match baseline.as_ref().or(previous) {
+        Some(before) => compute_rates(before, &sample),
+        None => Rates::default(),
+    };
 
     // Memory + Swap
     let meminfo_lines: Vec<&str> = meminfo.lines().collect();
@@ -53,6 +262,12 @@ pub fn parse_metrics_output(output: &str) -> Result<Metrics> {
     let df_line = df.lines().next().unwrap_or("");
     let disk_percent = parse_df(df_line).context("Failed to parse disk")?;
 
+    // Inode usage per filesystem
+    let inode_mounts = sections
+        .get("INODES")
+        .map(|s| parse_df_inodes(s))
+        .unwrap_or_default();
+
     // Load average + procs
     let loadavg_line = loadavg.lines().next().unwrap_or("");
     let (load_1, load_5, load_15) =
@@ -60,62 +275,246 @@ pub fn parse_metrics_output(output: &str) -> Result<Metrics> {
     let (procs_running, procs_total) =
         parse_procs(loadavg_line).unwrap_or((0, 0));
 
-    // Uptime
-    let uptime_line = uptime.lines().next().unwrap_or("");
-    let uptime_secs = parse_uptime(uptime_line).unwrap_or(0);
+    // Zombie and uninterruptible (D-state) processes
+    let (procs_zombie, procs_dstate) = sections
+        .get("PROCSTATES")
+        .map(|s| parse_proc_states(s))
+        .unwrap_or((0, 0));
 
-    // Nproc
-    let num_cpus = nproc
-        .lines()
-        .next()
+    // Nproc (counted from /proc/cpuinfo where nproc is missing)
+    let num_cpus = sections
+        .get("NPROC")
+        .and_then(|s| s.lines().next())
         .and_then(|l| l.trim().parse::<u32>().ok())
+        .filter(|n| *n > 0)
         .unwrap_or(1);
 
-    // Net RX/TX (delta of two samples)
-    let (net_rx_bytes_sec, net_tx_bytes_sec) = match (
-        sections.get("NETDEV1"),
-        sections.get("NETDEV2"),
-    ) {
-        (Some(nd1), Some(nd2)) => parse_net_delta(nd1, nd2).unwrap_or((0, 0)),
-        _ => (0, 0),
-    };
+    // Thermal zones (absent on most VMs)
+    let temps_celsius = sections
+        .get("THERMALS")
+        .map(|s| parse_thermals(s))
+        .unwrap_or_default();
+    let max_temp_celsius = temps_celsius.iter().copied().reduce(f64::max);
 
-    // Disk I/O (delta of two samples)
-    let (disk_read_bytes_sec, disk_write_bytes_sec) = match (
-        sections.get("DISKSTATS1"),
-        sections.get("DISKSTATS2"),
-    ) {
-        (Some(ds1), Some(ds2)) => parse_diskstats_delta(ds1, ds2).unwrap_or((0, 0)),
-        _ => (0, 0),
+    // File handles
+    let (fd_allocated, fd_max) = sections
+        .get("FILENR")
+        .and_then(|s| parse_file_nr(s))
+        .unwrap_or((0, 0));
+    let fd_used_pct = if fd_max > 0 {
+        fd_allocated as f64 / fd_max as f64 * 100.0
+    } else {
+        0.0
     };
 
+    // Entropy pool
+    let entropy_avail = sections
+        .get("ENTROPY")
+        .and_then(|s| s.lines().next())
+        .and_then(|l| l.trim().parse::<u32>().ok());
+
+    // Time the command took on the host, from its first to its last uptime
+    // reading (10ms resolution)
+    let started = baseline.as_ref().unwrap_or(&sample).uptime;
+    let command_runtime_ms = sections
+        .get("FINISHED")
+        .and_then(|s| parse_uptime(s.lines().next().unwrap_or("")).ok())
+        .map(|finished| finished - started)
+        .filter(|secs| *secs >= 0.0)
+        .map(|secs| (secs * 1000.0).round() as u64);
+
+    // Custom metrics, keyed by their configured name
+    let custom = sections
+        .iter()
+        .filter_map(|(key, value)| {
+            key.strip_prefix("CUSTOM_")
+                .map(|name| (name.to_string(), value.to_string()))
+        })
+        .collect();
+
+    // Why sudo was skipped, when become was requested but not possible
+    let become_error = sections
+        .get("BECOME")
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+
+    // Kernel and distribution
+    let kernel_version = sections
+        .get("UNAME")
+        .and_then(|s| s.lines().next())
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty());
+    let os_info = sections.get("OSRELEASE").and_then(|s| parse_os_release(s));
+
     // TCP connections
     let tcp_conns = sections
         .get("SOCKSTAT")
         .and_then(|s| parse_tcp_conns(s))
         .unwrap_or(0);
+    let sockstat = |proto| {
+        sections
+            .get("SOCKSTAT")
+            .and_then(|s| parse_sockstat_inuse(s, proto))
+            .unwrap_or(0)
+    };
+    let udp_conns = sockstat("UDP");
+    let raw_sockets = sockstat("RAW");
+    let frag_inuse = sockstat("FRAG");
 
-    Ok(Metrics {
-        cpu_percent,
+    // Failed systemd units (0 when systemctl is unavailable)
+    let systemd_failed_units = sections
+        .get("SYSTEMD_FAILED")
+        .and_then(|s| s.lines().next())
+        .and_then(|l| l.trim().parse::<u32>().ok())
+        .unwrap_or(0);
+
+    // TCP connection states
+    let (tcp_established, tcp_time_wait, tcp_close_wait) = sections
+        .get("TCPSTATES")
+        .map(|s| parse_tcp_states(s))
+        .unwrap_or((0, 0, 0));
+
+    let [net_rx_errors, net_rx_drops, net_tx_errors, net_tx_drops] = rates.net_faults;
+    let metrics = Metrics {
+        cpu_percent: rates.cpu_percent,
         mem_used_gb,
         mem_total_gb,
         disk_percent,
         load_1,
         load_5,
         load_15,
-        uptime_secs,
+        uptime_secs: sample.uptime as u64,
         num_cpus,
-        iowait_percent,
+        iowait_percent: rates.iowait_percent,
         swap_used_gb,
         swap_total_gb,
-        net_rx_bytes_sec,
-        net_tx_bytes_sec,
+        net_rx_bytes_sec: rates.net_rx_bytes_sec,
+        net_tx_bytes_sec: rates.net_tx_bytes_sec,
+        net_interfaces: rates.net_interfaces,
+        net_rx_errors,
+        net_rx_drops,
+        net_tx_errors,
+        net_tx_drops,
         tcp_conns,
+        udp_conns,
+        raw_sockets,
+        frag_inuse,
+        tcp_established,
+        tcp_time_wait,
+        tcp_close_wait,
         procs_running,
         procs_total,
-        disk_read_bytes_sec,
-        disk_write_bytes_sec,
-    })
+        procs_zombie,
+        procs_dstate,
+        disk_read_bytes_sec: rates.disk_read_bytes_sec,
+        disk_write_bytes_sec: rates.disk_write_bytes_sec,
+        inode_mounts,
+        systemd_failed_units,
+        kernel_version,
+        os_type,
+        os_info,
+        temps_celsius,
+        max_temp_celsius,
+        fd_allocated,
+        fd_max,
+        fd_used_pct,
+        entropy_avail,
+        command_runtime_ms,
+        custom,
+        become_error,
+    };
+    Ok((metrics, sample))
+}
+
+/// Parse the counter sections whose names end in `suffix`.
+fn parse_sample(
+    sections: &std::collections::HashMap<&str, &str>,
+    suffix: &str,
+) -> Result<CounterSample> {
+    let section = |name: &str| {
+        sections
+            .get(format!("{name}{suffix}").as_str())
+            .copied()
+            .with_context(|| format!("Missing ==={name}{suffix} section"))
+    };
+
+    let uptime = parse_uptime(section("UPTIME")?.lines().next().unwrap_or(""))
+        .context("Failed to parse uptime")?;
+    let cpu = parse_cpu_line(section("STAT")?.lines().next().unwrap_or(""))
+        .context("Failed to parse CPU")?;
+    let net = section("NETDEV").map(parse_net_counters).unwrap_or_default();
+    let disks = section("DISKSTATS").map(parse_diskstats).unwrap_or_default();
+
+    Ok(CounterSample { uptime, cpu, net, disks })
+}
+
+/// Rates between two samples of the same host, per second of uptime elapsed.
+/// Network errors and drops are totals between the samples. Devices missing
+/// from either sample are skipped.
+fn compute_rates(before: &CounterSample, after: &CounterSample) -> Rates {
+    let elapsed = after.uptime - before.uptime;
+    // Uptime going backwards means a reboot reset every counter
+    if elapsed <= 0.0 {
+        return Rates::default();
+    }
+    let per_sec = |delta: u64| (delta as f64 / elapsed).round() as u64;
+
+    let (cpu_percent, iowait_percent) = cpu_usage(&before.cpu, &after.cpu);
+    let mut rates = Rates {
+        cpu_percent,
+        iowait_percent,
+        ..Rates::default()
+    };
+
+    let (mut rx_total, mut tx_total) = (0, 0);
+    for iface in &after.net {
+        let Some(prev) = before.net.iter().find(|i| i.name == iface.name) else {
+            continue;
+        };
+        let rx = counter_delta(prev.rx_bytes, iface.rx_bytes);
+        let tx = counter_delta(prev.tx_bytes, iface.tx_bytes);
+        rx_total += rx;
+        tx_total += tx;
+        for (i, total) in rates.net_faults.iter_mut().enumerate() {
+            *total += counter_delta(prev.faults[i], iface.faults[i]);
+        }
+        rates.net_interfaces.push(NetInterface {
+            name: iface.name.clone(),
+            rx_bytes_sec: per_sec(rx),
+            tx_bytes_sec: per_sec(tx),
+        });
+    }
+    rates.net_rx_bytes_sec = per_sec(rx_total);
+    rates.net_tx_bytes_sec = per_sec(tx_total);
+
+    let (mut read_sectors, mut write_sectors) = (0, 0);
+    for (name, read, written) in &after.disks {
+        let Some((_, prev_read, prev_written)) = before.disks.iter().find(|(n, _, _)| n == name)
+        else {
+            continue;
+        };
+        read_sectors += counter_delta(*prev_read, *read);
+        write_sectors += counter_delta(*prev_written, *written);
+    }
+    // Each sector is 512 bytes
+    rates.disk_read_bytes_sec = per_sec(read_sectors * 512);
+    rates.disk_write_bytes_sec = per_sec(write_sectors * 512);
+
+    rates
+}
+
+/// Increase of a kernel counter between two samples. A counter that went
+/// down is assumed to have wrapped at 32 bits, as `unsigned long` counters do
+/// on 32-bit kernels; a larger one can only have been reset (e.g. by the
+/// interface being recreated), which counts as no activity.
+fn counter_delta(before: u64, after: u64) -> u64 {
+    if after >= before {
+        after - before
+    } else if before <= u32::MAX as u64 {
+        after + (u32::MAX as u64 - before) + 1
+    } else {
+        0
+    }
 }
 
 /// Split output by ===SECTION markers into a map of section_name -> content.
@@ -156,12 +555,9 @@ fn line_offsets(s: &str) -> Vec<(usize, &str)> {
     result
 }
 
-/// Parse CPU delta from two /proc/stat "cpu" lines.
+/// CPU usage between two samples of the /proc/stat "cpu" line fields.
 /// Returns (cpu_percent, iowait_percent).
-fn parse_cpu_delta(line1: &str, line2: &str) -> Result<(f64, f64)> {
-    let v1 = parse_cpu_line(line1)?;
-    let v2 = parse_cpu_line(line2)?;
-
+fn cpu_usage(v1: &[u64], v2: &[u64]) -> (f64, f64) {
     let idle1 = v1.get(3).copied().unwrap_or(0);
     let idle2 = v2.get(3).copied().unwrap_or(0);
     let iowait1 = v1.get(4).copied().unwrap_or(0);
@@ -175,13 +571,13 @@ fn parse_cpu_delta(line1: &str, line2: &str) -> Result<(f64, f64)> {
     let iowait_delta = iowait2.saturating_sub(iowait1) as f64;
 
     if total_delta == 0.0 {
-        return Ok((0.0, 0.0));
+        return (0.0, 0.0);
     }
 
     let cpu_pct = ((total_delta - idle_delta) / total_delta * 100.0).clamp(0.0, 100.0);
     let iowait_pct = (iowait_delta / total_delta * 100.0).clamp(0.0, 100.0);
 
-    Ok((cpu_pct, iowait_pct))
+    (cpu_pct, iowait_pct)
 }
 
 fn parse_cpu_line(line: &str) -> Result<Vec<u64>> {
@@ -252,27 +648,46 @@ fn parse_swap(lines: &[&str]) -> Result<(f64, f64)> {
 }
 
 fn extract_meminfo_value(line: &str, prefix: &str) -> Option<u64> {
-    if line.starts_with(prefix) {
-        line[prefix.len()..]
-            .split_whitespace()
-            .next()
-            .and_then(|v| v.parse().ok())
-    } else {
-        None
-    }
+    line.strip_prefix(prefix)?
+        .split_whitespace()
+        .next()
+        .and_then(|v| v.parse().ok())
 }
 
+/// Usage percentage from the last line of `df` output: the first field
+/// ending in `%`, wherever a wrapped or differently laid out line puts it.
 fn parse_df(line: &str) -> Result<f64> {
-    let parts: Vec<&str> = line.split_whitespace().collect();
-    if parts.len() < 5 {
-        anyhow::bail!("Unexpected df output: {line}");
-    }
-    let pct_str = parts[4].trim_end_matches('%');
+    let pct_str = line
+        .split_whitespace()
+        .find_map(|field| field.strip_suffix('%'))
+        .with_context(|| format!("Unexpected df output: {line}"))?;
     pct_str
         .parse::<f64>()
         .context("Failed to parse disk percentage")
 }
 
+/// Pseudo filesystems whose inode counts are meaningless for monitoring.
+const PSEUDO_FILESYSTEMS: &[&str] = &["tmpfs", "devtmpfs", "udev", "overlay", "shm", "none"];
+
+/// Parse `df -Pi` output into per-mount inode usage. Filesystems that don't
+/// track inodes (reported as `-`) and pseudo filesystems are skipped.
+fn parse_df_inodes(content: &str) -> Vec<InodeMount> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 6 || PSEUDO_FILESYSTEMS.contains(&parts[0]) {
+                return None;
+            }
+            let used_pct = parts[4].trim_end_matches('%').parse::<f64>().ok()?;
+            Some(InodeMount {
+                mount: parts[5..].join(" "),
+                used_pct,
+            })
+        })
+        .collect()
+}
+
 fn parse_loadavg(line: &str) -> Result<(f64, f64, f64)> {
     let parts: Vec<&str> = line.split_whitespace().collect();
     if parts.len() < 3 {
@@ -301,20 +716,96 @@ fn parse_procs(line: &str) -> Result<(u32, u32)> {
     ))
 }
 
-fn parse_uptime(line: &str) -> Result<u64> {
+/// Count zombie and D-state processes from `State:` lines of /proc/*/status.
+/// Returns (zombie, dstate).
+fn parse_proc_states(content: &str) -> (u32, u32) {
+    let mut zombie = 0;
+    let mut dstate = 0;
+    for line in content.lines() {
+        match line
+            .strip_prefix("State:")
+            .and_then(|rest| rest.trim_start().chars().next())
+        {
+            Some('Z') => zombie += 1,
+            Some('D') => dstate += 1,
+            _ => {}
+        }
+    }
+    (zombie, dstate)
+}
+
+/// Parse /proc/sys/fs/file-nr ("allocated free max") into (allocated, max).
+fn parse_file_nr(content: &str) -> Option<(u64, u64)> {
+    let parts: Vec<u64> = content
+        .split_whitespace()
+        .filter_map(|p| p.parse().ok())
+        .collect();
+    match parts.as_slice() {
+        [allocated, _free, max] => Some((*allocated, *max)),
+        _ => None,
+    }
+}
+
+/// Parse /sys/class/thermal zone readings (millidegrees Celsius) into °C.
+fn parse_thermals(content: &str) -> Vec<f64> {
+    content
+        .lines()
+        .filter_map(|l| l.trim().parse::<i64>().ok())
+        .map(|milli| milli as f64 / 1000.0)
+        .collect()
+}
+
+/// Parse the `ID=` and `VERSION_ID=` lines of /etc/os-release into e.g.
+/// "ubuntu 22.04". Values may be quoted.
+fn parse_os_release(content: &str) -> Option<String> {
+    let mut id = None;
+    let mut version = None;
+    for line in content.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let value = value.trim_matches(|c| c == '"' || c == '\'').to_string();
+        match key {
+            "ID" => id = Some(value),
+            "VERSION_ID" => version = Some(value),
+            _ => {}
+        }
+    }
+    match (id, version) {
+        (Some(id), Some(version)) => Some(format!("{id} {version}")),
+        (Some(id), None) => Some(id),
+        _ => None,
+    }
+}
+
+/// Seconds since boot, from the first field of /proc/uptime.
+fn parse_uptime(line: &str) -> Result<f64> {
     let parts: Vec<&str> = line.split_whitespace().collect();
     if parts.is_empty() {
         anyhow::bail!("Empty uptime");
     }
-    let secs_f: f64 = parts[0].parse().context("uptime seconds")?;
-    Ok(secs_f as u64)
+    parts[0].parse().context("uptime seconds")
 }
 
-/// Parse /proc/net/dev and sum RX bytes (col 1) and TX bytes (col 9) across
-/// all non-lo interfaces.
-fn parse_net_dev(content: &str) -> (u64, u64) {
-    let mut rx_total: u64 = 0;
-    let mut tx_total: u64 = 0;
+/// Parse /proc/net/dev into per-interface counters for all non-lo
+/// interfaces: RX bytes (col 0), TX bytes (col 8), and RX errors (col 2),
+/// RX drops (col 3), TX errors (col 10) and TX drops (col 11).
+fn parse_net_counters(content: &str) -> Vec<IfaceCounters> {
+    net_dev_rows(content)
+        .into_iter()
+        .filter(|(_, vals)| vals.len() >= 12)
+        .map(|(name, vals)| IfaceCounters {
+            name,
+            rx_bytes: vals[0],
+            tx_bytes: vals[8],
+            faults: [vals[2], vals[3], vals[10], vals[11]],
+        })
+        .collect()
+}
+
+/// Split /proc/net/dev into (interface, counters) rows, skipping headers and lo.
+fn net_dev_rows(content: &str) -> Vec<(String, Vec<u64>)> {
+    let mut rows = Vec::new();
 
     for line in content.lines() {
         let line = line.trim();
@@ -332,29 +823,17 @@ fn parse_net_dev(content: &str) -> (u64, u64) {
                 .split_whitespace()
                 .filter_map(|v| v.parse().ok())
                 .collect();
-            // col 0 = rx_bytes, col 8 = tx_bytes
-            if vals.len() >= 9 {
-                rx_total += vals[0];
-                tx_total += vals[8];
-            }
+            rows.push((iface.to_string(), vals));
         }
     }
 
-    (rx_total, tx_total)
-}
-
-/// Compute net bytes/sec from two /proc/net/dev samples taken 1s apart.
-fn parse_net_delta(content1: &str, content2: &str) -> Result<(u64, u64)> {
-    let (rx1, tx1) = parse_net_dev(content1);
-    let (rx2, tx2) = parse_net_dev(content2);
-    Ok((rx2.saturating_sub(rx1), tx2.saturating_sub(tx1)))
+    rows
 }
 
-/// Parse /proc/diskstats and sum sectors read/written for real block devices.
-/// Returns (sectors_read, sectors_written).
-fn parse_diskstats(content: &str) -> (u64, u64) {
-    let mut reads: u64 = 0;
-    let mut writes: u64 = 0;
+/// Parse /proc/diskstats into sectors read/written per real block device.
+/// Returns (device, sectors_read, sectors_written) rows.
+fn parse_diskstats(content: &str) -> Vec<(String, u64, u64)> {
+    let mut disks = Vec::new();
 
     for line in content.lines() {
         let parts: Vec<&str> = line.split_whitespace().collect();
@@ -379,11 +858,10 @@ fn parse_diskstats(content: &str) -> (u64, u64) {
         // Field 5 (index 5) = sectors read, field 9 (index 9) = sectors written
         let sr: u64 = parts[5].parse().unwrap_or(0);
         let sw: u64 = parts[9].parse().unwrap_or(0);
-        reads += sr;
-        writes += sw;
+        disks.push((dev_name.to_string(), sr, sw));
     }
 
-    (reads, writes)
+    disks
 }
 
 /// Heuristic to detect partition names (e.g. sda1, nvme0n1p1).
@@ -420,29 +898,593 @@ fn is_partition(name: &str) -> bool {
     false
 }
 
-/// Compute disk I/O bytes/sec from two /proc/diskstats samples taken 1s apart.
-fn parse_diskstats_delta(content1: &str, content2: &str) -> Result<(u64, u64)> {
-    let (sr1, sw1) = parse_diskstats(content1);
-    let (sr2, sw2) = parse_diskstats(content2);
-    // Each sector is 512 bytes
-    let read_bytes_sec = sr2.saturating_sub(sr1) * 512;
-    let write_bytes_sec = sw2.saturating_sub(sw1) * 512;
-    Ok((read_bytes_sec, write_bytes_sec))
-}
-
 /// Parse TCP connections from /proc/net/sockstat.
 /// Looks for line: "TCP: inuse N ..."
 fn parse_tcp_conns(content: &str) -> Option<u32> {
+    parse_sockstat_inuse(content, "TCP")
+}
+
+/// Parse the `inuse` count of one protocol line in /proc/net/sockstat,
+/// e.g. "TCP: inuse N orphan N tw N alloc N mem N" or "UDP: inuse N mem N".
+fn parse_sockstat_inuse(content: &str, proto: &str) -> Option<u32> {
     for line in content.lines() {
-        if line.starts_with("TCP:") {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() >= 3 && parts[0].strip_suffix(':') == Some(proto) && parts[1] == "inuse" {
+            return parts[2].parse().ok();
+        }
+    }
+    None
+}
+
+/// Parse `uniq -c` counts of the hex `st` column from /proc/net/tcp{,6}.
+/// Returns (established, time_wait, close_wait).
+fn parse_tcp_states(content: &str) -> (u32, u32, u32) {
+    let mut established = 0;
+    let mut time_wait = 0;
+    let mut close_wait = 0;
+
+    for line in content.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() < 2 {
+            continue;
+        }
+        let Ok(count) = parts[0].parse::<u32>() else {
+            continue;
+        };
+        // States from include/net/tcp_states.h
+        match parts[1] {
+            "01" => established += count,
+            "06" => time_wait += count,
+            "08" => close_wait += count,
+            _ => {}
+        }
+    }
+
+    (established, time_wait, close_wait)
+}
+
+/// Parse the output of the BSD half of the metrics script. Network rates
+/// come from `netstat -ib` counters against `previous`; CPU usage is
+/// iostat's own one-second sample, and disk I/O isn't collected.
+fn parse_bsd_output(
+    sections: &std::collections::HashMap<&str, &str>,
+    os_type: Option<String>,
+    previous: Option<&CounterSample>,
+) -> Result<(Metrics, CounterSample)> {
+    let section = |name: &str| sections.get(name).copied().unwrap_or("");
+    let sysctl = parse_sysctl(section("SYSCTL"));
+    let number = |name: &str| sysctl.get(name).and_then(|v| v.parse::<u64>().ok());
+
+    // Memory: vm_stat's page counts on macOS, sysctl's elsewhere
+    let mem_total = number("hw.memsize")
+        .or_else(|| number("hw.physmem"))
+        .filter(|n| *n > 0)
+        .context("Failed to parse memory")?;
+    let mem_available = match section("VMSTAT") {
+        "" => number("hw.pagesize").zip(number("vm.stats.vm.v_free_count")).map(
+            |(page, free)| (free + number("vm.stats.vm.v_inactive_count").unwrap_or(0)) * page,
+        ),
+        vm_stat => parse_vm_stat(vm_stat),
+    }
+    .context("Failed to parse memory")?;
+    let gb = |bytes: u64| bytes as f64 / 1_073_741_824.0;
+    let (swap_used_gb, swap_total_gb) = sysctl
+        .get("vm.swapusage")
+        .and_then(|s| parse_swapusage(s))
+        .or_else(|| parse_swapinfo(section("SWAPINFO")))
+        .unwrap_or((0.0, 0.0));
+
+    let df_line = section("DF").lines().next().unwrap_or("");
+    let disk_percent = parse_df(df_line).context("Failed to parse disk")?;
+
+    let loadavg = sysctl.get("vm.loadavg").copied().unwrap_or("");
+    let (load_1, load_5, load_15) =
+        parse_loadavg(loadavg.trim_matches(|c| c == '{' || c == '}' || c == ' '))
+            .context("Failed to parse load")?;
+
+    // Uptime from the boot time, to the second
+    let uptime = sysctl
+        .get("kern.boottime")
+        .and_then(|s| parse_boottime(s))
+        .zip(section("NOW").trim().parse::<u64>().ok())
+        .map(|(boot, now)| now.saturating_sub(boot))
+        .context("Failed to parse uptime")?;
+
+    let sample = CounterSample {
+        uptime: uptime as f64,
+        cpu: Vec::new(),
+        net: parse_netstat_ib(section("NETSTAT")),
+        disks: Vec::new(),
+    };
+    let rates = match previous {
+        Some(before) => compute_rates(before, &sample),
+        None => Rates::default(),
+    };
+
+    let (tcp_conns, tcp_established, tcp_time_wait, tcp_close_wait) =
+        parse_bsd_tcp_states(section("TCPSTATES"));
+    let (procs_running, procs_total, procs_zombie, procs_dstate) =
+        parse_ps_states(section("PSSTATES"));
+
+    let fd_allocated = number("kern.num_files")
+        .or_else(|| number("kern.openfiles"))
+        .unwrap_or(0);
+    let fd_max = number("kern.maxfiles").unwrap_or(0);
+    let fd_used_pct = if fd_max > 0 {
+        fd_allocated as f64 / fd_max as f64 * 100.0
+    } else {
+        0.0
+    };
+
+    let [net_rx_errors, net_rx_drops, net_tx_errors, net_tx_drops] = rates.net_faults;
+    let metrics = Metrics {
+        cpu_percent: parse_iostat_cpu(section("IOSTAT")).unwrap_or(0.0),
+        mem_used_gb: gb(mem_total.saturating_sub(mem_available)),
+        mem_total_gb: gb(mem_total),
+        disk_percent,
+        load_1,
+        load_5,
+        load_15,
+        uptime_secs: uptime,
+        num_cpus: number("hw.ncpu").filter(|n| *n > 0).map_or(1, |n| n as u32),
+        swap_used_gb,
+        swap_total_gb,
+        net_rx_bytes_sec: rates.net_rx_bytes_sec,
+        net_tx_bytes_sec: rates.net_tx_bytes_sec,
+        net_interfaces: rates.net_interfaces,
+        net_rx_errors,
+        net_rx_drops,
+        net_tx_errors,
+        net_tx_drops,
+        tcp_conns,
+        tcp_established,
+        tcp_time_wait,
+        tcp_close_wait,
+        procs_running,
+        procs_total,
+        procs_zombie,
+        procs_dstate,
+        kernel_version: Some(section("UNAME").trim().to_string()).filter(|s| !s.is_empty()),
+        os_type,
+        os_info: parse_os_release(section("OSRELEASE")),
+        fd_allocated,
+        fd_max,
+        fd_used_pct,
+        // There is no sub-second clock to time the command with, but
+        // iostat's one-second sample is nearly all of its runtime
+        command_runtime_ms: (!section("IOSTAT").is_empty()).then_some(1000),
+        custom: sections
+            .iter()
+            .filter_map(|(key, value)| {
+                key.strip_prefix("CUSTOM_")
+                    .map(|name| (name.to_string(), value.to_string()))
+            })
+            .collect(),
+        become_error: Some(section("BECOME").trim().to_string()).filter(|s| !s.is_empty()),
+        ..Metrics::default()
+    };
+    Ok((metrics, sample))
+}
+
+/// Parse `sysctl name...` output ("name: value" lines) into a map.
+fn parse_sysctl(content: &str) -> std::collections::HashMap<&str, &str> {
+    content
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim(), value.trim()))
+        .collect()
+}
+
+/// Available memory in bytes from macOS `vm_stat`: free, inactive and
+/// speculative pages, at the page size given in its header.
+fn parse_vm_stat(content: &str) -> Option<u64> {
+    let page_size = content
+        .lines()
+        .next()?
+        .split("page size of ")
+        .nth(1)?
+        .split_whitespace()
+        .next()?
+        .parse::<u64>()
+        .ok()?;
+    let pages = |name: &str| {
+        content
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|rest| rest.trim().trim_end_matches('.').parse::<u64>().ok())
+            .unwrap_or(0)
+    };
+    let free = pages("Pages free:") + pages("Pages inactive:") + pages("Pages speculative:");
+    Some(free * page_size)
+}
+
+/// Parse macOS `vm.swapusage` ("total = 2048.00M  used = 1024.50M ...")
+/// into (used, total) in GB.
+fn parse_swapusage(value: &str) -> Option<(f64, f64)> {
+    let size = |name: &str| {
+        let rest = value.split(&format!("{name} = ")).nth(1)?;
+        let field = rest.split_whitespace().next()?;
+        let (number, unit) = field.split_at(field.len().checked_sub(1)?);
+        let number: f64 = number.parse().ok()?;
+        Some(match unit {
+            "K" => number / 1_048_576.0,
+            "M" => number / 1024.0,
+            "G" => number,
+            _ => return None,
+        })
+    };
+    Some((size("used")?, size("total")?))
+}
+
+/// Parse FreeBSD `swapinfo -k` into (used, total) in GB, summed over its
+/// devices. None without swap devices.
+fn parse_swapinfo(content: &str) -> Option<(f64, f64)> {
+    let devices: Vec<(u64, u64)> = content
+        .lines()
+        .filter(|line| line.starts_with('/'))
+        .filter_map(|line| {
             let parts: Vec<&str> = line.split_whitespace().collect();
-            // "TCP: inuse N orphan N tw N alloc N mem N"
-            if parts.len() >= 3 && parts[1] == "inuse" {
-                return parts[2].parse().ok();
+            Some((parts.get(1)?.parse().ok()?, parts.get(2)?.parse().ok()?))
+        })
+        .collect();
+    if devices.is_empty() {
+        return None;
+    }
+    let (total_kb, used_kb) = devices
+        .iter()
+        .fold((0, 0), |(total, used), (t, u)| (total + t, used + u));
+    Some((used_kb as f64 / 1_048_576.0, total_kb as f64 / 1_048_576.0))
+}
+
+/// Boot time in seconds since the epoch from `kern.boottime`
+/// ("{ sec = 1712345678, usec = 123456 } Thu Apr  4 ...").
+fn parse_boottime(value: &str) -> Option<u64> {
+    value
+        .split("sec = ")
+        .nth(1)?
+        .split(|c: char| !c.is_ascii_digit())
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// CPU usage from `iostat -c 2 -w 1`: 100 minus the idle column of the last
+/// report, the first being the average since boot.
+fn parse_iostat_cpu(content: &str) -> Option<f64> {
+    let header: Vec<&str> = content
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .find(|fields| fields.contains(&"us") && fields.contains(&"id"))?;
+    let idle_column = header.iter().position(|f| *f == "id")?;
+    let last: Vec<&str> = content.lines().last()?.split_whitespace().collect();
+    if last.len() != header.len() {
+        return None;
+    }
+    let idle: f64 = last[idle_column].parse().ok()?;
+    Some((100.0 - idle).clamp(0.0, 100.0))
+}
+
+/// Parse `netstat -ib` into per-interface counters for all non-loopback
+/// interfaces, from their `<Link#n>` rows. Columns are found by their
+/// header, as they differ between systems; an interface without a hardware
+/// address leaves the Address column empty.
+fn parse_netstat_ib(content: &str) -> Vec<IfaceCounters> {
+    let mut lines = content.lines();
+    let Some(header) = lines.next() else {
+        return Vec::new();
+    };
+    let header: Vec<&str> = header.split_whitespace().collect();
+    let column = |name: &str| header.iter().position(|h| *h == name);
+    let address = column("Address");
+
+    lines
+        .filter_map(|line| {
+            let mut fields: Vec<&str> = line.split_whitespace().collect();
+            if !fields.get(2)?.starts_with("<Link") {
+                return None;
+            }
+            if fields.len() + 1 == header.len() {
+                fields.insert(address?, "");
+            }
+            let name = fields[0].trim_end_matches('*');
+            if name.starts_with("lo") {
+                return None;
             }
+            let counter = |name: &str| {
+                column(name)
+                    .and_then(|i| fields.get(i))
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .unwrap_or(0)
+            };
+            Some(IfaceCounters {
+                name: name.to_string(),
+                rx_bytes: counter("Ibytes"),
+                tx_bytes: counter("Obytes"),
+                faults: [counter("Ierrs"), counter("Idrop"), counter("Oerrs"), counter("Odrop")],
+            })
+        })
+        .collect()
+}
+
+/// Lines of `sort | uniq -c` output as (count, value).
+fn uniq_counts(content: &str) -> impl Iterator<Item = (u32, &str)> {
+    content.lines().filter_map(|line| {
+        let (count, value) = line.trim().split_once(char::is_whitespace)?;
+        Some((count.parse().ok()?, value.trim()))
+    })
+}
+
+/// Count TCP connections by state from BSD `netstat -an` states. Returns
+/// (total, established, time_wait, close_wait).
+fn parse_bsd_tcp_states(content: &str) -> (u32, u32, u32, u32) {
+    let mut counts = (0, 0, 0, 0);
+    for (count, state) in uniq_counts(content) {
+        counts.0 += count;
+        match state {
+            "ESTABLISHED" => counts.1 += count,
+            "TIME_WAIT" => counts.2 += count,
+            "CLOSE_WAIT" => counts.3 += count,
+            _ => {}
         }
     }
-    None
+    counts
+}
+
+/// Count processes by the first letter of their `ps` state. Returns
+/// (running, total, zombie, uninterruptible); macOS marks the last U, the
+/// BSDs D.
+fn parse_ps_states(content: &str) -> (u32, u32, u32, u32) {
+    let mut counts = (0, 0, 0, 0);
+    for (count, state) in uniq_counts(content) {
+        counts.1 += count;
+        match state {
+            "R" => counts.0 += count,
+            "Z" => counts.2 += count,
+            "D" | "U" => counts.3 += count,
+            _ => {}
+        }
+    }
+    counts
+}
+
+/// Fields of /proc/stat's `cpu` line, in order, as node_exporter names the
+/// modes of `node_cpu_seconds_total`.
+const CPU_MODES: [&str; 8] = ["user", "nice", "system", "idle", "iowait", "irq", "softirq", "steal"];
+
+/// One sample of Prometheus' text format.
+struct PromSample<'a> {
+    name: &'a str,
+    labels: Vec<(&'a str, String)>,
+    value: f64,
+}
+
+impl PromSample<'_> {
+    fn label(&self, key: &str) -> Option<&str> {
+        self.labels.iter().find(|(k, _)| *k == key).map(|(_, v)| v.as_str())
+    }
+}
+
+/// Parse one line of Prometheus' text format. Comments, blank lines and
+/// malformed samples give None; a trailing timestamp is ignored.
+fn parse_prom_line(line: &str) -> Option<PromSample<'_>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let name_end = line.find(|c: char| c == '{' || c.is_whitespace())?;
+    let name = &line[..name_end];
+    let mut rest = &line[name_end..];
+    let mut labels = Vec::new();
+    if let Some(mut body) = rest.strip_prefix('{') {
+        loop {
+            body = body.trim_start_matches([',', ' ']);
+            if let Some(after) = body.strip_prefix('}') {
+                rest = after;
+                break;
+            }
+            let (key, after) = body.split_once("=\"")?;
+            let mut value = String::new();
+            let mut chars = after.char_indices();
+            let end = loop {
+                match chars.next()? {
+                    (i, '"') => break i,
+                    (_, '\\') => match chars.next()?.1 {
+                        'n' => value.push('\n'),
+                        c => value.push(c),
+                    },
+                    (_, c) => value.push(c),
+                }
+            };
+            labels.push((key.trim(), value));
+            body = &after[end + 1..];
+        }
+    }
+    let value = rest.split_whitespace().next()?.parse().ok()?;
+    Some(PromSample { name, labels, value })
+}
+
+/// Parse node_exporter's metrics (e.g. from `curl host:9100/metrics`), for
+/// hosts collected with a local command. Memory, the filesystem mounted at
+/// `disk_path`, load and uptime are required, as they are from the metrics
+/// script; anything else the exporter doesn't report is left at zero.
+pub fn parse_prometheus_output(
+    output: &str,
+    disk_path: &str,
+    previous: Option<&CounterSample>,
+) -> Result<(Metrics, CounterSample)> {
+    let samples: Vec<PromSample> = output.lines().filter_map(parse_prom_line).collect();
+    let named = |name: &'static str| samples.iter().filter(move |s| s.name == name);
+    let value = |name: &'static str| named(name).next().map(|s| s.value);
+    let count = |name: &'static str| value(name).map_or(0, |v| v as u32);
+    let for_label = |name: &'static str, key: &str, wanted: &str| {
+        named(name).find(|s| s.label(key) == Some(wanted)).map(|s| s.value)
+    };
+
+    // CPU: seconds per mode summed over CPUs, in /proc/stat's centiseconds
+    let mut cpu = vec![0; CPU_MODES.len()];
+    let mut cpus = Vec::new();
+    for s in named("node_cpu_seconds_total") {
+        if let Some(i) = s.label("mode").and_then(|m| CPU_MODES.iter().position(|c| *c == m)) {
+            cpu[i] += (s.value * 100.0) as u64;
+        }
+        if let Some(id) = s.label("cpu").filter(|id| !cpus.contains(id)) {
+            cpus.push(id);
+        }
+    }
+
+    let gb = |bytes: f64| bytes / 1_073_741_824.0;
+    let mem_total = value("node_memory_MemTotal_bytes")
+        .filter(|t| *t > 0.0)
+        .context("Failed to parse memory")?;
+    let mem_available = value("node_memory_MemAvailable_bytes").unwrap_or_else(|| {
+        ["node_memory_MemFree_bytes", "node_memory_Buffers_bytes", "node_memory_Cached_bytes"]
+            .into_iter()
+            .filter_map(value)
+            .sum()
+    });
+    let swap_total = value("node_memory_SwapTotal_bytes").unwrap_or(0.0);
+    let swap_free = value("node_memory_SwapFree_bytes").unwrap_or(0.0);
+
+    // Disk usage as df reports it: used over what non-root users can use
+    let mount = |name| for_label(name, "mountpoint", disk_path);
+    let disk_percent = mount("node_filesystem_size_bytes")
+        .zip(mount("node_filesystem_free_bytes"))
+        .zip(mount("node_filesystem_avail_bytes"))
+        .map(|((size, free), avail)| {
+            let used = size - free;
+            if used + avail > 0.0 {
+                used / (used + avail) * 100.0
+            } else {
+                0.0
+            }
+        })
+        .with_context(|| format!("Failed to parse disk: no filesystem mounted at {disk_path}"))?;
+    let inode_mounts = named("node_filesystem_files")
+        .filter(|s| s.value > 0.0)
+        .filter_map(|s| {
+            let mountpoint = s.label("mountpoint")?;
+            let free = for_label("node_filesystem_files_free", "mountpoint", mountpoint)?;
+            Some(InodeMount {
+                mount: mountpoint.to_string(),
+                used_pct: (s.value - free) / s.value * 100.0,
+            })
+        })
+        .collect();
+
+    let load = value("node_load1")
+        .zip(value("node_load5"))
+        .zip(value("node_load15"))
+        .context("Failed to parse load")?;
+    let uptime = value("node_time_seconds")
+        .zip(value("node_boot_time_seconds"))
+        .map(|(now, boot)| (now - boot).max(0.0))
+        .context("Failed to parse uptime")?;
+
+    // Interfaces in the order the exporter lists them, skipping lo as
+    // /proc/net/dev parsing does
+    let device_counter = |name: &'static str, device: &str| {
+        for_label(name, "device", device).map_or(0, |v| v as u64)
+    };
+    let net = named("node_network_receive_bytes_total")
+        .filter_map(|s| s.label("device"))
+        .filter(|device| *device != "lo")
+        .map(|device| IfaceCounters {
+            name: device.to_string(),
+            rx_bytes: device_counter("node_network_receive_bytes_total", device),
+            tx_bytes: device_counter("node_network_transmit_bytes_total", device),
+            faults: [
+                device_counter("node_network_receive_errs_total", device),
+                device_counter("node_network_receive_drop_total", device),
+                device_counter("node_network_transmit_errs_total", device),
+                device_counter("node_network_transmit_drop_total", device),
+            ],
+        })
+        .collect();
+    // node_exporter leaves partitions out by default; counters are in
+    // bytes, the sample in 512-byte sectors
+    let disks = named("node_disk_read_bytes_total")
+        .filter_map(|s| s.label("device"))
+        .map(|device| {
+            (
+                device.to_string(),
+                device_counter("node_disk_read_bytes_total", device) / 512,
+                device_counter("node_disk_written_bytes_total", device) / 512,
+            )
+        })
+        .collect();
+
+    let sample = CounterSample { uptime, cpu, net, disks };
+    let rates = match previous {
+        Some(before) => compute_rates(before, &sample),
+        None => Rates::default(),
+    };
+
+    let temps_celsius: Vec<f64> = named("node_hwmon_temp_celsius").map(|s| s.value).collect();
+    let max_temp_celsius = temps_celsius.iter().copied().reduce(f64::max);
+    let fd_allocated = value("node_filefd_allocated").map_or(0, |v| v as u64);
+    let fd_max = value("node_filefd_maximum").map_or(0, |v| v as u64);
+    let fd_used_pct = if fd_max > 0 {
+        fd_allocated as f64 / fd_max as f64 * 100.0
+    } else {
+        0.0
+    };
+    let uname = named("node_uname_info").next();
+    let os_info = named("node_os_info").next().and_then(|s| {
+        match (s.label("id"), s.label("version_id")) {
+            (Some(id), Some(version)) => Some(format!("{id} {version}")),
+            (Some(id), None) => Some(id.to_string()),
+            _ => None,
+        }
+    });
+
+    let [net_rx_errors, net_rx_drops, net_tx_errors, net_tx_drops] = rates.net_faults;
+    let ((load_1, load_5), load_15) = load;
+    let metrics = Metrics {
+        cpu_percent: rates.cpu_percent,
+        iowait_percent: rates.iowait_percent,
+        mem_used_gb: gb(mem_total - mem_available),
+        mem_total_gb: gb(mem_total),
+        disk_percent,
+        load_1,
+        load_5,
+        load_15,
+        uptime_secs: uptime as u64,
+        num_cpus: (cpus.len() as u32).max(1),
+        swap_used_gb: gb(swap_total - swap_free),
+        swap_total_gb: gb(swap_total),
+        net_rx_bytes_sec: rates.net_rx_bytes_sec,
+        net_tx_bytes_sec: rates.net_tx_bytes_sec,
+        net_interfaces: rates.net_interfaces,
+        net_rx_errors,
+        net_rx_drops,
+        net_tx_errors,
+        net_tx_drops,
+        tcp_conns: count("node_sockstat_TCP_inuse"),
+        udp_conns: count("node_sockstat_UDP_inuse"),
+        raw_sockets: count("node_sockstat_RAW_inuse"),
+        frag_inuse: count("node_sockstat_FRAG_inuse"),
+        tcp_established: count("node_netstat_Tcp_CurrEstab"),
+        tcp_time_wait: count("node_sockstat_TCP_tw"),
+        procs_running: count("node_procs_running"),
+        procs_dstate: count("node_procs_blocked"),
+        disk_read_bytes_sec: rates.disk_read_bytes_sec,
+        disk_write_bytes_sec: rates.disk_write_bytes_sec,
+        inode_mounts,
+        systemd_failed_units: named("node_systemd_unit_state")
+            .filter(|s| s.label("state") == Some("failed") && s.value > 0.0)
+            .count() as u32,
+        kernel_version: uname.and_then(|s| s.label("release")).map(str::to_string),
+        os_type: uname.and_then(|s| s.label("sysname")).map(str::to_string),
+        os_info,
+        temps_celsius,
+        max_temp_celsius,
+        fd_allocated,
+        fd_max,
+        fd_used_pct,
+        entropy_avail: value("node_entropy_available_bits").map(|v| v as u32),
+        ..Metrics::default()
+    };
+    Ok((metrics, sample))
 }
 
 #[cfg(test)]
@@ -461,9 +1503,9 @@ mod tests {
 
     #[test]
     fn test_parse_cpu_delta_with_iowait() {
-        let line1 = "cpu  1000 200 300 5000 100 0 0 0 0 0";
-        let line2 = "cpu  1100 220 320 5050 120 0 0 0 0 0";
-        let (cpu, iow) = parse_cpu_delta(line1, line2).unwrap();
+        let line1 = parse_cpu_line("cpu  1000 200 300 5000 100 0 0 0 0 0").unwrap();
+        let line2 = parse_cpu_line("cpu  1100 220 320 5050 120 0 0 0 0 0").unwrap();
+        let (cpu, iow) = cpu_usage(&line1, &line2);
         assert!(cpu > 0.0);
         assert!(iow >= 0.0);
     }
@@ -484,9 +1526,81 @@ mod tests {
     #[test]
     fn test_parse_net_dev() {
         let content = "Inter-|   Receive    |  Transmit\n face |bytes    packets  errs drop fifo frame compressed multicast|bytes packets errs drop fifo colls carrier compressed\n    lo: 1000 10 0 0 0 0 0 0 1000 10 0 0 0 0 0 0\n  eth0: 5000 50 0 0 0 0 0 0 3000 30 0 0 0 0 0 0\n";
-        let (rx, tx) = parse_net_dev(content);
-        assert_eq!(rx, 5000); // eth0 only, lo excluded
-        assert_eq!(tx, 3000);
+        let ifaces = parse_net_counters(content);
+        assert_eq!(ifaces.len(), 1); // eth0 only, lo excluded
+        assert_eq!(ifaces[0].rx_bytes, 5000);
+        assert_eq!(ifaces[0].tx_bytes, 3000);
+    }
+
+    /// A sample at `uptime` with the given cpu line and /proc/net/dev rows.
+    fn sample(uptime: f64, cpu: &str, netdev: &str) -> CounterSample {
+        CounterSample {
+            uptime,
+            cpu: parse_cpu_line(cpu).unwrap(),
+            net: parse_net_counters(netdev),
+            disks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_net_iface_delta() {
+        let s1 = "  eth0: 5000 50 0 0 0 0 0 0 3000 30 0 0 0 0 0 0\n bond0: 100 1 0 0 0 0 0 0 100 1 0 0 0 0 0 0\n";
+        let s2 = "  eth0: 5500 55 0 0 0 0 0 0 3100 31 0 0 0 0 0 0\n bond0: 9100 91 0 0 0 0 0 0 100 1 0 0 0 0 0 0\n  tun0: 10 1 0 0 0 0 0 0 10 1 0 0 0 0 0 0\n";
+        let rates = compute_rates(&sample(10.0, "cpu 0", s1), &sample(11.0, "cpu 0", s2));
+        let ifaces = rates.net_interfaces;
+        assert_eq!(ifaces.len(), 2);
+        assert_eq!(ifaces[0].name, "eth0");
+        assert_eq!((ifaces[0].rx_bytes_sec, ifaces[0].tx_bytes_sec), (500, 100));
+        assert_eq!(ifaces[1].name, "bond0");
+        assert_eq!(ifaces[1].rx_bytes_sec, 9000);
+    }
+
+    #[test]
+    fn test_rates_across_polls() {
+        let before = sample(
+            1000.0,
+            "cpu  1000 0 0 9000 0 0 0 0 0 0",
+            "  eth0: 1000 10 0 0 0 0 0 0 2000 20 0 0 0 0 0 0\n",
+        );
+        // 30s later, a quarter of the CPU time was busy
+        let after = sample(
+            1030.0,
+            "cpu  1300 0 0 9900 0 0 0 0 0 0",
+            "  eth0: 31000 310 4 0 0 0 0 0 2300 23 0 1 0 0 0 0\n",
+        );
+        let rates = compute_rates(&before, &after);
+        assert_eq!(rates.cpu_percent, 25.0);
+        assert_eq!(rates.net_rx_bytes_sec, 1000);
+        assert_eq!(rates.net_tx_bytes_sec, 10);
+        assert_eq!(rates.net_faults, [4, 0, 0, 1]);
+
+        let disks = |read, written| vec![("sda".to_string(), read, written)];
+        let before = CounterSample { disks: disks(0, 100), ..before };
+        let after = CounterSample { disks: disks(600, 100), ..after };
+        assert_eq!(compute_rates(&before, &after).disk_read_bytes_sec, 10240);
+    }
+
+    #[test]
+    fn test_counter_wraparound() {
+        assert_eq!(counter_delta(100, 250), 150);
+        // A 32-bit counter wrapped
+        assert_eq!(counter_delta(u32::MAX as u64 - 9, 20), 30);
+        // A 64-bit counter can only have been reset
+        assert_eq!(counter_delta(1 << 40, 20), 0);
+
+        let before = sample(50.0, "cpu 0", "  eth0: 4294967000 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n");
+        let after = sample(51.0, "cpu 0", "  eth0: 704 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n");
+        assert_eq!(compute_rates(&before, &after).net_rx_bytes_sec, 1000);
+    }
+
+    #[test]
+    fn test_rates_reset_after_reboot() {
+        let netdev = "  eth0: 5000 50 0 0 0 0 0 0 3000 30 0 0 0 0 0 0\n";
+        let before = sample(86400.0, "cpu  1000 0 0 9000 0 0 0 0 0 0", netdev);
+        let after = sample(60.0, "cpu  100 0 0 900 0 0 0 0 0 0", netdev);
+        let rates = compute_rates(&before, &after);
+        assert_eq!(rates.cpu_percent, 0.0);
+        assert!(rates.net_interfaces.is_empty());
     }
 
     #[test]
@@ -495,6 +1609,37 @@ mod tests {
         assert_eq!(parse_tcp_conns(content), Some(42));
     }
 
+    #[test]
+    fn test_parse_sockstat_inuse() {
+        let content = "TCP: inuse 42 orphan 0 tw 10 alloc 50 mem 5\nUDP: inuse 3 mem 2\nUDPLITE: inuse 0\nRAW: inuse 1\nFRAG: inuse 0 memory 0\n";
+        assert_eq!(parse_sockstat_inuse(content, "UDP"), Some(3));
+        assert_eq!(parse_sockstat_inuse(content, "RAW"), Some(1));
+        assert_eq!(parse_sockstat_inuse(content, "FRAG"), Some(0));
+        assert_eq!(parse_sockstat_inuse(content, "SCTP"), None);
+    }
+
+    #[test]
+    fn test_parse_df_inodes() {
+        let content = "\
+Filesystem      Inodes  IUsed   IFree IUse% Mounted on
+/dev/sda1      6553600 327680 6225920    5% /
+tmpfs           505068      1  505067    1% /run
+/dev/sdb1      1310720 1245184   65536   95% /var
+/dev/sdc1            0      0       0     - /boot/efi";
+        let mounts = parse_df_inodes(content);
+        assert_eq!(mounts.len(), 2);
+        assert_eq!(mounts[0].mount, "/");
+        assert_eq!(mounts[0].used_pct, 5.0);
+        assert_eq!(mounts[1].mount, "/var");
+        assert_eq!(mounts[1].used_pct, 95.0);
+    }
+
+    #[test]
+    fn test_parse_tcp_states() {
+        let content = "      2 st\n     30 01\n      8 06\n      4 08\n      5 0A\n      1 01\n";
+        assert_eq!(parse_tcp_states(content), (31, 8, 4));
+    }
+
     #[test]
     fn test_parse_procs() {
         let line = "0.50 0.30 0.20 3/120 12345";
@@ -503,6 +1648,13 @@ mod tests {
         assert_eq!(total, 120);
     }
 
+    #[test]
+    fn test_parse_proc_states() {
+        let content = "State:\tZ (zombie)\nState:\tD (disk sleep)\nState:\tZ (zombie)\n";
+        assert_eq!(parse_proc_states(content), (2, 1));
+        assert_eq!(parse_proc_states(""), (0, 0));
+    }
+
     #[test]
     fn test_is_partition() {
         assert!(is_partition("sda1"));
@@ -524,23 +1676,27 @@ mod tests {
     #[test]
     fn test_full_marker_parse() {
         let output = "\
-===STAT1
+===UPTIME_BASE
+86399.50 172798.00
+===STAT_BASE
 cpu  1000 200 300 5000 100 0 0 0 0 0
-===NETDEV1
+===NETDEV_BASE
 Inter-|   Receive
  face |bytes
     lo: 100 1 0 0 0 0 0 0 100 1 0 0 0 0 0 0
   eth0: 5000 50 0 0 0 0 0 0 3000 30 0 0 0 0 0 0
-===DISKSTATS1
+===DISKSTATS_BASE
    8       0 sda 100 0 2000 0 50 0 1000 0 0 0 0 0 0 0
-===STAT2
+===UPTIME
+86400.50 172800.00
+===STAT
 cpu  1100 220 320 5050 120 0 0 0 0 0
-===NETDEV2
+===NETDEV
 Inter-|   Receive
  face |bytes
     lo: 100 1 0 0 0 0 0 0 100 1 0 0 0 0 0 0
-  eth0: 6000 60 0 0 0 0 0 0 4000 40 0 0 0 0 0 0
-===DISKSTATS2
+  eth0: 6000 60 3 1 0 0 0 0 4000 40 0 2 0 0 0 0
+===DISKSTATS
    8       0 sda 110 0 2200 0 60 0 1100 0 0 0 0 0 0 0
 ===MEMINFO
 MemTotal:       8000000 kB
@@ -552,30 +1708,542 @@ SwapTotal:      2000000 kB
 SwapFree:       1500000 kB
 ===DF
 /dev/sda1       100000 30000 70000 30% /
+===INODES
+Filesystem      Inodes  IUsed   IFree IUse% Mounted on
+/dev/sda1      6553600 327680 6225920    5% /
 ===LOADAVG
 0.50 0.30 0.20 3/120 12345
-===UPTIME
-86400.50 172800.00
 ===NPROC
 4
+===UNAME
+6.1.0-18-amd64
+===OSRELEASE
+ID=debian
+VERSION_ID=12
+===FILENR
+2000	0	10000
+===ENTROPY
+3754
+===SYSTEMD_FAILED
+2
 ===SOCKSTAT
 sockets: used 150
 TCP: inuse 42 orphan 0 tw 10 alloc 50 mem 5
-UDP: inuse 3";
+UDP: inuse 3
+===TCPSTATES
+      2 st
+     30 01
+      8 06
+      4 08
+===FINISHED
+86400.62 172800.20";
 
-        let m = parse_metrics_output(output).unwrap();
+        let (m, sample) = parse_metrics_output(output, None).unwrap();
         assert!(m.cpu_percent > 0.0);
         assert!(m.iowait_percent >= 0.0);
         assert!(m.mem_total_gb > 0.0);
         assert!(m.swap_total_gb > 0.0);
         assert_eq!(m.disk_percent, 30.0);
+        assert_eq!(m.inode_mounts.len(), 1);
         assert_eq!(m.load_1, 0.50);
         assert_eq!(m.num_cpus, 4);
         assert_eq!(m.tcp_conns, 42);
+        assert_eq!(m.systemd_failed_units, 2);
+        assert_eq!(m.entropy_avail, Some(3754));
+        assert_eq!(m.fd_max, 10000);
+        assert_eq!(m.fd_used_pct, 20.0);
+        assert_eq!(m.tcp_established, 30);
+        assert_eq!(m.tcp_time_wait, 8);
+        assert_eq!(m.tcp_close_wait, 4);
         assert_eq!(m.procs_running, 3);
         assert_eq!(m.procs_total, 120);
         assert_eq!(m.net_rx_bytes_sec, 1000);
         assert_eq!(m.net_tx_bytes_sec, 1000);
+        assert_eq!(m.net_rx_errors, 3);
+        assert_eq!(m.net_rx_drops, 1);
+        assert_eq!(m.net_tx_errors, 0);
+        assert_eq!(m.net_tx_drops, 2);
+        assert_eq!(m.net_interfaces.len(), 1);
+        assert_eq!(m.net_interfaces[0].name, "eth0");
+        assert_eq!(m.net_interfaces[0].rx_bytes_sec, 1000);
         assert!(m.uptime_secs == 86400);
+        assert_eq!(m.kernel_version.as_deref(), Some("6.1.0-18-amd64"));
+        assert_eq!(m.os_info.as_deref(), Some("debian 12"));
+        assert_eq!(m.disk_read_bytes_sec, 102400);
+        // From the baseline's uptime, so the remote sleep counts as runtime
+        assert_eq!(m.command_runtime_ms, Some(1120));
+
+        // The next poll has no baseline and compares against this one
+        let next = output
+            .split("===UPTIME\n")
+            .nth(1)
+            .unwrap()
+            .replace("86400.50", "86410.50")
+            .replace("cpu  1100 220 320 5050 120", "cpu  1100 220 320 6050 120");
+        let (m, _) = parse_metrics_output(&format!("===UPTIME\n{next}"), Some(&sample)).unwrap();
+        assert_eq!(m.cpu_percent, 0.0);
+        assert_eq!(m.net_rx_bytes_sec, 0);
+        assert_eq!(m.net_rx_errors, 0);
+    }
+
+    /// Output from an OpenWrt router (BusyBox 1.36): no nproc, no
+    /// /proc/diskstats or sockstat, an old kernel without MemAvailable, and
+    /// a df without -i.
+    const BUSYBOX_OUTPUT: &str = "\
+===OS
+Linux
+===UPTIME
+5321.84 10212.40
+===STAT
+cpu  10512 0 8841 1021833 312 0 2710 0 0 0
+===NETDEV
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo:   24160     320    0    0    0     0          0         0    24160     320    0    0    0     0       0          0
+  br-lan: 8412331   61233    0    0    0     0          0      1204 91822011   80012    0    0    0     0       0          0
+===DISKSTATS
+===MEMINFO
+MemTotal:         124280 kB
+MemFree:           62244 kB
+Buffers:            4112 kB
+Cached:            21880 kB
+SwapCached:            0 kB
+SwapTotal:             0 kB
+SwapFree:              0 kB
+===DF
+overlayfs:/overlay        93504      1532     91972   2% /
+===INODES
+===LOADAVG
+0.08 0.03 0.01 1/61 2241
+===PROCSTATES
+===NPROC
+2
+===UNAME
+5.15.137
+===OSRELEASE
+ID=\"openwrt\"
+VERSION_ID=\"23.05.2\"
+===THERMALS
+===FILENR
+672	0	11788
+===ENTROPY
+256
+===SYSTEMD_FAILED
+0
+===SOCKSTAT
+===TCPSTATES
+      1 st
+      3 0A
+      2 01
+===FINISHED
+5321.90 10212.48";
+
+    #[test]
+    fn test_busybox_output() {
+        let (m, sample) = parse_metrics_output(BUSYBOX_OUTPUT, None).unwrap();
+        assert_eq!(m.num_cpus, 2);
+        assert_eq!(m.disk_percent, 2.0);
+        assert!(m.inode_mounts.is_empty());
+        // MemFree + Buffers + Cached count as available
+        assert!((m.mem_used_gb - 36044.0 / 1_048_576.0).abs() < 1e-9);
+        assert_eq!(m.swap_total_gb, 0.0);
+        assert_eq!(m.tcp_conns, 0);
+        assert_eq!(m.udp_conns, 0);
+        assert_eq!(m.tcp_established, 2);
+        assert_eq!(m.os_info.as_deref(), Some("openwrt 23.05.2"));
+        assert_eq!(m.os_type.as_deref(), Some("Linux"));
+        assert!(sample.disks.is_empty());
+        assert_eq!(sample.net.len(), 1);
+    }
+
+    /// Output from a macOS 14 laptop: memory from vm_stat, swap from
+    /// vm.swapusage, and a tunnel interface without a hardware address.
+    const MACOS_OUTPUT: &str = "\
+===OS
+Darwin
+===UNAME
+23.4.0
+===OSRELEASE
+ID=macos
+VERSION_ID=14.4
+===SYSCTL
+hw.ncpu: 8
+hw.memsize: 17179869184
+hw.physmem: 2147483648
+hw.pagesize: 16384
+vm.loadavg: { 1.87 1.96 2.03 }
+kern.boottime: { sec = 1712000000, usec = 123456 } Mon Apr  1 19:33:20 2024
+vm.swapusage: total = 2048.00M  used = 1024.50M  free = 1023.50M  (encrypted)
+kern.num_files: 7321
+kern.maxfiles: 245760
+===NOW
+1712086400
+===VMSTAT
+Mach Virtual Memory Statistics: (page size of 16384 bytes)
+Pages free:                                3276.
+Pages active:                            250000.
+Pages inactive:                          245000.
+Pages speculative:                         1500.
+Pages throttled:                              0.
+Pages wired down:                        120000.
+===SWAPINFO
+===IOSTAT
+              disk0       cpu    load average
+    KB/t  tps  MB/s  us sy id   1m   5m   15m
+   21.45   12  0.25   5  3 92  1.87 1.96 2.03
+   16.00    4  0.06  10  5 85  1.87 1.96 2.03
+===NETSTAT
+Name       Mtu   Network       Address            Ipkts Ierrs     Ibytes    Opkts Oerrs     Obytes  Coll
+lo0        16384 <Link#1>                         52341     0   12345678    52341     0   12345678     0
+lo0        16384 127           127.0.0.1          52341     -   12345678    52341     -   12345678     -
+en0        1500  <Link#6>    a4:83:e7:12:34:56  1234567     0 1456789012   987654     0  123456789     0
+en0        1500  192.168.1     192.168.1.20     1234567     - 1456789012   987654     -  123456789     -
+utun0*     1380  <Link#12>                            0     0          0        2     0        200     0
+===TCPSTATES
+  12 ESTABLISHED
+   3 LISTEN
+   2 TIME_WAIT
+   1 CLOSE_WAIT
+===PSSTATES
+   2 R
+ 480 S
+   1 U
+   1 Z
+===DF
+/dev/disk3s1s1 1942700360 20634104 1020568864     2%    /
+===FINISHED
+";
+
+    /// Output from a FreeBSD 14 server: memory from page counts, swap from
+    /// swapinfo, and netstat's extra Idrop column.
+    const FREEBSD_OUTPUT: &str = "\
+===OS
+FreeBSD
+===UNAME
+14.0-RELEASE
+===OSRELEASE
+ID=freebsd
+VERSION_ID=14.0-RELEASE-p6
+===SYSCTL
+hw.ncpu: 4
+hw.physmem: 8533299200
+hw.pagesize: 4096
+vm.loadavg: { 0.52 0.41 0.38 }
+kern.boottime: { sec = 1712000000, usec = 500000 } Mon Apr  1 19:33:20 2024
+vm.stats.vm.v_free_count: 1500000
+vm.stats.vm.v_inactive_count: 250000
+kern.openfiles: 812
+kern.maxfiles: 260000
+===NOW
+1712003600
+===VMSTAT
+===SWAPINFO
+Device          1K-blocks     Used    Avail Capacity
+/dev/ada0p3       2097152   524288  1572864    25%
+===IOSTAT
+       tty            ada0             cpu
+ tin  tout  KB/t   tps  MB/s  us ni sy in id
+   0     5  22.3     3   0.1   1  0  1  0 98
+   0    47   0.0     0   0.0  20  0  5  0 75
+===NETSTAT
+Name    Mtu Network       Address              Ipkts Ierrs Idrop     Ibytes    Opkts Oerrs     Obytes  Coll
+em0    1500 <Link#1>      08:00:27:aa:bb:cc    51234     2     1   61234567    31234     0    4123456     0
+em0       - 10.0.2.0/24   10.0.2.15            50000     -     -   60000000    30000     -    4000000     -
+lo0   16384 <Link#2>      lo0                    120     0     0       9000      120     0       9000     0
+===TCPSTATES
+   4 ESTABLISHED
+   2 LISTEN
+===PSSTATES
+   1 R
+  40 S
+  10 I
+   1 D
+===DF
+/dev/ada0p2  30450484 12180194 15834252    43%    /
+===FINISHED
+";
+
+    #[test]
+    fn test_macos_output() {
+        let (m, sample) = parse_metrics_output(MACOS_OUTPUT, None).unwrap();
+        assert_eq!(m.os_type.as_deref(), Some("Darwin"));
+        assert_eq!(m.os_info.as_deref(), Some("macos 14.4"));
+        assert_eq!(m.kernel_version.as_deref(), Some("23.4.0"));
+        assert_eq!(m.num_cpus, 8);
+        assert_eq!(m.mem_total_gb, 16.0);
+        // Free, inactive and speculative pages count as available
+        let available: u64 = (3276 + 245_000 + 1500) * 16384;
+        let used = (17_179_869_184 - available) as f64 / 1_073_741_824.0;
+        assert!((m.mem_used_gb - used).abs() < 1e-9);
+        assert!((m.swap_used_gb - 1024.5 / 1024.0).abs() < 1e-9);
+        assert_eq!(m.swap_total_gb, 2.0);
+        assert_eq!((m.load_1, m.load_5, m.load_15), (1.87, 1.96, 2.03));
+        assert_eq!(m.uptime_secs, 86400);
+        assert_eq!(m.cpu_percent, 15.0);
+        assert_eq!(m.disk_percent, 2.0);
+        assert_eq!((m.fd_allocated, m.fd_max), (7321, 245760));
+        assert_eq!((m.tcp_conns, m.tcp_established), (18, 12));
+        assert_eq!((m.tcp_time_wait, m.tcp_close_wait), (2, 1));
+        assert_eq!((m.procs_running, m.procs_total), (2, 484));
+        assert_eq!((m.procs_zombie, m.procs_dstate), (1, 1));
+        let names: Vec<&str> = sample.net.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, ["en0", "utun0"]);
+        assert_eq!(sample.net[1].tx_bytes, 200);
+
+        // Network rates against the previous poll, ten seconds later
+        let later = MACOS_OUTPUT
+            .replace("1712086400", "1712086410")
+            .replace("1456789012", "1457789012");
+        let (m, _) = parse_metrics_output(&later, Some(&sample)).unwrap();
+        assert_eq!(m.net_rx_bytes_sec, 100_000);
+        assert_eq!(m.net_tx_bytes_sec, 0);
+    }
+
+    #[test]
+    fn test_freebsd_output() {
+        let (m, sample) = parse_metrics_output(FREEBSD_OUTPUT, None).unwrap();
+        assert_eq!(m.os_type.as_deref(), Some("FreeBSD"));
+        assert_eq!(m.os_info.as_deref(), Some("freebsd 14.0-RELEASE-p6"));
+        assert_eq!(m.num_cpus, 4);
+        // Free and inactive pages count as available
+        let used: u64 = 8_533_299_200 - (1_500_000 + 250_000) * 4096;
+        assert!((m.mem_used_gb - used as f64 / 1_073_741_824.0).abs() < 1e-9);
+        assert_eq!((m.swap_used_gb, m.swap_total_gb), (0.5, 2.0));
+        assert_eq!(m.uptime_secs, 3600);
+        assert_eq!(m.cpu_percent, 25.0);
+        assert_eq!(m.disk_percent, 43.0);
+        assert_eq!((m.fd_allocated, m.fd_max), (812, 260000));
+        assert_eq!((m.tcp_conns, m.tcp_established), (6, 4));
+        assert_eq!((m.procs_running, m.procs_total, m.procs_dstate), (1, 52, 1));
+        assert_eq!(sample.net.len(), 1);
+        assert_eq!(sample.net[0].rx_bytes, 61234567);
+        assert_eq!(sample.net[0].faults, [2, 1, 0, 0]);
+    }
+
+    const NODE_EXPORTER_OUTPUT: &str = r#"# HELP node_boot_time_seconds Node boot time, in unixtime.
+# TYPE node_boot_time_seconds gauge
+node_boot_time_seconds 1.7e+09
+node_time_seconds 1.7000036e+09
+node_cpu_seconds_total{cpu="0",mode="idle"} 3000.5
+node_cpu_seconds_total{cpu="0",mode="iowait"} 10
+node_cpu_seconds_total{cpu="0",mode="user"} 400
+node_cpu_seconds_total{cpu="0",mode="system"} 100
+node_cpu_seconds_total{cpu="1",mode="idle"} 3100
+node_cpu_seconds_total{cpu="1",mode="user"} 300
+node_memory_MemTotal_bytes 8.589934592e+09
+node_memory_MemAvailable_bytes 6.442450944e+09
+node_memory_SwapTotal_bytes 2.147483648e+09
+node_memory_SwapFree_bytes 1.073741824e+09
+node_filesystem_size_bytes{device="/dev/sda1",fstype="ext4",mountpoint="/"} 1000
+node_filesystem_free_bytes{device="/dev/sda1",fstype="ext4",mountpoint="/"} 500
+node_filesystem_avail_bytes{device="/dev/sda1",fstype="ext4",mountpoint="/"} 300
+node_filesystem_files{device="/dev/sda1",fstype="ext4",mountpoint="/"} 200
+node_filesystem_files_free{device="/dev/sda1",fstype="ext4",mountpoint="/"} 150
+node_load1 0.5
+node_load5 0.25
+node_load15 0.125
+node_network_receive_bytes_total{device="eth0"} 1000
+node_network_receive_bytes_total{device="lo"} 99999
+node_network_transmit_bytes_total{device="eth0"} 2000
+node_network_receive_errs_total{device="eth0"} 3
+node_disk_read_bytes_total{device="sda"} 5120
+node_disk_written_bytes_total{device="sda"} 10240
+node_netstat_Tcp_CurrEstab 7
+node_sockstat_TCP_inuse 9
+node_procs_running 2
+node_filefd_allocated 1024
+node_filefd_maximum 4096
+node_hwmon_temp_celsius{chip="platform_coretemp_0",sensor="temp1"} 41
+node_hwmon_temp_celsius{chip="platform_coretemp_0",sensor="temp2"} 45.5
+node_uname_info{machine="x86_64",release="6.1.0-18-amd64",sysname="Linux"} 1
+node_os_info{id="debian",name="Debian GNU/Linux",version_id="12"} 1
+node_systemd_unit_state{name="backup.service",state="active",type="oneshot"} 0
+node_systemd_unit_state{name="backup.service",state="failed",type="oneshot"} 1
+node_systemd_unit_state{name="sshd.service",state="failed",type="simple"} 0
+"#;
+
+    #[test]
+    fn test_prometheus_output() {
+        let (m, sample) = parse_prometheus_output(NODE_EXPORTER_OUTPUT, "/", None).unwrap();
+        assert_eq!(m.num_cpus, 2);
+        assert_eq!((m.mem_used_gb, m.mem_total_gb), (2.0, 8.0));
+        assert_eq!((m.swap_used_gb, m.swap_total_gb), (1.0, 2.0));
+        // 500 used of the 800 non-root users can use, as df counts it
+        assert_eq!(m.disk_percent, 62.5);
+        assert_eq!(m.inode_mounts[0].used_pct, 25.0);
+        assert_eq!((m.load_1, m.load_5, m.load_15), (0.5, 0.25, 0.125));
+        assert_eq!(m.uptime_secs, 3600);
+        assert_eq!((m.tcp_conns, m.tcp_established, m.procs_running), (9, 7, 2));
+        assert_eq!(m.fd_used_pct, 25.0);
+        assert_eq!(m.max_temp_celsius, Some(45.5));
+        assert_eq!(m.kernel_version.as_deref(), Some("6.1.0-18-amd64"));
+        assert_eq!(m.os_type.as_deref(), Some("Linux"));
+        assert_eq!(m.os_info.as_deref(), Some("debian 12"));
+        assert_eq!(m.systemd_failed_units, 1);
+        // Modes in /proc/stat order, summed over CPUs, in centiseconds
+        assert_eq!(sample.cpu, vec![70000, 0, 10000, 610050, 1000, 0, 0, 0]);
+        assert_eq!(sample.net.len(), 1);
+        assert_eq!(sample.net[0].faults, [3, 0, 0, 0]);
+        assert_eq!(sample.disks, vec![("sda".to_string(), 10, 20)]);
+
+        // Rates come from the previous sample, as with the metrics script
+        let later = NODE_EXPORTER_OUTPUT
+            .replace("node_time_seconds 1.7000036e+09", "node_time_seconds 1.70000361e+09")
+            .replace(r#"{device="eth0"} 1000"#, r#"{device="eth0"} 11000"#)
+            .replace(r#"mode="user"} 400"#, r#"mode="user"} 410"#);
+        let (m, _) = parse_prometheus_output(&later, "/", Some(&sample)).unwrap();
+        assert_eq!(m.net_rx_bytes_sec, 1000);
+        assert!(m.cpu_percent > 0.0);
+    }
+
+    #[test]
+    fn test_prometheus_output_needs_disk_path() {
+        let err = parse_prometheus_output(NODE_EXPORTER_OUTPUT, "/srv", None).unwrap_err();
+        assert_eq!(err.to_string(), "Failed to parse disk: no filesystem mounted at /srv");
+        assert!(parse_prometheus_output("<html>Not Found</html>", "/", None).is_err());
+    }
+
+    #[test]
+    fn test_prom_line_labels() {
+        let s = parse_prom_line(r#"x{a="1, \"b\"",c="d\\e"} 2.5 1700000000000"#).unwrap();
+        assert_eq!(s.name, "x");
+        assert_eq!(s.label("a"), Some(r#"1, "b""#));
+        assert_eq!(s.label("c"), Some(r"d\e"));
+        assert_eq!(s.value, 2.5);
+        assert!(parse_prom_line("# TYPE x gauge").is_none());
+        assert!(parse_prom_line(r#"x{a="1} 2"#).is_none());
+    }
+
+    #[test]
+    fn test_optional_sections_may_be_missing() {
+        // Sections dropped entirely, as from a script that was cut short
+        // before them or a wrapper that filters output
+        let output: String = BUSYBOX_OUTPUT
+            .split_inclusive('\n')
+            .scan(true, |keep, line| {
+                if let Some(name) = line.strip_prefix("===") {
+                    *keep = !["NETDEV", "DISKSTATS", "SOCKSTAT", "NPROC", "TCPSTATES"]
+                        .contains(&name.trim());
+                }
+                Some(if *keep { line } else { "" })
+            })
+            .collect();
+        let (m, sample) = parse_metrics_output(&output, None).unwrap();
+        assert_eq!(m.num_cpus, 1);
+        assert_eq!(m.net_rx_bytes_sec, 0);
+        assert_eq!(m.tcp_conns, 0);
+        assert_eq!(m.tcp_established, 0);
+        assert!(sample.net.is_empty());
+        assert_eq!(m.disk_percent, 2.0);
+    }
+
+    #[test]
+    fn test_baseline_only_on_first_poll() {
+        let first = metrics_command("/", &[], true);
+        assert!(first.contains("===STAT_BASE") && first.contains("sleep 1"));
+        let later = metrics_command("/", &[], false);
+        assert!(!later.contains("_BASE") && !later.contains("sleep"));
+        assert!(later.contains("===UPTIME'; cat /proc/uptime"));
+    }
+
+    #[test]
+    fn test_custom_metrics_sections() {
+        let custom = vec![CustomMetric {
+            name: "it's".to_string(),
+            command: "echo PONG".to_string(),
+            unit: String::new(),
+        }];
+        let cmd = metrics_command("/", &custom, true);
+        assert!(cmd.contains("; echo '===CUSTOM_it'\\''s'; (echo PONG) 2>/dev/null; "));
+        assert!(cmd.ends_with("; echo '===FINISHED'; cat /proc/uptime 2>/dev/null || true"));
+
+        let sections = parse_sections("===STAT1\ncpu 1\n===CUSTOM_redis\nPONG\n");
+        assert_eq!(sections.get("CUSTOM_redis"), Some(&"PONG"));
+    }
+
+    #[test]
+    fn test_become_command_falls_back() {
+        let cmd = become_command("echo '===UNAME'; uname -r", Some("monitor"));
+        assert!(cmd.starts_with("if become_err=$(sudo -n -u 'monitor' true 2>&1); then"));
+        assert!(cmd.contains("sudo -n -u 'monitor' sh -c 'echo '\\''===UNAME'\\''; uname -r'"));
+        assert!(cmd.ends_with("else echo '===BECOME'; echo \"$become_err\"; echo '===UNAME'; uname -r; fi"));
+
+        let sections = parse_sections("===BECOME\nsudo: a password is required\n===UNAME\n6.1\n");
+        assert_eq!(sections.get("BECOME"), Some(&"sudo: a password is required"));
+    }
+
+    #[test]
+    fn test_disk_path_in_command() {
+        assert!(metrics_command("/", &[], true)
+            .contains("{ df -P '/' 2>/dev/null || df '/'; } | tail -n 1"));
+        assert!(metrics_command("/data", &[], false).contains("df -P '/data'"));
+    }
+
+    #[test]
+    fn test_sections_survive_failures() {
+        let cmd = metrics_command("/", &[], false);
+        assert!(cmd.contains(
+            "echo '===NPROC'; nproc 2>/dev/null || grep -c '^processor' /proc/cpuinfo || true; "
+        ));
+        assert!(cmd.contains("echo '===STAT'; head -n 1 /proc/stat || true; "));
+        // Run the script where nothing it reads exists: every section is
+        // still printed, so later ones aren't lost
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(cmd.replace("/proc/", "/nonexistent/"))
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        for marker in ["===MEMINFO", "===LOADAVG", "===SOCKSTAT", "===TCPSTATES", "===FINISHED"] {
+            assert!(stdout.contains(marker), "{marker} missing");
+        }
+
+        // Likewise the BSD half, whose tools are mostly missing here
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(cmd.replace("\"$(uname -s)\"", "Darwin"))
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success());
+        for marker in ["===SYSCTL", "===VMSTAT", "===NETSTAT", "===DF", "===FINISHED"] {
+            assert!(stdout.contains(marker), "{marker} missing");
+        }
+        assert!(!stdout.contains("===MEMINFO"));
+    }
+
+    #[test]
+    fn test_parse_df_variants() {
+        // GNU / BusyBox df -P
+        assert_eq!(parse_df("/dev/sda1 100000 30000 70000 30% /").unwrap(), 30.0);
+        assert_eq!(parse_df("overlayfs:/overlay 93504 1532 91972 2% /").unwrap(), 2.0);
+        // Second half of a line wrapped after a long device name
+        assert_eq!(parse_df("  20511312 8734672 10712116  45% /").unwrap(), 45.0);
+        // Mount points with spaces
+        assert_eq!(parse_df("/dev/sdb1 100 50 50 50% /mnt/my disk").unwrap(), 50.0);
+        assert!(parse_df("df: /data: No such file or directory").is_err());
+    }
+
+    #[test]
+    fn test_parse_file_nr() {
+        assert_eq!(parse_file_nr("12864\t0\t9223372036854775807\n"), Some((12864, 9223372036854775807)));
+        assert_eq!(parse_file_nr("garbage"), None);
+    }
+
+    #[test]
+    fn test_parse_thermals() {
+        assert_eq!(parse_thermals("45000\n67500\n\n"), vec![45.0, 67.5]);
+        assert!(parse_thermals("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_os_release() {
+        assert_eq!(
+            parse_os_release("ID=ubuntu\nVERSION_ID=\"22.04\"\n").as_deref(),
+            Some("ubuntu 22.04")
+        );
+        // Rolling distros have no VERSION_ID
+        assert_eq!(parse_os_release("ID=arch\n").as_deref(), Some("arch"));
+        assert_eq!(parse_os_release(""), None);
     }
 }
@@ -1,13 +1,31 @@
+pub mod args;
 pub mod commands;
+#[cfg(feature = "native-ssh")]
+mod native;
+mod rate;
+mod runner;
 
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
 use tokio::process::Command;
 use tokio::sync::{mpsc, Semaphore};
+use tokio::task::{self, JoinHandle, JoinSet};
+use tokio::time::Instant;
 
 use crate::cli::ResolvedArgs;
-use crate::inventory::types::Host;
-use crate::metrics::{HostMetrics, HostStatus};
+use crate::config::{HostKeyChecking, SshBackend};
+use crate::inventory::types::{Host, LocalFormat};
+use crate::metrics::{HostMetrics, HostStatus, SshErrorKind};
+use commands::CounterSample;
+use rate::RateLimiter;
+#[cfg(feature = "native-ssh")]
+pub use native::Native;
+pub use runner::{CommandRunner, OpenSsh};
 
 /// Message sent from SSH polling tasks back to the TUI.
 #[derive(Debug)]
@@ -15,128 +33,2182 @@ pub enum SshMessage {
     /// Host is being polled
     Connecting(String),
     /// Poll result for a host
-    Result(HostMetrics),
+    Result(Box<HostMetrics>),
+    /// A host's poll ended without a result: it panicked, or was killed at
+    /// shutdown
+    Cancelled(String),
+}
+
+/// Control messages sent from the TUI to the poller.
+#[derive(Debug)]
+pub enum PollerCommand {
+    /// Replace the set of hosts being polled and start a new cycle
+    SetHosts(Vec<Host>),
+    /// Poll every host now instead of waiting for the interval
+    RefreshAll,
+    /// Poll one host now, outside its normal schedule
+    RefreshHost(String),
+    /// Stop (`true`) or resume (`false`) polling a host
+    Freeze(String, bool),
+    /// Kill in-flight polls and stop the loop
+    Shutdown,
 }
 
-/// Spawn the SSH polling loop. Returns a receiver for results.
-pub fn spawn_poller(
+/// Spawn the SSH polling loop, running its commands with `runner`. Returns a
+/// sender for control commands, a receiver for results, and the loop's task,
+/// which finishes after `Shutdown` once every in-flight ssh process has been
+/// killed.
+pub fn spawn_poller<R: CommandRunner>(
     hosts: Vec<Host>,
     args: Arc<ResolvedArgs>,
     interval_secs: u64,
-) -> mpsc::UnboundedReceiver<SshMessage> {
+    runner: Arc<R>,
+) -> (
+    mpsc::UnboundedSender<PollerCommand>,
+    mpsc::UnboundedReceiver<SshMessage>,
+    JoinHandle<()>,
+) {
     let (tx, rx) = mpsc::unbounded_channel();
+    let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
 
-    tokio::spawn(async move {
-        let semaphore = Arc::new(Semaphore::new(args.forks));
-
-        loop {
-            let mut handles = Vec::new();
+    let poll = move |host: Host,
+                     args: Arc<ResolvedArgs>,
+                     previous: Option<CounterSample>,
+                     limiter: Option<Arc<RateLimiter>>| {
+        let runner = runner.clone();
+        async move {
+            poll_host(&*runner, &host, &args, previous.as_ref(), limiter.as_deref()).await
+        }
+    };
+    let task = tokio::spawn(run_poller(hosts, args, interval_secs, cmd_rx, tx, poll));
 
-            for host in &hosts {
-                let host = host.clone();
-                let args = args.clone();
-                let tx = tx.clone();
-                let sem = semaphore.clone();
+    (cmd_tx, rx, task)
+}
 
-                let handle = tokio::spawn(async move {
-                    let _permit = sem.acquire().await.ok();
+/// The polling loop. Every host runs on its own schedule: it is polled again
+/// once its interval has passed since its last poll finished, with at most
+/// one poll in flight per host, so a slow or hanging host never holds back
+/// the others. `poll` polls one host, taking the connection rate limiter
+/// for its retries.
+async fn run_poller<F, Fut>(
+    mut hosts: Vec<Host>,
+    args: Arc<ResolvedArgs>,
+    interval_secs: u64,
+    mut cmd_rx: mpsc::UnboundedReceiver<PollerCommand>,
+    tx: mpsc::UnboundedSender<SshMessage>,
+    poll: F,
+) where
+    F: Fn(Host, Arc<ResolvedArgs>, Option<CounterSample>, Option<Arc<RateLimiter>>) -> Fut,
+    Fut: Future<Output = (HostMetrics, Option<CounterSample>)> + Send + 'static,
+{
+    let semaphore = Arc::new(Semaphore::new(args.forks));
+    // Shared by every poll, scheduled or refreshed on demand
+    let limiter = RateLimiter::new(args.max_connections_per_sec).map(Arc::new);
+    let default_interval = Duration::from_secs(interval_secs);
+    let mut last_polled: HashMap<String, Instant> = HashMap::new();
+    // Each host's counters from its last successful poll, to compute rates
+    // against at the next one
+    let mut samples: HashMap<String, CounterSample> = HashMap::new();
+    let mut tasks = JoinSet::new();
+    let mut in_flight: HashMap<task::Id, String> = HashMap::new();
+    let mut frozen: HashSet<String> = HashSet::new();
+    report_disabled(&hosts, &tx);
 
-                    let _ = tx.send(SshMessage::Connecting(host.name.clone()));
+    loop {
+        let skip: HashSet<&String> = in_flight.values().chain(&frozen).collect();
+        let due = due_hosts(&hosts, &last_polled, &skip, default_interval, Instant::now());
+        let count = due.len();
+        let mut started = Vec::new();
+        for (index, host) in due.into_iter().enumerate() {
+            let tx = tx.clone();
+            let sem = semaphore.clone();
+            // Local hosts don't open a connection
+            let limiter = limiter.clone().filter(|_| host.uses_ssh());
+            let name = host.name.clone();
+            let delay = stagger_delay(index, count, default_interval, args.stagger);
+            let previous = samples.get(&name).cloned();
+            let polling = poll(host.clone(), args.clone(), previous, limiter.clone());
+            let handle = tasks.spawn(async move {
+                tokio::time::sleep(delay).await;
+                let _permit = sem.acquire().await.ok();
+                if let Some(limiter) = limiter {
+                    limiter.acquire().await;
+                }
 
-                    let result = poll_host(&host, &args).await;
-                    let _ = tx.send(SshMessage::Result(result));
-                });
+                let _ = tx.send(SshMessage::Connecting(name));
 
-                handles.push(handle);
-            }
+                let (result, sample) = polling.await;
+                let _ = tx.send(SshMessage::Result(Box::new(result)));
+                sample
+            });
+            started.push((handle.id(), host.name.clone()));
+        }
+        in_flight.extend(started);
 
-            // Wait for all polls to complete
-            for handle in handles {
-                let _ = handle.await;
+        // Sleep until a poll finishes, the next idle host is due, or a
+        // command arrives
+        let next_due = hosts
+            .iter()
+            .filter(|h| !h.is_disabled() && !frozen.contains(&h.name))
+            .filter(|h| !in_flight.values().any(|n| *n == h.name))
+            .filter_map(|h| Some(*last_polled.get(&h.name)? + host_interval(h, default_interval)))
+            .min();
+        tokio::select! {
+            Some(done) = tasks.join_next_with_id() => {
+                let (id, sample, cancelled) = match done {
+                    Ok((id, sample)) => (id, sample, false),
+                    Err(e) => (e.id(), None, true),
+                };
+                if let Some(name) = in_flight.remove(&id) {
+                    if cancelled {
+                        let _ = tx.send(SshMessage::Cancelled(name.clone()));
+                    }
+                    // Intervals count from the end of the host's poll
+                    last_polled.insert(name.clone(), Instant::now());
+                    if let Some(sample) = sample {
+                        samples.insert(name, sample);
+                    }
+                }
             }
-
-            tokio::time::sleep(tokio::time::Duration::from_secs(interval_secs)).await;
+            _ = tokio::time::sleep_until(next_due.unwrap_or_else(Instant::now)),
+                if next_due.is_some() => {}
+            cmd = cmd_rx.recv() => match cmd {
+                // Stopping, or the TUI has gone away
+                Some(PollerCommand::Shutdown) | None => {
+                    shutdown(&mut tasks).await;
+                    for name in in_flight.into_values() {
+                        let _ = tx.send(SshMessage::Cancelled(name));
+                    }
+                    return;
+                }
+                Some(PollerCommand::Freeze(name, true)) => {
+                    frozen.insert(name);
+                }
+                Some(PollerCommand::Freeze(name, false)) => {
+                    frozen.remove(&name);
+                }
+                Some(cmd) => {
+                    apply_command(cmd, &mut hosts, &mut last_polled, &tx);
+                    // Hosts dropped by a reload lose their freeze, as they
+                    // do in the TUI
+                    frozen.retain(|name| hosts.iter().any(|h| h.name == *name));
+                }
+            },
         }
-    });
+    }
+}
 
-    rx
+/// Abort in-flight polls and wait until they are gone: dropping a poll
+/// kills its ssh process (`kill_on_drop`), so none outlive ansimon.
+async fn shutdown<T: 'static>(tasks: &mut JoinSet<T>) {
+    tasks.abort_all();
+    while tasks.join_next().await.is_some() {}
 }
 
-async fn poll_host(host: &Host, args: &ResolvedArgs) -> HostMetrics {
-    let mut metrics = HostMetrics::new(&host.name);
+/// Apply a TUI command by marking hosts as due. A host with a poll in
+/// flight isn't polled again: that poll's result is fresh enough.
+fn apply_command(
+    cmd: PollerCommand,
+    hosts: &mut Vec<Host>,
+    last_polled: &mut HashMap<String, Instant>,
+    tx: &mpsc::UnboundedSender<SshMessage>,
+) {
+    match cmd {
+        PollerCommand::SetHosts(new_hosts) => {
+            // Start a fresh cycle, like at startup
+            *hosts = new_hosts;
+            last_polled.clear();
+            report_disabled(hosts, tx);
+        }
+        // Handled by the loop itself
+        PollerCommand::Shutdown | PollerCommand::Freeze(..) => {}
+        PollerCommand::RefreshAll => last_polled.clear(),
+        PollerCommand::RefreshHost(name) => {
+            last_polled.remove(&name);
+        }
+    }
+}
 
-    let effective_host = host.effective_host();
-    let effective_port = args.port.unwrap_or_else(|| host.effective_port());
-    let effective_user = args
-        .user
-        .as_deref()
-        .or(host.ansible_user.as_deref());
-    let effective_key = args
-        .key
-        .as_deref()
-        .or(host.ansible_ssh_private_key_file.as_deref());
+/// Longest window connection starts are spread over with `stagger`.
+const MAX_STAGGER: Duration = Duration::from_secs(5);
 
-    let mut cmd = Command::new("ssh");
+/// Start delay for the `index`th of `count` polls in a cycle: evenly spaced
+/// over the interval (capped at `MAX_STAGGER`) when staggering, else none.
+fn stagger_delay(index: usize, count: usize, interval: Duration, enabled: bool) -> Duration {
+    if !enabled || count == 0 {
+        return Duration::ZERO;
+    }
+    interval.min(MAX_STAGGER) * index as u32 / count as u32
+}
 
-    // SSH options for non-interactive, batch mode
-    cmd.arg("-o").arg("BatchMode=yes")
-        .arg("-o").arg(format!("ConnectTimeout={}", args.ssh_timeout))
-        .arg("-o").arg("StrictHostKeyChecking=accept-new")
-        .arg("-o").arg("LogLevel=ERROR");
+/// Poll interval for a host: its `ansimon_interval`, else the global one.
+fn host_interval(host: &Host, default: Duration) -> Duration {
+    host.poll_interval().map(Duration::from_secs).unwrap_or(default)
+}
 
-    cmd.arg("-p").arg(effective_port.to_string());
+/// Hosts to poll now: enabled hosts not in `skip` (being polled, or frozen)
+/// that were never polled, or whose interval has elapsed since their last
+/// poll finished.
+fn due_hosts<'a>(
+    hosts: &'a [Host],
+    last_polled: &HashMap<String, Instant>,
+    skip: &HashSet<&String>,
+    default_interval: Duration,
+    now: Instant,
+) -> Vec<&'a Host> {
+    hosts
+        .iter()
+        .filter(|h| !h.is_disabled() && !skip.contains(&h.name))
+        .filter(|h| match last_polled.get(&h.name) {
+            Some(at) => now.duration_since(*at) >= host_interval(h, default_interval),
+            None => true,
+        })
+        .collect()
+}
 
-    if let Some(key) = effective_key {
-        cmd.arg("-i").arg(key);
+/// Show disabled hosts as such, since they never get a poll result.
+fn report_disabled(hosts: &[Host], tx: &mpsc::UnboundedSender<SshMessage>) {
+    for host in hosts.iter().filter(|h| h.is_disabled()) {
+        let mut metrics = HostMetrics::new(&host.name);
+        metrics.status = HostStatus::Disabled;
+        let _ = tx.send(SshMessage::Result(Box::new(metrics)));
     }
+}
 
-    let target = if let Some(user) = effective_user {
-        format!("{user}@{effective_host}")
-    } else {
-        effective_host.to_string()
+/// Poll one host with `runner`. `previous` is the counter sample from the
+/// host's last successful poll, if any; the new sample is returned alongside
+/// the result. Retries wait for a token from `limiter`.
+async fn poll_host<R: CommandRunner>(
+    runner: &R,
+    host: &Host,
+    args: &ResolvedArgs,
+    previous: Option<&CounterSample>,
+    limiter: Option<&RateLimiter>,
+) -> (HostMetrics, Option<CounterSample>) {
+    let mut metrics = HostMetrics::new(&host.name);
+    let local = !host.uses_ssh();
+    let jump = if local { None } else { jump_host(host, args) };
+    let with_password = !local && password(host, args).is_some();
+    metrics.jump_host = jump.map(|j| j.to_string());
+    metrics.ssh_config = (!local).then(|| ssh_config_label(host, args));
+    // An existing control socket means this poll rides on a live master
+    let multiplexed =
+        args.ssh_multiplexing && !local && control_path(host, args).exists();
+    let script = match poll_script(host, args, previous.is_none()) {
+        Ok(script) => script,
+        Err(e) => {
+            metrics.status = HostStatus::Down;
+            metrics.error = Some(format!("{e:#}"));
+            metrics.last_updated = Some(std::time::Instant::now());
+            return (metrics, None);
+        }
     };
+    let mut attempt = 0;
+    let (result, start) = loop {
+        // Measure SSH latency: the wall time minus the command's runtime
+        let start = Instant::now();
+        let limit = poll_timeout(host, args);
+        let result = match tokio::time::timeout(limit, runner.run(host, args, &script)).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(e)) => {
+                metrics.status = HostStatus::Down;
+                metrics.error = Some(format!("{e:#}"));
+                metrics.last_updated = Some(std::time::Instant::now());
+                return (metrics, None);
+            }
+            // Dropping the run future killed the command. A shell that
+            // hangs once likely hangs again, so this isn't retried.
+            Err(_) => {
+                metrics.status = HostStatus::Down;
+                metrics.error = Some(format!("command timed out after {}s", limit.as_secs()));
+                metrics.error_kind = Some(SshErrorKind::Timeout);
+                metrics.last_updated = Some(std::time::Instant::now());
+                return (metrics, None);
+            }
+        };
 
-    cmd.arg(&target);
-    cmd.arg(commands::metrics_command());
-
-    // Measure SSH latency (includes the remote sleep 1)
-    let start = Instant::now();
+        // Only network hiccups are retried: bad credentials won't fix
+        // themselves (and retrying can trigger lockouts), nor will a missing
+        // ssh binary or a command that fails on the host
+        let retry = match &result {
+            Ok(output) if output.status.success() => false,
+            Ok(output) => is_transient(output),
+            Err(_) => false,
+        };
+        if !retry || attempt >= args.retry_attempts {
+            break (result, start);
+        }
+        tokio::time::sleep(retry_delay(args.retry_delay_ms, attempt)).await;
+        // A retry opens a new connection, so it waits its turn like the
+        // first attempt did in the poller
+        if let Some(limiter) = limiter {
+            limiter.acquire().await;
+        }
+        attempt += 1;
+    };
 
-    match cmd.output().await {
+    let attempts = attempt + 1;
+    let mut sample = None;
+    match result {
         Ok(output) => {
             let elapsed_ms = start.elapsed().as_millis() as u64;
-            // Subtract the 1000ms remote sleep to get actual SSH + parse latency
-            let ssh_latency = elapsed_ms.saturating_sub(1000);
+            // Without a runtime reported by the host, fall back to
+            // subtracting a baseline's 1000ms remote sleep. Local hosts have
+            // no transport, so there is no latency to report.
+            let baseline_sleep = if previous.is_none() { 1000 } else { 0 };
+            let ssh_latency = if local {
+                None
+            } else {
+                Some(elapsed_ms.saturating_sub(baseline_sleep))
+            };
 
             if output.status.success() {
                 let stdout = String::from_utf8_lossy(&output.stdout);
-                match commands::parse_metrics_output(&stdout) {
-                    Ok(m) => {
+                let parsed = match host.local_format() {
+                    LocalFormat::Prometheus if host.collects_locally() => {
+                        commands::parse_prometheus_output(&stdout, host.disk_path(), previous)
+                    }
+                    _ => commands::parse_metrics_output(&stdout, previous),
+                };
+                match parsed {
+                    Ok((m, counters)) => {
+                        sample = Some(counters);
+                        let ssh_latency = match m.command_runtime_ms {
+                            Some(runtime) if !local => Some(elapsed_ms.saturating_sub(runtime)),
+                            _ => ssh_latency,
+                        };
                         metrics.status = HostStatus::Up;
                         metrics.metrics = Some(m);
-                        metrics.last_updated = Some(Instant::now());
-                        metrics.ssh_latency_ms = Some(ssh_latency);
+                        metrics.last_updated = Some(std::time::Instant::now());
+                        metrics.ssh_latency_ms = ssh_latency;
+                        metrics.multiplexed = multiplexed;
                     }
                     Err(e) => {
                         metrics.status = HostStatus::Down;
                         metrics.error = Some(format!("Parse error: {e}"));
-                        metrics.last_updated = Some(Instant::now());
-                        metrics.ssh_latency_ms = Some(ssh_latency);
+                        metrics.error_kind = Some(SshErrorKind::Parse);
+                        metrics.last_updated = Some(std::time::Instant::now());
+                        metrics.ssh_latency_ms = ssh_latency;
                     }
                 }
             } else {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                metrics.status = HostStatus::Down;
-                metrics.error = Some(stderr.trim().to_string());
-                metrics.last_updated = Some(Instant::now());
+                record_failure(&mut metrics, &output, local, with_password, jump);
+                if attempts > 1 {
+                    if let Some(error) = &mut metrics.error {
+                        error.push_str(&format!(" (after {attempts} attempts)"));
+                    }
+                }
+                metrics.last_updated = Some(std::time::Instant::now());
             }
         }
         Err(e) => {
             metrics.status = HostStatus::Down;
-            metrics.error = Some(format!("SSH failed: {e}"));
-            metrics.last_updated = Some(Instant::now());
+            metrics.error = Some(spawn_error(&e, local, with_password));
+            metrics.last_updated = Some(std::time::Instant::now());
         }
     }
 
+    (metrics, sample)
+}
+
+/// Mark a host down for a command that ran and failed, explaining why from
+/// its exit status and stderr.
+fn record_failure(
+    metrics: &mut HostMetrics,
+    output: &std::process::Output,
+    local: bool,
+    with_password: bool,
+    jump: Option<&str>,
+) {
+    let stderr = ssh_stderr(output);
+    metrics.status = HostStatus::Down;
+    metrics.error = Some(match jump.and_then(|j| failed_jump(&stderr, j)) {
+        Some(hop) => format!("Jump host {hop}: {}", stderr.trim()),
+        None => stderr.trim().to_string(),
+    });
+    metrics.error_kind = if local {
+        Some(SshErrorKind::Command)
+    } else {
+        failure_kind(output)
+    };
+    if with_password && metrics.error_kind == Some(SshErrorKind::Auth) {
+        metrics.error = Some("Authentication failed: password rejected".to_string());
+    }
+}
+
+/// Error message for a command that couldn't be started at all.
+fn spawn_error(e: &std::io::Error, local: bool, with_password: bool) -> String {
+    if local {
+        format!("Local command failed: {e}")
+    } else if with_password && e.kind() == std::io::ErrorKind::NotFound {
+        "Password authentication needs sshpass, which was not found".to_string()
+    } else {
+        format!("SSH failed: {e}")
+    }
+}
+
+/// Connect to every enabled host and run `true` with `runner`, at most
+/// `forks` at a time, for `ansimon check`. Results come back in the order
+/// hosts finish.
+pub async fn check_hosts<R: CommandRunner>(
+    hosts: &[Host],
+    args: Arc<ResolvedArgs>,
+    runner: Arc<R>,
+) -> Vec<HostMetrics> {
+    let semaphore = Arc::new(Semaphore::new(args.forks.max(1)));
+    let limiter = RateLimiter::new(args.max_connections_per_sec).map(Arc::new);
+    let mut tasks = JoinSet::new();
+    for host in hosts.iter().filter(|h| !h.is_disabled()) {
+        let host = host.clone();
+        let sem = semaphore.clone();
+        let limiter = limiter.clone().filter(|_| host.uses_ssh());
+        let args = args.clone();
+        let runner = runner.clone();
+        tasks.spawn(async move {
+            let _permit = sem.acquire().await.ok();
+            if let Some(limiter) = limiter {
+                limiter.acquire().await;
+            }
+            check_host(&*runner, &host, &args).await
+        });
+    }
+
+    let mut results = Vec::new();
+    while let Some(done) = tasks.join_next().await {
+        if let Ok(result) = done {
+            results.push(result);
+        }
+    }
+    results
+}
+
+/// Check that a host is reachable: the same command a poll would run, with
+/// `true` as the script, so it fails exactly where polling would. Hosts
+/// collected locally run their local command instead, as there is nothing
+/// to connect to. Latency is the whole run's wall time. Not retried.
+async fn check_host<R: CommandRunner>(
+    runner: &R,
+    host: &Host,
+    args: &ResolvedArgs,
+) -> HostMetrics {
+    let mut metrics = HostMetrics::new(&host.name);
+    metrics.last_updated = Some(std::time::Instant::now());
+    let local = !host.uses_ssh();
+    let jump = if local { None } else { jump_host(host, args) };
+    let with_password = !local && password(host, args).is_some();
+    metrics.jump_host = jump.map(|j| j.to_string());
+    metrics.ssh_config = (!local).then(|| ssh_config_label(host, args));
+    let script = if host.collects_locally() {
+        match poll_script(host, args, false) {
+            Ok(script) => script,
+            Err(e) => {
+                metrics.status = HostStatus::Down;
+                metrics.error = Some(format!("{e:#}"));
+                return metrics;
+            }
+        }
+    } else {
+        "true".to_string()
+    };
+
+    let start = Instant::now();
+    let limit = poll_timeout(host, args);
+    match tokio::time::timeout(limit, runner.run(host, args, &script)).await {
+        Err(_) => {
+            metrics.status = HostStatus::Down;
+            metrics.error = Some(format!("timed out after {}s", limit.as_secs()));
+            metrics.error_kind = Some(SshErrorKind::Timeout);
+        }
+        Ok(Err(e)) => {
+            metrics.status = HostStatus::Down;
+            metrics.error = Some(format!("{e:#}"));
+        }
+        Ok(Ok(Ok(output))) if output.status.success() => {
+            metrics.status = HostStatus::Up;
+            if !local {
+                metrics.ssh_latency_ms = Some(start.elapsed().as_millis() as u64);
+            }
+        }
+        Ok(Ok(Ok(output))) => record_failure(&mut metrics, &output, local, with_password, jump),
+        Ok(Ok(Err(e))) => {
+            metrics.status = HostStatus::Down;
+            metrics.error = Some(spawn_error(&e, local, with_password));
+        }
+    }
     metrics
 }
+
+/// Longest a poll's command may run: the connect timeout plus the command
+/// budget. Local hosts don't connect, so they only get the budget.
+fn poll_timeout(host: &Host, args: &ResolvedArgs) -> Duration {
+    let connect = if !host.uses_ssh() {
+        0
+    } else {
+        host.ssh_timeout.unwrap_or(args.ssh_timeout)
+    };
+    Duration::from_secs(connect + args.command_timeout)
+}
+
+/// ssh_config file to pass with `-F`: the host's `ansimon_ssh_config_file`,
+/// else the config's `ssh_config_file`, else /dev/null when
+/// `ignore_user_ssh_config` is set. None (or an empty var) leaves ssh to read
+/// ~/.ssh/config and the system-wide file as usual.
+fn ssh_config_file(host: &Host, args: &ResolvedArgs) -> Option<PathBuf> {
+    let file = host
+        .ssh_config_file()
+        .or(args.ssh_config_file.as_deref())
+        .or(args.ignore_user_ssh_config.then_some("/dev/null"))
+        .filter(|f| !f.is_empty())?;
+    let home = std::env::var("HOME").ok();
+    Some(expand_home(file, home.as_deref()))
+}
+
+/// The ssh_config file in effect for a host, for the detail panel.
+fn ssh_config_label(host: &Host, args: &ResolvedArgs) -> String {
+    match ssh_config_file(host, args) {
+        Some(file) if file.as_os_str() == "/dev/null" => "none (-F /dev/null)".to_string(),
+        Some(file) => file.display().to_string(),
+        None => "~/.ssh/config (ssh default)".to_string(),
+    }
+}
+
+/// Private key to pass with `-i`: `--key`, else the host's
+/// `ansible_ssh_private_key_file`, else the config's (for the host's group,
+/// or the default). ssh doesn't expand `~` in its arguments, so `~` and
+/// `$HOME` are expanded here, and a relative inventory path is taken from the
+/// inventory's directory. A missing file is an error rather than a silent
+/// fallback to other keys.
+fn key_file(host: &Host, args: &ResolvedArgs) -> Result<Option<PathBuf>> {
+    let home = std::env::var("HOME").ok();
+    let path = match (&args.key, &host.ansible_ssh_private_key_file) {
+        (Some(key), _) => expand_home(key, home.as_deref()),
+        (None, Some(key)) => {
+            let path = expand_home(key, home.as_deref());
+            if path.is_relative() {
+                inventory_dir(&args.inventory).join(path)
+            } else {
+                path
+            }
+        }
+        (None, None) => match args.config_key_for(host) {
+            Some(key) => expand_home(key, home.as_deref()),
+            None => return Ok(None),
+        },
+    };
+    if !path.exists() {
+        anyhow::bail!("key file not found: {}", path.display());
+    }
+    Ok(Some(path))
+}
+
+/// Expand a leading `~`, `$HOME` or `${HOME}` to the home directory.
+fn expand_home(path: &str, home: Option<&str>) -> PathBuf {
+    let Some(home) = home else {
+        return PathBuf::from(path);
+    };
+    for prefix in ["~", "$HOME", "${HOME}"] {
+        if let Some(rest) = path.strip_prefix(prefix) {
+            if rest.is_empty() {
+                return PathBuf::from(home);
+            }
+            if let Some(rest) = rest.strip_prefix('/') {
+                return PathBuf::from(home).join(rest);
+            }
+        }
+    }
+    PathBuf::from(path)
+}
+
+/// Directory relative inventory paths are resolved against: the first
+/// inventory source itself when it is a directory, else the directory it's in.
+fn inventory_dir(spec: &str) -> PathBuf {
+    let first = PathBuf::from(spec.split(',').next().unwrap_or("").trim());
+    if first.is_dir() {
+        return first;
+    }
+    match first.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// `-o` options for the host's key checking mode (its inventory var, else the
+/// config setting) and the configured known_hosts file. With checking off,
+/// keys go to /dev/null unless a known_hosts file is set.
+fn host_key_options(host: &Host, args: &ResolvedArgs) -> Vec<String> {
+    let mode = host.host_key_checking().unwrap_or(args.host_key_checking);
+    let strict = match mode {
+        HostKeyChecking::Strict => "yes",
+        HostKeyChecking::AcceptNew => "accept-new",
+        HostKeyChecking::Off => "no",
+    };
+    let known_hosts = match (&args.known_hosts_file, mode) {
+        (Some(file), _) => {
+            let home = std::env::var("HOME").ok();
+            Some(expand_home(file, home.as_deref()).display().to_string())
+        }
+        (None, HostKeyChecking::Off) => Some("/dev/null".to_string()),
+        (None, _) => None,
+    };
+
+    let mut options = vec!["-o".to_string(), format!("StrictHostKeyChecking={strict}")];
+    if let Some(file) = known_hosts {
+        options.push("-o".to_string());
+        options.push(format!("UserKnownHostsFile={file}"));
+    }
+    options
+}
+
+/// SSH password for a host: its inventory var, else the one from --ask-pass.
+fn password<'a>(host: &'a Host, args: &'a ResolvedArgs) -> Option<&'a str> {
+    host.ansible_password
+        .as_deref()
+        .or(args.ssh_password.as_deref())
+}
+
+/// Why a finished ssh run failed. ssh exits with 255 for its own errors and
+/// passes on the remote command's status otherwise, so only 255 is matched
+/// against ssh's messages; the remote stderr could say anything. sshpass
+/// passes ssh's status on too, so a rejected password is told apart by the
+/// second prompt `sshpass -v` reports, not by an exit code a remote command
+/// could also use.
+fn failure_kind(output: &std::process::Output) -> Option<SshErrorKind> {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.lines().any(|l| l.starts_with("SSHPASS: detected prompt, again")) {
+        return Some(SshErrorKind::Auth);
+    }
+    match output.status.code() {
+        Some(255) | None => classify_error(&ssh_stderr(output)),
+        Some(_) => Some(SshErrorKind::Command),
+    }
+}
+
+/// A run's stderr without the `SSHPASS:` lines that `sshpass -v` adds.
+fn ssh_stderr(output: &std::process::Output) -> String {
+    String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .filter(|l| !l.starts_with("SSHPASS:"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Whether a failed run looks like a network problem worth retrying.
+fn is_transient(output: &std::process::Output) -> bool {
+    let stderr = ssh_stderr(output);
+    matches!(
+        failure_kind(output),
+        Some(SshErrorKind::Timeout | SshErrorKind::Refused | SshErrorKind::Unreachable)
+    ) || stderr.contains("Connection reset")
+        || stderr.contains("Connection closed by")
+}
+
+/// Jump host for a host: its inventory var, else the global setting. An
+/// empty var opts the host out of the global jump host.
+fn jump_host<'a>(host: &'a Host, args: &'a ResolvedArgs) -> Option<&'a str> {
+    host.jump_host()
+        .or(args.jump_host.as_deref())
+        .filter(|j| !j.is_empty())
+}
+
+/// The hop of a `ProxyJump` chain that ssh's stderr blames, if the failure
+/// happened at a jump host rather than the target.
+fn failed_jump<'a>(stderr: &str, jump: &'a str) -> Option<&'a str> {
+    jump.split(',').find(|hop| {
+        let address = hop.rsplit_once('@').map_or(*hop, |(_, a)| a);
+        let name = address.split_once(':').map_or(address, |(n, _)| n);
+        stderr.contains(&format!("connect to host {name} "))
+            || stderr.contains(&format!("resolve hostname {name}:"))
+    })
+}
+
+/// The configured SSH backend, if this build includes it.
+pub fn backend(backend: SshBackend) -> Result<SshBackend> {
+    if backend == SshBackend::Native && !cfg!(feature = "native-ssh") {
+        anyhow::bail!("ssh_backend: native needs ansimon built with `--features native-ssh`");
+    }
+    Ok(backend)
+}
+
+/// Recognize common ssh connection failures from its stderr.
+fn classify_error(stderr: &str) -> Option<SshErrorKind> {
+    if stderr.contains("REMOTE HOST IDENTIFICATION HAS CHANGED")
+        || stderr.contains("has changed and you have requested strict checking")
+    {
+        Some(SshErrorKind::HostKeyChanged)
+    } else if stderr.contains("Permission denied") {
+        Some(SshErrorKind::Auth)
+    } else if stderr.contains("timed out") {
+        Some(SshErrorKind::Timeout)
+    } else if stderr.contains("Connection refused") {
+        Some(SshErrorKind::Refused)
+    } else if stderr.contains("No route to host") || stderr.contains("Network is unreachable") {
+        Some(SshErrorKind::Unreachable)
+    } else if stderr.contains("Could not resolve hostname")
+        || stderr.contains("Name or service not known")
+        || stderr.contains("nodename nor servname provided")
+    {
+        Some(SshErrorKind::Dns)
+    } else {
+        None
+    }
+}
+
+/// Backoff before retry number `attempt` (0-based): the base delay doubled
+/// for each earlier retry.
+fn retry_delay(base_ms: u64, attempt: u32) -> Duration {
+    Duration::from_millis(base_ms.saturating_mul(1u64 << attempt.min(16)))
+}
+
+/// Inventory vars holding raw ssh arguments, in Ansible's precedence order.
+const SSH_ARG_VARS: &[&str] = &[
+    "ansible_ssh_args",
+    "ansible_ssh_common_args",
+    "ansible_ssh_extra_args",
+];
+
+/// Directory holding the multiplexing control sockets: under
+/// `$XDG_RUNTIME_DIR` when set, else a per-user directory in the temp dir.
+pub fn control_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("ansimon"),
+        _ => {
+            let user = std::env::var("USER").unwrap_or_default();
+            std::env::temp_dir().join(format!("ansimon-{user}"))
+        }
+    }
+}
+
+/// Create the control socket directory, readable only by the current user.
+pub fn prepare_control_dir() -> Result<()> {
+    secure_dir(&control_dir())
+}
+
+/// Create `dir` with mode 0700, or reuse it if it exists. Since the temp dir
+/// is world-writable and the fallback name predictable, an existing `dir`
+/// must be a real directory owned by the current user, or it is refused.
+fn secure_dir(dir: &std::path::Path) -> Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to create {}", dir.display()));
+        }
+    }
+
+    let meta = std::fs::symlink_metadata(dir)
+        .with_context(|| format!("Failed to inspect {}", dir.display()))?;
+    // SAFETY: geteuid has no preconditions and never fails
+    let uid = unsafe { libc::geteuid() };
+    if !meta.is_dir() || meta.uid() != uid {
+        anyhow::bail!("{} is not a directory owned by the current user", dir.display());
+    }
+    if meta.mode() & 0o077 != 0 {
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+            .with_context(|| format!("Failed to secure {}", dir.display()))?;
+    }
+    Ok(())
+}
+
+/// Control socket for a host's multiplexing master. Named by a short hash of
+/// the destination rather than ssh's `%h-%p-%r` tokens so long host names
+/// can't overflow the ~100 byte limit on socket paths, and so ansimon can
+/// tell whether a master is already up.
+fn control_path(host: &Host, args: &ResolvedArgs) -> PathBuf {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    ssh_target(host, args).hash(&mut hasher);
+    control_dir().join(format!("{:016x}", hasher.finish()))
+}
+
+/// SSH destination (`user@host`) and port for a host, CLI overrides first.
+fn ssh_target(host: &Host, args: &ResolvedArgs) -> (String, u16) {
+    let effective_host = host.ssh_address();
+    let effective_port = args.port_for(host);
+    let effective_user = args.user_for(host);
+
+    let target = if let Some(user) = effective_user {
+        format!("{user}@{effective_host}")
+    } else {
+        effective_host.to_string()
+    };
+    (target, effective_port)
+}
+
+/// Ask the multiplexing master of every remote host to exit, so no control
+/// sockets are left behind. Hosts without a master just fail quietly.
+pub async fn close_masters(hosts: &[Host], args: &ResolvedArgs) {
+    let mut exits = JoinSet::new();
+    for host in hosts.iter().filter(|h| h.uses_ssh()) {
+        let (target, port) = ssh_target(host, args);
+        let mut cmd = Command::new("ssh");
+        cmd.arg("-O").arg("exit")
+            .arg("-o").arg(format!("ControlPath={}", control_path(host, args).display()))
+            .arg("-p").arg(port.to_string())
+            .arg(target)
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null());
+        exits.spawn(async move { cmd.status().await });
+    }
+
+    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(2), async {
+        while exits.join_next().await.is_some() {}
+    })
+    .await;
+    // Only succeeds once every socket is gone
+    let _ = std::fs::remove_dir(control_dir());
+}
+
+/// Build the script that collects metrics on a host, under sudo when become
+/// is wanted. `baseline` adds the one-second counter sample needed without a previous
+/// poll to compare against. Hosts collected locally run their
+/// `ansimon_local_command` as is.
+fn poll_script(host: &Host, args: &ResolvedArgs, baseline: bool) -> Result<String> {
+    if host.collects_locally() {
+        return host
+            .local_command()
+            .context("ansimon_collector=local needs an ansimon_local_command");
+    }
+    let script = commands::metrics_command(host.disk_path(), &args.custom_metrics, baseline);
+    if args.sudo || host.wants_become() {
+        Ok(commands::become_command(&script, host.become_user()))
+    } else {
+        Ok(script)
+    }
+}
+
+/// Run a shell script on a host: `sh -c` for local hosts and hosts collected
+/// locally, otherwise a non-interactive `ssh` (through sshpass for password
+/// logins).
+fn remote_command(host: &Host, args: &ResolvedArgs, script: &str) -> Result<Command> {
+    if !host.uses_ssh() {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(script);
+        return Ok(cmd);
+    }
+
+    let mut cmd = match password(host, args) {
+        // sshpass answers the password prompt, so batch mode has to go; the
+        // password travels in the environment, never on the command line.
+        // -v reports a rejected password on stderr, for failure_kind.
+        Some(password) => {
+            let mut cmd = Command::new("sshpass");
+            cmd.arg("-v").arg("-e").arg("ssh").env("SSHPASS", password);
+            cmd.arg("-o").arg("NumberOfPasswordPrompts=1");
+            cmd
+        }
+        None => {
+            let mut cmd = Command::new("ssh");
+            cmd.arg("-o").arg("BatchMode=yes");
+            cmd
+        }
+    };
+
+    add_connection_options(&mut cmd, host, args)?;
+    cmd.arg(script);
+    Ok(cmd)
+}
+
+/// Build an interactive login to a host, for opening a session from the
+/// TUI: ssh with the same connection options as polling (reusing the
+/// multiplexed connection when there is one), or the user's shell for local
+/// hosts.
+pub fn interactive_command(host: &Host, args: &ResolvedArgs) -> Result<Command> {
+    if host.is_local() {
+        let shell = std::env::var("SHELL").unwrap_or_else(|_| "sh".to_string());
+        return Ok(Command::new(shell));
+    }
+
+    let mut cmd = match password(host, args) {
+        Some(password) => {
+            let mut cmd = Command::new("sshpass");
+            cmd.arg("-e").arg("ssh").env("SSHPASS", password);
+            cmd
+        }
+        None => Command::new("ssh"),
+    };
+    add_connection_options(&mut cmd, host, args)?;
+    Ok(cmd)
+}
+
+/// Add the ssh options and target for a remote host, shared by polls and
+/// interactive sessions. The target comes last.
+fn add_connection_options(cmd: &mut Command, host: &Host, args: &ResolvedArgs) -> Result<()> {
+    let (target, effective_port) = ssh_target(host, args);
+    let effective_key = key_file(host, args)?;
+
+    let connect_timeout = host.ssh_timeout.unwrap_or(args.ssh_timeout);
+
+    if let Some(file) = ssh_config_file(host, args) {
+        cmd.arg("-F").arg(file);
+    }
+    cmd.arg("-o").arg(format!("ConnectTimeout={connect_timeout}"))
+        .arg("-o").arg("LogLevel=ERROR");
+    cmd.args(host_key_options(host, args));
+
+    // A master that died leaves a stale socket; ssh notices and connects
+    // directly, becoming the new master
+    if args.ssh_multiplexing {
+        cmd.arg("-o").arg("ControlMaster=auto")
+            .arg("-o").arg(format!("ControlPath={}", control_path(host, args).display()))
+            .arg("-o").arg("ControlPersist=60s");
+    }
+
+    cmd.arg("-p").arg(effective_port.to_string());
+
+    if let Some(key) = effective_key {
+        cmd.arg("-i").arg(key);
+    }
+
+    if let Some(jump) = jump_host(host, args) {
+        cmd.arg("-o").arg(format!("ProxyJump={jump}"));
+    }
+
+    // A dedicated proxy setting goes first: ssh keeps the first value it
+    // sees, so it wins over a ProxyCommand inside the raw args below. It is
+    // one argument, so its spaces reach ssh untouched.
+    if let Some(proxy) = args.proxy_command_for(host) {
+        cmd.arg("-o").arg(format!("ProxyCommand={proxy}"));
+    }
+
+    // Extra ssh options from the inventory, in the order Ansible applies them
+    for var in SSH_ARG_VARS {
+        if let Some(value) = host.vars.get(*var) {
+            let extra = args::shell_split(value).with_context(|| format!("Invalid {var}"))?;
+            cmd.args(extra);
+        }
+    }
+
+    cmd.arg(&target);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SshSettings;
+    use std::process::Output;
+
+    fn test_args() -> ResolvedArgs {
+        ResolvedArgs {
+            ssh_timeout: 5,
+            ..Default::default()
+        }
+    }
+
+    /// The command a poll runs through OpenSsh.
+    fn build_command(host: &Host, args: &ResolvedArgs, baseline: bool) -> Result<Command> {
+        remote_command(host, args, &poll_script(host, args, baseline).unwrap())
+    }
+
+    /// Answers each run with the next canned result after `delay`, and
+    /// records the scripts it was asked to run.
+    struct MockRunner {
+        results: std::sync::Mutex<std::collections::VecDeque<std::io::Result<Output>>>,
+        delay: Duration,
+        scripts: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl MockRunner {
+        fn new(results: Vec<std::io::Result<Output>>) -> Self {
+            Self {
+                results: std::sync::Mutex::new(results.into()),
+                delay: Duration::ZERO,
+                scripts: std::sync::Mutex::new(Vec::new()),
+            }
+        }
+
+        fn runs(&self) -> usize {
+            self.scripts.lock().unwrap().len()
+        }
+    }
+
+    impl CommandRunner for MockRunner {
+        async fn run(
+            &self,
+            _: &Host,
+            _: &ResolvedArgs,
+            script: &str,
+        ) -> Result<std::io::Result<Output>> {
+            self.scripts.lock().unwrap().push(script.to_string());
+            tokio::time::sleep(self.delay).await;
+            Ok(self.results.lock().unwrap().pop_front().expect("unexpected run"))
+        }
+    }
+
+    fn output(code: i32, stdout: &str, stderr: &str) -> std::io::Result<Output> {
+        use std::os::unix::process::ExitStatusExt;
+        Ok(Output {
+            status: std::process::ExitStatus::from_raw(code << 8),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: stderr.as_bytes().to_vec(),
+        })
+    }
+
+    /// Minimal metrics output from a Linux host that ran for 1.2s.
+    const POLL_OUTPUT: &str = "\
+===UPTIME
+100.00 50.00
+===STAT
+cpu  100 0 50 1000 10 0 0 0 0 0
+===MEMINFO
+MemTotal:        2048000 kB
+MemAvailable:    1024000 kB
+===DF
+/dev/sda1 100000 30000 70000 30% /
+===LOADAVG
+0.50 0.40 0.30 1/80 1234
+===FINISHED
+101.20 51.00
+";
+
+    fn program(cmd: &Command) -> String {
+        cmd.as_std().get_program().to_string_lossy().into_owned()
+    }
+
+    fn cmd_args(cmd: &Command) -> Vec<String> {
+        cmd.as_std()
+            .get_args()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_local_connection_uses_sh() {
+        let mut host = Host::new("runner01");
+        host.apply_host_var("ansible_connection", "local");
+        let cmd = build_command(&host, &test_args(), true).unwrap();
+        assert_eq!(program(&cmd), "sh");
+    }
+
+    #[test]
+    fn test_bare_localhost_is_local() {
+        let host = Host::new("localhost");
+        assert_eq!(program(&build_command(&host, &test_args(), true).unwrap()), "sh");
+
+        let mut host = Host::new("localhost");
+        host.apply_host_var("ansible_host", "10.0.0.5");
+        assert_eq!(program(&build_command(&host, &test_args(), true).unwrap()), "ssh");
+    }
+
+    #[test]
+    fn test_remote_host_uses_ssh() {
+        let mut host = Host::new("web01");
+        host.apply_host_var("ansible_connection", "ssh");
+        host.apply_host_var("ansible_user", "deploy");
+        let cmd = build_command(&host, &test_args(), true).unwrap();
+        assert_eq!(program(&cmd), "ssh");
+        assert!(cmd_args(&cmd).contains(&"deploy@web01".to_string()));
+    }
+
+    #[test]
+    fn test_check_command_runs_true_like_a_poll() {
+        let mut host = Host::new("web01");
+        host.apply_host_var("ansible_user", "deploy");
+        host.apply_host_var("jump_host", "bastion");
+        let args = test_args();
+        let check = cmd_args(&remote_command(&host, &args, "true").unwrap());
+        assert_eq!(check.last().unwrap(), "true");
+
+        // Everything before the remote command matches a poll's
+        let poll = cmd_args(&build_command(&host, &args, true).unwrap());
+        assert_eq!(check[..check.len() - 1], poll[..poll.len() - 1]);
+    }
+
+    #[tokio::test]
+    async fn test_check_hosts_skips_disabled() {
+        let local = Host::new("localhost");
+        let mut disabled = Host::new("web01");
+        disabled.apply_host_var("ansimon_disabled", "true");
+        let args = Arc::new(ResolvedArgs {
+            forks: 1,
+            command_timeout: 5,
+            ..test_args()
+        });
+
+        let results = check_hosts(&[local, disabled], args, Arc::new(OpenSsh)).await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].host_name, "localhost");
+        assert_eq!(results[0].status, HostStatus::Up);
+        assert_eq!(results[0].ssh_latency_ms, None);
+    }
+
+    #[test]
+    fn test_ssh_arg_vars_are_split_and_ordered() {
+        let mut host = Host::new("web01");
+        host.apply_group_var("ansible_ssh_extra_args", "-o ServerAliveInterval=5");
+        host.apply_group_var(
+            "ansible_ssh_common_args",
+            "-o 'ProxyCommand=ssh -W %h:%p jump.example.com'",
+        );
+        let args = cmd_args(&build_command(&host, &test_args(), true).unwrap());
+
+        let proxy = args
+            .iter()
+            .position(|a| a == "ProxyCommand=ssh -W %h:%p jump.example.com")
+            .expect("quoted option kept as one argument");
+        let alive = args
+            .iter()
+            .position(|a| a == "ServerAliveInterval=5")
+            .unwrap();
+        let target = args.iter().position(|a| a == "web01").unwrap();
+        assert!(proxy < alive && alive < target);
+    }
+
+    #[test]
+    fn test_proxy_command_var() {
+        let mut host = Host::new("i-0abc");
+        host.apply_group_var("ansible_ssh_common_args", "-o 'ProxyCommand=tsh proxy ssh %r@%h:%p'");
+        host.apply_host_var(
+            "ansible_proxy_command",
+            "aws ssm start-session --target %h --document-name AWS-StartSSHSession",
+        );
+        let args = cmd_args(&build_command(&host, &test_args(), true).unwrap());
+        let first_proxy = args.iter().position(|a| a.starts_with("ProxyCommand=")).unwrap();
+        assert_eq!(
+            args[first_proxy],
+            "ProxyCommand=aws ssm start-session --target %h --document-name AWS-StartSSHSession"
+        );
+        assert_eq!(args[first_proxy - 1], "-o");
+    }
+
+    #[test]
+    fn test_ssh_config_file() {
+        let config_arg = |host: &Host, args: &ResolvedArgs| {
+            let cmd_args = cmd_args(&build_command(host, args, true).unwrap());
+            cmd_args
+                .iter()
+                .position(|a| a == "-F")
+                .map(|i| cmd_args[i + 1].clone())
+        };
+        let mut host = Host::new("web01");
+        assert_eq!(config_arg(&host, &test_args()), None);
+        assert_eq!(ssh_config_label(&host, &test_args()), "~/.ssh/config (ssh default)");
+
+        let ignore = ResolvedArgs {
+            ignore_user_ssh_config: true,
+            ..test_args()
+        };
+        assert_eq!(config_arg(&host, &ignore).as_deref(), Some("/dev/null"));
+        assert_eq!(ssh_config_label(&host, &ignore), "none (-F /dev/null)");
+
+        // A named file wins over ignoring, and the host var over both
+        let named = ResolvedArgs {
+            ssh_config_file: Some("/etc/ansimon/ssh_config".into()),
+            ..ignore
+        };
+        assert_eq!(config_arg(&host, &named).as_deref(), Some("/etc/ansimon/ssh_config"));
+        host.apply_host_var("ansimon_ssh_config_file", "/srv/dc2/ssh_config");
+        assert_eq!(config_arg(&host, &named).as_deref(), Some("/srv/dc2/ssh_config"));
+        host.apply_host_var("ansimon_ssh_config_file", "");
+        assert_eq!(config_arg(&host, &named), None);
+    }
+
+    #[tokio::test]
+    async fn test_poll_reports_ssh_config() {
+        let args = ResolvedArgs {
+            ignore_user_ssh_config: true,
+            ..test_args()
+        };
+        let runner = MockRunner::new(vec![output(0, POLL_OUTPUT, "")]);
+        let (metrics, _) = poll_host(&runner, &Host::new("web01"), &args, None, None).await;
+        assert_eq!(metrics.ssh_config.as_deref(), Some("none (-F /dev/null)"));
+
+        let runner = MockRunner::new(vec![output(0, POLL_OUTPUT, "")]);
+        let (metrics, _) = poll_host(&runner, &Host::new("localhost"), &args, None, None).await;
+        assert_eq!(metrics.ssh_config, None);
+    }
+
+    #[test]
+    fn test_proxy_command_precedence() {
+        let proxy = |command: &str| SshSettings {
+            proxy_command: Some(command.into()),
+            ..Default::default()
+        };
+        let args = ResolvedArgs {
+            config_ssh: proxy("nc -x localhost:1080  %h %p"),
+            group_ssh: HashMap::from([(
+                "dc2".to_string(),
+                proxy("nc -x localhost:1081 %h %p"),
+            )]),
+            ..test_args()
+        };
+        let proxy_arg = |host: &Host| {
+            cmd_args(&build_command(host, &args, true).unwrap())
+                .into_iter()
+                .find(|a| a.starts_with("ProxyCommand="))
+        };
+
+        // Passed as one argument, double space included
+        let mut host = Host::new("web01");
+        assert_eq!(proxy_arg(&host).unwrap(), "ProxyCommand=nc -x localhost:1080  %h %p");
+
+        host.groups = vec!["dc2".into()];
+        assert_eq!(proxy_arg(&host).unwrap(), "ProxyCommand=nc -x localhost:1081 %h %p");
+
+        host.apply_group_var("ansible_ssh_proxy_command", "ssh -W %h:%p gw");
+        assert_eq!(proxy_arg(&host).unwrap(), "ProxyCommand=ssh -W %h:%p gw");
+
+        host.apply_host_var("ansible_ssh_proxy_command", "");
+        assert_eq!(proxy_arg(&host), None);
+    }
+
+    #[test]
+    fn test_per_host_connect_timeout() {
+        let host = Host::new("web01");
+        assert!(cmd_args(&build_command(&host, &test_args(), true).unwrap())
+            .contains(&"ConnectTimeout=5".to_string()));
+
+        let mut host = Host::new("far01");
+        host.apply_group_var("ansimon_ssh_timeout", "30");
+        assert!(cmd_args(&build_command(&host, &test_args(), true).unwrap())
+            .contains(&"ConnectTimeout=30".to_string()));
+
+        host.apply_host_var("ansible_ssh_connect_timeout", "2");
+        assert!(cmd_args(&build_command(&host, &test_args(), true).unwrap())
+            .contains(&"ConnectTimeout=2".to_string()));
+    }
+
+    #[test]
+    fn test_multiplexing_options() {
+        let host = Host::new("web01");
+        assert!(!cmd_args(&build_command(&host, &test_args(), true).unwrap())
+            .contains(&"ControlMaster=auto".to_string()));
+
+        let args = ResolvedArgs {
+            ssh_multiplexing: true,
+            ..test_args()
+        };
+        let cmd_line = cmd_args(&build_command(&host, &args, true).unwrap());
+        assert!(cmd_line.contains(&"ControlMaster=auto".to_string()));
+        let path = control_path(&host, &args);
+        assert!(path.starts_with(control_dir()));
+        assert!(cmd_line.contains(&format!("ControlPath={}", path.display())));
+        assert!(cmd_line.contains(&"ControlPersist=60s".to_string()));
+    }
+
+    #[test]
+    fn test_secure_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let base = std::env::temp_dir().join(format!("ansimon-secure-{}", std::process::id()));
+        std::fs::create_dir_all(&base).unwrap();
+        let mode = |path: &std::path::Path| {
+            std::fs::metadata(path).unwrap().permissions().mode() & 0o777
+        };
+
+        let fresh = base.join("fresh");
+        secure_dir(&fresh).unwrap();
+        assert_eq!(mode(&fresh), 0o700);
+
+        // A leftover directory of ours is tightened
+        let loose = base.join("loose");
+        std::fs::create_dir(&loose).unwrap();
+        std::fs::set_permissions(&loose, std::fs::Permissions::from_mode(0o777)).unwrap();
+        secure_dir(&loose).unwrap();
+        assert_eq!(mode(&loose), 0o700);
+
+        // A symlink planted in its place is refused, not followed
+        let link = base.join("link");
+        std::os::unix::fs::symlink(&loose, &link).unwrap();
+        let err = secure_dir(&link).unwrap_err();
+        std::fs::remove_dir_all(&base).unwrap();
+        assert!(err.to_string().contains("not a directory owned by the current user"));
+    }
+
+    #[test]
+    fn test_interactive_command() {
+        let mut host = Host::new("web01");
+        host.ansible_user = Some("deploy".into());
+        host.ansible_port = Some(2222);
+        host.vars.insert("ansible_ssh_common_args".into(), "-o ProxyJump=bastion".into());
+        let args = ResolvedArgs {
+            ssh_multiplexing: true,
+            ..test_args()
+        };
+        let cmd = interactive_command(&host, &args).unwrap();
+        assert_eq!(program(&cmd), "ssh");
+        let cmd_line = cmd_args(&cmd);
+        assert!(!cmd_line.contains(&"BatchMode=yes".to_string()));
+        assert!(cmd_line.contains(&"ControlMaster=auto".to_string()));
+        assert!(cmd_line.contains(&"ProxyJump=bastion".to_string()));
+        assert!(cmd_line.windows(2).any(|w| w[0] == "-p" && w[1] == "2222"));
+        // No remote command: the target is last, so ssh opens a login shell
+        assert_eq!(cmd_line.last().map(String::as_str), Some("deploy@web01"));
+    }
+
+    #[test]
+    fn test_host_key_options() {
+        let mut host = Host::new("web01");
+        let options = |host: &Host, args: &ResolvedArgs| host_key_options(host, args).join(" ");
+        assert_eq!(options(&host, &test_args()), "-o StrictHostKeyChecking=accept-new");
+
+        let lab = ResolvedArgs {
+            host_key_checking: HostKeyChecking::Off,
+            ..test_args()
+        };
+        assert_eq!(
+            options(&host, &lab),
+            "-o StrictHostKeyChecking=no -o UserKnownHostsFile=/dev/null"
+        );
+
+        let pinned = ResolvedArgs {
+            known_hosts_file: Some("/etc/ansimon/known_hosts".into()),
+            ..lab
+        };
+        host.vars.insert("ansimon_host_key_checking".into(), "strict".into());
+        assert_eq!(
+            options(&host, &pinned),
+            "-o StrictHostKeyChecking=yes -o UserKnownHostsFile=/etc/ansimon/known_hosts"
+        );
+    }
+
+    #[test]
+    fn test_classify_error() {
+        let cases = [
+            ("deploy@web01: Permission denied (publickey).", Some(SshErrorKind::Auth)),
+            ("ssh: connect to host web01 port 22: Connection timed out", Some(SshErrorKind::Timeout)),
+            ("ssh: connect to host web01 port 22: Operation timed out", Some(SshErrorKind::Timeout)),
+            ("ssh: connect to host web01 port 22: Connection refused", Some(SshErrorKind::Refused)),
+            ("ssh: connect to host web01 port 22: No route to host", Some(SshErrorKind::Unreachable)),
+            ("ssh: Could not resolve hostname web01: Name or service not known", Some(SshErrorKind::Dns)),
+            (
+                "ssh: Could not resolve hostname web01: nodename nor servname provided, or not known",
+                Some(SshErrorKind::Dns),
+            ),
+            ("kex_exchange_identification: read: Connection reset by peer", None),
+            (
+                "@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@\n\
+                 @    WARNING: REMOTE HOST IDENTIFICATION HAS CHANGED!     @\n\
+                 Host key for web01 has changed and you have requested strict checking.\n\
+                 Host key verification failed.",
+                Some(SshErrorKind::HostKeyChanged),
+            ),
+        ];
+        for (stderr, kind) in cases {
+            assert_eq!(classify_error(stderr), kind, "{stderr}");
+        }
+    }
+
+    #[test]
+    fn test_only_network_errors_are_transient() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let failed = |stderr: &str| std::process::Output {
+            status: std::process::ExitStatus::from_raw(255 << 8),
+            stdout: Vec::new(),
+            stderr: stderr.as_bytes().to_vec(),
+        };
+        assert!(is_transient(&failed("ssh: connect to host web01 port 22: Connection timed out")));
+        assert!(is_transient(&failed("Connection reset by 10.0.0.1 port 22")));
+        assert!(!is_transient(&failed("deploy@web01: Permission denied (publickey).")));
+        assert!(!is_transient(&failed("sh: 1: df: not found")));
+    }
+
+    #[test]
+    fn test_failure_kind() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let exited = |code: i32, stderr: &str| std::process::Output {
+            status: std::process::ExitStatus::from_raw(code << 8),
+            stdout: Vec::new(),
+            stderr: stderr.as_bytes().to_vec(),
+        };
+        let denied = "deploy@web01: Permission denied (publickey).";
+        assert_eq!(failure_kind(&exited(255, denied)), Some(SshErrorKind::Auth));
+        // Exit codes other than 255 come from the remote command, whatever it printed
+        assert_eq!(
+            failure_kind(&exited(1, "cat: /proc/x: Permission denied")),
+            Some(SshErrorKind::Command)
+        );
+        assert_eq!(failure_kind(&exited(127, "sh: df: not found")), Some(SshErrorKind::Command));
+        assert_eq!(failure_kind(&exited(255, "something odd")), None);
+
+        // Through sshpass, only a second password prompt means the password
+        // was rejected; a remote command's status passes through as usual
+        let sent = "SSHPASS: searching for password prompt using match \"assword\"\n\
+                    SSHPASS: detected prompt. Sending password.";
+        let rejected =
+            format!("{sent}\nSSHPASS: detected prompt, again. Wrong password. Terminating.");
+        assert_eq!(failure_kind(&exited(5, &rejected)), Some(SshErrorKind::Auth));
+        assert_eq!(failure_kind(&exited(5, sent)), Some(SshErrorKind::Command));
+        assert_eq!(failure_kind(&exited(1, sent)), Some(SshErrorKind::Command));
+        let timed_out = format!("{sent}\nssh: connect to host web01 port 22: Connection timed out");
+        assert_eq!(failure_kind(&exited(255, &timed_out)), Some(SshErrorKind::Timeout));
+        assert_eq!(
+            ssh_stderr(&exited(255, &timed_out)),
+            "ssh: connect to host web01 port 22: Connection timed out"
+        );
+    }
+
+    #[test]
+    fn test_ipv6_target() {
+        let mut host = Host::new("web01");
+        host.ansible_host = Some("[2001:db8::10]".into());
+        host.ansible_user = Some("deploy".into());
+        let cmd = cmd_args(&build_command(&host, &test_args(), true).unwrap());
+        assert!(cmd.contains(&"deploy@2001:db8::10".to_string()));
+        assert!(cmd.windows(2).any(|w| w[0] == "-p" && w[1] == "22"));
+    }
+
+    #[test]
+    fn test_ssh_settings_precedence() {
+        let settings = |user: &str, port, key: &str| SshSettings {
+            user: Some(user.into()),
+            port: Some(port),
+            key: Some(key.into()),
+            proxy_command: None,
+        };
+        let mut args = ResolvedArgs {
+            config_ssh: settings("ops", 2200, "/keys/default"),
+            group_ssh: HashMap::from([(
+                "legacy".to_string(),
+                settings("root", 2222, "/keys/legacy"),
+            )]),
+            ..test_args()
+        };
+        let mut host = Host::new("web01");
+        host.groups = vec!["web".into(), "legacy".into()];
+
+        assert_eq!(ssh_target(&host, &args), ("root@web01".to_string(), 2222));
+        assert_eq!(args.config_key_for(&host), Some("/keys/legacy"));
+
+        host.groups = vec!["web".into()];
+        assert_eq!(ssh_target(&host, &args), ("ops@web01".to_string(), 2200));
+        assert_eq!(args.config_key_for(&host), Some("/keys/default"));
+
+        host.groups = vec!["legacy".into()];
+        host.ansible_user = Some("deploy".into());
+        host.ansible_port = Some(2022);
+        assert_eq!(ssh_target(&host, &args), ("deploy@web01".to_string(), 2022));
+
+        args.user = Some("admin".into());
+        args.port = Some(22);
+        assert_eq!(ssh_target(&host, &args), ("admin@web01".to_string(), 22));
+    }
+
+    #[test]
+    fn test_group_settings_follow_primary_group() {
+        let user = |name: &str| SshSettings {
+            user: Some(name.into()),
+            ..Default::default()
+        };
+        let args = ResolvedArgs {
+            group_ssh: HashMap::from([
+                ("web".to_string(), user("www")),
+                ("legacy".to_string(), user("root")),
+            ]),
+            ..test_args()
+        };
+        let mut host = Host::new("web01");
+        host.groups = vec!["web".into(), "legacy".into()];
+        assert_eq!(args.user_for(&host), Some("www"));
+
+        host.primary_group = Some("legacy".into());
+        assert_eq!(args.user_for(&host), Some("root"));
+
+        // A primary group without an entry falls back to the inventory order
+        host.primary_group = Some("prod".into());
+        assert_eq!(args.user_for(&host), Some("www"));
+    }
+
+    #[test]
+    fn test_resolve_preset() {
+        let presets = HashMap::from([
+            ("prod".to_string(), "web*,!web03".to_string()),
+            ("db".to_string(), "db*".to_string()),
+        ]);
+        let resolve =
+            |limit: Option<&str>| crate::resolve_preset(limit.map(String::from), &presets);
+
+        assert_eq!(resolve(Some("preset:prod")).unwrap().as_deref(), Some("web*,!web03"));
+        // Anything without the prefix is a plain pattern, even a preset's name
+        assert_eq!(resolve(Some("prod")).unwrap().as_deref(), Some("prod"));
+        assert_eq!(resolve(None).unwrap(), None);
+
+        let err = resolve(Some("preset:stage")).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown filter preset 'stage' (available: db, prod)");
+        let err = crate::resolve_preset(Some("preset:prod".into()), &HashMap::new()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown filter preset 'prod': no filter_presets in the config"
+        );
+    }
+
+    #[test]
+    fn test_expand_home() {
+        let home = Some("/home/deploy");
+        assert_eq!(expand_home("~/.ssh/id", home), PathBuf::from("/home/deploy/.ssh/id"));
+        assert_eq!(expand_home("$HOME/.ssh/id", home), PathBuf::from("/home/deploy/.ssh/id"));
+        assert_eq!(expand_home("${HOME}/id", home), PathBuf::from("/home/deploy/id"));
+        assert_eq!(expand_home("~", home), PathBuf::from("/home/deploy"));
+        // ~user and lookalike variables are left alone
+        assert_eq!(expand_home("~alice/id", home), PathBuf::from("~alice/id"));
+        assert_eq!(expand_home("$HOMER/id", home), PathBuf::from("$HOMER/id"));
+        assert_eq!(expand_home("~/id", None), PathBuf::from("~/id"));
+    }
+
+    #[test]
+    fn test_key_file_relative_to_inventory() {
+        let dir = std::env::temp_dir().join(format!("ansimon-keys-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("keys")).unwrap();
+        std::fs::write(dir.join("keys/deploy"), "").unwrap();
+        let args = ResolvedArgs {
+            inventory: dir.join("hosts.ini").display().to_string(),
+            ..test_args()
+        };
+
+        let mut host = Host::new("web01");
+        host.ansible_ssh_private_key_file = Some("keys/deploy".into());
+        let cmd = cmd_args(&build_command(&host, &args, true).unwrap());
+        let key = dir.join("keys/deploy").display().to_string();
+        assert!(cmd.windows(2).any(|w| w[0] == "-i" && w[1] == key));
+
+        host.ansible_ssh_private_key_file = Some("keys/missing".into());
+        let err = build_command(&host, &args, true).unwrap_err().to_string();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(err, format!("key file not found: {}", dir.join("keys/missing").display()));
+    }
+
+    #[test]
+    fn test_refresh_commands_mark_hosts_due() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let mut hosts = vec![Host::new("web01"), Host::new("web02"), Host::new("db01")];
+        let now = Instant::now();
+        let interval = Duration::from_secs(10);
+        let polled_all = || -> HashMap<String, Instant> {
+            ["web01", "web02", "db01"]
+                .iter()
+                .map(|h| (h.to_string(), now))
+                .collect()
+        };
+        let due = |hosts: &[Host], polled: &HashMap<String, Instant>, busy: &[&str]| {
+            let busy: Vec<String> = busy.iter().map(|b| b.to_string()).collect();
+            due_hosts(hosts, polled, &busy.iter().collect(), interval, now)
+                .iter()
+                .map(|h| h.name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let mut polled = polled_all();
+        apply_command(PollerCommand::RefreshHost("web02".into()), &mut hosts, &mut polled, &tx);
+        assert_eq!(due(&hosts, &polled, &[]), vec!["web02"]);
+
+        // web01 is being polled already, so it isn't polled twice
+        let mut polled = polled_all();
+        apply_command(PollerCommand::RefreshAll, &mut hosts, &mut polled, &tx);
+        assert_eq!(due(&hosts, &polled, &["web01"]), vec!["web02", "db01"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_frozen_host_is_not_polled() {
+        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let hosts = vec![Host::new("web01"), Host::new("web02")];
+        let poll = |host: Host, _: Arc<ResolvedArgs>, _: Option<CounterSample>, _| async move {
+            (HostMetrics::new(&host.name), None)
+        };
+        let args = Arc::new(ResolvedArgs { forks: 10, ..test_args() });
+        let poller = tokio::spawn(run_poller(hosts, args, 10, cmd_rx, tx, poll));
+
+        let begin = Instant::now();
+        let mut results = Vec::new();
+        cmd_tx.send(PollerCommand::Freeze("web02".into(), true)).unwrap();
+        while results.len() < 5 {
+            if let Some(SshMessage::Result(m)) = rx.recv().await {
+                results.push((m.host_name, (Instant::now() - begin).as_secs()));
+            }
+        }
+        cmd_tx.send(PollerCommand::Freeze("web02".into(), false)).unwrap();
+        while results.len() < 6 {
+            if let Some(SshMessage::Result(m)) = rx.recv().await {
+                results.push((m.host_name, (Instant::now() - begin).as_secs()));
+            }
+        }
+        let _ = cmd_tx.send(PollerCommand::Shutdown);
+        poller.await.unwrap();
+
+        // Polled once before the freeze took effect, then only once unfrozen
+        let web02: Vec<u64> =
+            results.iter().filter(|(n, _)| n == "web02").map(|(_, t)| *t).collect();
+        assert_eq!(web02, vec![0, 30]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reload_drops_freeze_of_removed_host() {
+        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let hosts = vec![Host::new("web01"), Host::new("web02")];
+        let poll = |host: Host, _: Arc<ResolvedArgs>, _: Option<CounterSample>, _| async move {
+            (HostMetrics::new(&host.name), None)
+        };
+        let args = Arc::new(ResolvedArgs { forks: 10, ..test_args() });
+        let poller = tokio::spawn(run_poller(hosts.clone(), args, 10, cmd_rx, tx, poll));
+
+        let begin = Instant::now();
+        let mut results = Vec::new();
+        cmd_tx.send(PollerCommand::Freeze("web02".into(), true)).unwrap();
+        while results.len() < 3 {
+            if let Some(SshMessage::Result(m)) = rx.recv().await {
+                results.push((m.host_name, (Instant::now() - begin).as_secs()));
+            }
+        }
+        // Reloaded without web02, then with it again
+        cmd_tx.send(PollerCommand::SetHosts(vec![Host::new("web01")])).unwrap();
+        cmd_tx.send(PollerCommand::SetHosts(hosts)).unwrap();
+        while results.len() < 7 {
+            if let Some(SshMessage::Result(m)) = rx.recv().await {
+                results.push((m.host_name, (Instant::now() - begin).as_secs()));
+            }
+        }
+        let _ = cmd_tx.send(PollerCommand::Shutdown);
+        poller.await.unwrap();
+
+        // Polled again as soon as it came back, not left frozen
+        let web02: Vec<u64> =
+            results.iter().filter(|(n, _)| n == "web02").map(|(_, t)| *t).collect();
+        assert_eq!(web02, vec![0, 10, 20]);
+    }
+
+    #[tokio::test]
+    async fn test_hung_command_times_out() {
+        let mut host = Host::new("localhost");
+        host.apply_host_var("ansible_connection", "local");
+        let args = ResolvedArgs {
+            command_timeout: 1,
+            custom_metrics: vec![crate::config::CustomMetric {
+                name: "hang".to_string(),
+                command: "sleep 30".to_string(),
+                unit: String::new(),
+            }],
+            ..test_args()
+        };
+        let (metrics, sample) = poll_host(&OpenSsh, &host, &args, None, None).await;
+        assert_eq!(metrics.status, HostStatus::Down);
+        assert_eq!(metrics.error.as_deref(), Some("command timed out after 1s"));
+        assert_eq!(metrics.error_kind, Some(SshErrorKind::Timeout));
+        assert!(sample.is_none());
+
+        // Remote hosts also get their connect timeout
+        let mut remote = Host::new("web01");
+        remote.apply_host_var("ansimon_ssh_timeout", "3");
+        assert_eq!(poll_timeout(&remote, &args), Duration::from_secs(4));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_poll_success() {
+        let host = Host::new("web01");
+        let runner = MockRunner {
+            delay: Duration::from_millis(1500),
+            ..MockRunner::new(vec![output(0, POLL_OUTPUT, "")])
+        };
+        let (metrics, sample) = poll_host(&runner, &host, &test_args(), None, None).await;
+        assert_eq!(metrics.status, HostStatus::Up);
+        assert_eq!(metrics.error, None);
+        assert_eq!(metrics.metrics.as_ref().unwrap().disk_percent, 30.0);
+        // The wall time less the 1.2s the command ran on the host
+        assert_eq!(metrics.ssh_latency_ms, Some(300));
+        assert!(sample.is_some());
+        // A first poll takes a baseline sample
+        assert!(runner.scripts.lock().unwrap()[0].contains("===STAT_BASE"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_poll_nonzero_exit() {
+        // A command failing on a local host is the command's fault
+        let local = Host::new("localhost");
+        let runner = MockRunner::new(vec![output(1, "", "sh: 1: head: not found\n")]);
+        let (metrics, sample) = poll_host(&runner, &local, &test_args(), None, None).await;
+        assert_eq!(metrics.status, HostStatus::Down);
+        assert_eq!(metrics.error.as_deref(), Some("sh: 1: head: not found"));
+        assert_eq!(metrics.error_kind, Some(SshErrorKind::Command));
+        assert!(metrics.last_updated.is_some());
+        assert!(sample.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_poll_classifies_stderr() {
+        // Authentication failures are never retried
+        let host = Host::new("web01");
+        let args = ResolvedArgs { retry_attempts: 2, ..test_args() };
+        let runner =
+            MockRunner::new(vec![output(255, "", "deploy@web01: Permission denied (publickey).")]);
+        let (metrics, _) = poll_host(&runner, &host, &args, None, None).await;
+        assert_eq!(metrics.error_kind, Some(SshErrorKind::Auth));
+        assert_eq!(runner.runs(), 1);
+
+        // Network errors are, and say how many attempts were made
+        let refused = "ssh: connect to host web01 port 22: Connection refused";
+        let runner = MockRunner::new(vec![output(255, "", refused), output(255, "", refused)]);
+        let args = ResolvedArgs { retry_attempts: 1, ..test_args() };
+        let (metrics, _) = poll_host(&runner, &host, &args, None, None).await;
+        assert_eq!(metrics.status, HostStatus::Down);
+        assert_eq!(metrics.error_kind, Some(SshErrorKind::Refused));
+        assert_eq!(metrics.error, Some(format!("{refused} (after 2 attempts)")));
+        assert_eq!(runner.runs(), 2);
+
+        // A retry that gets through counts as a success
+        let runner = MockRunner::new(vec![output(255, "", refused), output(0, POLL_OUTPUT, "")]);
+        let (metrics, _) = poll_host(&runner, &host, &args, None, None).await;
+        assert_eq!(metrics.status, HostStatus::Up);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retries_wait_for_rate_limiter() {
+        let host = Host::new("web01");
+        let args = ResolvedArgs { retry_attempts: 1, retry_delay_ms: 100, ..test_args() };
+        let refused = "ssh: connect to host web01 port 22: Connection refused";
+        let runner = MockRunner::new(vec![output(255, "", refused), output(0, POLL_OUTPUT, "")]);
+        let limiter = RateLimiter::new(1).unwrap();
+        let begin = Instant::now();
+        // The poller took the token for the first attempt
+        limiter.acquire().await;
+        let (metrics, _) = poll_host(&runner, &host, &args, None, Some(&limiter)).await;
+        assert_eq!(metrics.status, HostStatus::Up);
+        // The retry waited out the second's spacing, not just its backoff
+        assert_eq!(Instant::now() - begin, Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_poll_times_out() {
+        let host = Host::new("web01");
+        let args = ResolvedArgs { command_timeout: 10, retry_attempts: 3, ..test_args() };
+        let runner = MockRunner {
+            delay: Duration::from_secs(60),
+            ..MockRunner::new(vec![output(0, POLL_OUTPUT, "")])
+        };
+        let begin = Instant::now();
+        let (metrics, sample) = poll_host(&runner, &host, &args, None, None).await;
+        // The connect timeout plus the command budget, and no retry
+        assert_eq!(Instant::now() - begin, Duration::from_secs(15));
+        assert_eq!(metrics.status, HostStatus::Down);
+        assert_eq!(metrics.error.as_deref(), Some("command timed out after 15s"));
+        assert_eq!(metrics.error_kind, Some(SshErrorKind::Timeout));
+        assert_eq!(runner.runs(), 1);
+        assert!(sample.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_poll_parse_failure() {
+        let host = Host::new("web01");
+        let runner = MockRunner::new(vec![output(0, "Welcome to web01!\n", "")]);
+        let (metrics, sample) = poll_host(&runner, &host, &test_args(), None, None).await;
+        assert_eq!(metrics.status, HostStatus::Down);
+        assert_eq!(metrics.error_kind, Some(SshErrorKind::Parse));
+        assert!(metrics.error.unwrap().starts_with("Parse error: Missing ===MEMINFO"));
+        // The connection itself worked
+        assert!(metrics.ssh_latency_ms.is_some());
+        assert!(sample.is_none());
+
+        // A later poll compares against the previous sample instead
+        let (_, previous) = poll_host(
+            &MockRunner::new(vec![output(0, POLL_OUTPUT, "")]),
+            &host,
+            &test_args(),
+            None,
+            None,
+        )
+        .await;
+        let runner = MockRunner::new(vec![output(0, POLL_OUTPUT, "")]);
+        poll_host(&runner, &host, &test_args(), previous.as_ref(), None).await;
+        assert!(!runner.scripts.lock().unwrap()[0].contains("_BASE"));
+    }
+
+    #[tokio::test]
+    async fn test_poll_local_collector() {
+        let mut host = Host::new("edge01");
+        host.apply_host_var("ansible_host", "10.8.0.5");
+        host.apply_host_var("ansimon_collector", "local");
+        host.apply_host_var("ansimon_local_command", "cat /var/cache/ansimon/{host}.out");
+        let runner = MockRunner::new(vec![output(0, POLL_OUTPUT, "")]);
+        let (metrics, sample) = poll_host(&runner, &host, &test_args(), None, None).await;
+        assert_eq!(metrics.status, HostStatus::Up);
+        assert!(sample.is_some());
+        // Nothing was connected to, so there's no latency
+        assert_eq!(metrics.ssh_latency_ms, None);
+        assert_eq!(
+            runner.scripts.lock().unwrap()[0],
+            "cat /var/cache/ansimon/10.8.0.5.out"
+        );
+        let cmd = build_command(&host, &test_args(), true).unwrap();
+        assert_eq!(cmd.as_std().get_program(), "sh");
+
+        host.apply_host_var("ansimon_local_format", "prometheus");
+        let runner = MockRunner::new(vec![output(0, POLL_OUTPUT, "")]);
+        let (metrics, _) = poll_host(&runner, &host, &test_args(), None, None).await;
+        assert_eq!(metrics.error_kind, Some(SshErrorKind::Parse));
+
+        let runner = MockRunner::new(vec![output(7, "", "curl: (7) Failed to connect")]);
+        let (metrics, _) = poll_host(&runner, &host, &test_args(), None, None).await;
+        assert_eq!(metrics.error_kind, Some(SshErrorKind::Command));
+    }
+
+    #[tokio::test]
+    async fn test_local_collector_needs_command() {
+        let mut host = Host::new("edge01");
+        host.apply_host_var("ansimon_collector", "local");
+        let runner = MockRunner::new(vec![]);
+        let (metrics, _) = poll_host(&runner, &host, &test_args(), None, None).await;
+        assert_eq!(metrics.status, HostStatus::Down);
+        assert_eq!(
+            metrics.error.as_deref(),
+            Some("ansimon_collector=local needs an ansimon_local_command")
+        );
+        assert!(runner.scripts.lock().unwrap().is_empty());
+
+        let metrics = check_host(&runner, &host, &test_args()).await;
+        assert_eq!(metrics.status, HostStatus::Down);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_host_does_not_delay_others() {
+        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let hosts = vec![Host::new("slow01"), Host::new("web01")];
+        let args = Arc::new(ResolvedArgs {
+            forks: 10,
+            ..test_args()
+        });
+        // slow01 hangs for 25s (say, on DNS); web01 answers at once
+        let poll = |host: Host, _: Arc<ResolvedArgs>, _: Option<CounterSample>, _| async move {
+            if host.name == "slow01" {
+                tokio::time::sleep(Duration::from_secs(25)).await;
+            }
+            (HostMetrics::new(&host.name), None)
+        };
+        let poller = tokio::spawn(run_poller(hosts, args, 10, cmd_rx, tx, poll));
+
+        let begin = Instant::now();
+        let mut results = Vec::new();
+        while results.len() < 5 {
+            if let Some(SshMessage::Result(m)) = rx.recv().await {
+                results.push((m.host_name, (Instant::now() - begin).as_secs()));
+            }
+        }
+        let _ = cmd_tx.send(PollerCommand::Shutdown);
+        poller.await.unwrap();
+
+        let at = |name: &str| {
+            results.iter().filter(|(n, _)| n == name).map(|(_, t)| *t).collect::<Vec<_>>()
+        };
+        assert_eq!(at("web01"), vec![0, 10, 20, 30]);
+        assert_eq!(at("slow01"), vec![25]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_connection_rate_limit() {
+        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let hosts: Vec<Host> = (1..=20).map(|i| Host::new(&format!("web{i:02}"))).collect();
+        let args = Arc::new(ResolvedArgs {
+            forks: 50,
+            max_connections_per_sec: 5,
+            ..test_args()
+        });
+        let poll = |host: Host, _: Arc<ResolvedArgs>, _: Option<CounterSample>, _| async move {
+            (HostMetrics::new(&host.name), None)
+        };
+        let poller = tokio::spawn(run_poller(hosts, args, 60, cmd_rx, tx, poll));
+
+        let begin = Instant::now();
+        let mut started = Vec::new();
+        while started.len() < 20 {
+            if let Some(SshMessage::Connecting(_)) = rx.recv().await {
+                started.push((Instant::now() - begin).as_millis());
+            }
+        }
+
+        // A refresh waits its turn behind the scheduled connections
+        let _ = cmd_tx.send(PollerCommand::RefreshHost("web01".to_string()));
+        while !matches!(rx.recv().await, Some(SshMessage::Connecting(_))) {}
+        let refreshed = (Instant::now() - begin).as_millis();
+        let _ = cmd_tx.send(PollerCommand::Shutdown);
+        poller.await.unwrap();
+
+        // Plenty of forks are free, yet starts are 200ms apart
+        assert_eq!(started[0], 0);
+        assert!(started.windows(2).all(|w| w[1] - w[0] == 200));
+        assert_eq!(started[19], 3800);
+        assert_eq!(refreshed, 4000);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_kills_in_flight_commands() {
+        let (pid_tx, pid_rx) = tokio::sync::oneshot::channel();
+        let mut tasks = JoinSet::new();
+        tasks.spawn(async move {
+            let mut cmd = Command::new("sleep");
+            cmd.arg("30").kill_on_drop(true);
+            let child = cmd.spawn().unwrap();
+            let _ = pid_tx.send(child.id().unwrap());
+            let _ = child.wait_with_output().await;
+        });
+        let pid = pid_rx.await.unwrap();
+
+        shutdown(&mut tasks).await;
+        assert!(tasks.is_empty());
+        wait_until_gone(pid).await;
+    }
+
+    /// Wait for a process to exit, failing after a second. Killed processes
+    /// linger as zombies until reaped, which counts as gone.
+    async fn wait_until_gone(pid: u32) {
+        let gone = || match std::fs::read_to_string(format!("/proc/{pid}/stat")) {
+            Ok(stat) => stat.rsplit(')').next().is_some_and(|s| s.trim_start().starts_with('Z')),
+            Err(_) => true,
+        };
+        for _ in 0..100 {
+            if gone() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("process {pid} is still running");
+    }
+
+    /// Pid of a running process with this exact command line.
+    fn find_process(cmdline: &[&str]) -> Option<u32> {
+        let wanted: String = cmdline.iter().map(|arg| format!("{arg}\0")).collect();
+        std::fs::read_dir("/proc")
+            .ok()?
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+            .find(|pid| {
+                std::fs::read_to_string(format!("/proc/{pid}/cmdline")).is_ok_and(|c| c == wanted)
+            })
+    }
+
+    #[tokio::test]
+    async fn test_dropped_run_kills_its_process() {
+        let host = Host::new("localhost");
+        let run = tokio::spawn(async move {
+            let _ = OpenSsh.run(&host, &test_args(), "exec sleep 4242").await;
+        });
+        let mut pid = None;
+        for _ in 0..100 {
+            pid = find_process(&["sleep", "4242"]);
+            if pid.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let pid = pid.expect("sleep never started");
+
+        // As when the poller aborts a poll
+        run.abort();
+        assert!(run.await.unwrap_err().is_cancelled());
+        wait_until_gone(pid).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancelled_polls_are_reported() {
+        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let hosts = vec![Host::new("web01"), Host::new("web02")];
+        let args = Arc::new(ResolvedArgs { forks: 10, ..test_args() });
+        // web01's poll panics; web02's never finishes
+        let poll = |host: Host, _: Arc<ResolvedArgs>, _: Option<CounterSample>, _| async move {
+            if host.name == "web01" {
+                panic!("poll of {} failed", host.name);
+            }
+            std::future::pending::<()>().await;
+            (HostMetrics::new(&host.name), None)
+        };
+        let poller = tokio::spawn(run_poller(hosts, args, 10, cmd_rx, tx, poll));
+
+        let mut cancelled = Vec::new();
+        while cancelled.is_empty() {
+            if let Some(SshMessage::Cancelled(name)) = rx.recv().await {
+                cancelled.push(name);
+            }
+        }
+        let _ = cmd_tx.send(PollerCommand::Shutdown);
+        poller.await.unwrap();
+        while let Ok(msg) = rx.try_recv() {
+            if let SshMessage::Cancelled(name) = msg {
+                cancelled.push(name);
+            }
+        }
+        assert_eq!(cancelled, ["web01", "web02"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stagger_spreads_starts() {
+        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let hosts: Vec<Host> = (1..=5).map(|i| Host::new(&format!("web{i:02}"))).collect();
+        let args = Arc::new(ResolvedArgs {
+            forks: 10,
+            stagger: true,
+            ..test_args()
+        });
+        let poll = |host: Host, _: Arc<ResolvedArgs>, _: Option<CounterSample>, _| async move {
+            (HostMetrics::new(&host.name), None)
+        };
+        let poller = tokio::spawn(run_poller(hosts, args, 10, cmd_rx, tx, poll));
+
+        let begin = Instant::now();
+        let mut started = Vec::new();
+        while started.len() < 5 {
+            if let Some(SshMessage::Connecting(name)) = rx.recv().await {
+                started.push((name, (Instant::now() - begin).as_millis()));
+            }
+        }
+        let _ = cmd_tx.send(PollerCommand::Shutdown);
+        poller.await.unwrap();
+
+        // The first cycle's starts are spread over the capped 5s window
+        let expected: Vec<(String, u128)> =
+            (1..=5).map(|i| (format!("web{i:02}"), (i - 1) * 1000)).collect();
+        assert_eq!(started, expected);
+
+        assert_eq!(stagger_delay(4, 5, Duration::from_secs(10), false), Duration::ZERO);
+        assert_eq!(stagger_delay(1, 2, Duration::from_secs(2), true), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_retry_delay_doubles() {
+        let delays: Vec<u128> = (0..4).map(|a| retry_delay(500, a).as_millis()).collect();
+        assert_eq!(delays, vec![500, 1000, 2000, 4000]);
+        assert_eq!(retry_delay(0, 3).as_millis(), 0);
+    }
+
+    #[test]
+    fn test_disabled_host_is_never_due() {
+        let mut off = Host::new("off01");
+        off.apply_host_var("ansimon_disabled", "true");
+        let mut slow = Host::new("wan01");
+        slow.apply_host_var("ansimon_interval", "60");
+        let hosts = vec![off, slow, Host::new("web01")];
+
+        let start = Instant::now();
+        let names = |due: Vec<&Host>| due.iter().map(|h| h.name.clone()).collect::<Vec<_>>();
+        let interval = Duration::from_secs(10);
+        let idle = HashSet::new();
+        assert_eq!(
+            names(due_hosts(&hosts, &HashMap::new(), &idle, interval, start)),
+            vec!["wan01", "web01"]
+        );
+
+        let polled: HashMap<String, Instant> =
+            [("wan01".to_string(), start), ("web01".to_string(), start)].into();
+        let later = start + Duration::from_secs(30);
+        assert_eq!(names(due_hosts(&hosts, &polled, &idle, interval, later)), vec!["web01"]);
+        let much_later = start + Duration::from_secs(60);
+        assert_eq!(
+            names(due_hosts(&hosts, &polled, &idle, interval, much_later)),
+            vec!["wan01", "web01"]
+        );
+    }
+
+    #[test]
+    fn test_disk_path_from_vars() {
+        let mut host = Host::new("web01");
+        host.apply_group_var("ansimon_disk_path", "/data");
+        let cmd = cmd_args(&build_command(&host, &test_args(), true).unwrap());
+        assert!(cmd.last().unwrap().contains("df -P '/data'"));
+    }
+
+    #[test]
+    fn test_become_wraps_command() {
+        let mut host = Host::new("web01");
+        let script = |host: &Host, args: &ResolvedArgs| {
+            cmd_args(&build_command(host, args, true).unwrap()).pop().unwrap()
+        };
+        assert!(!script(&host, &test_args()).contains("sudo -n"));
+
+        let args = ResolvedArgs {
+            sudo: true,
+            ..test_args()
+        };
+        assert!(script(&host, &args).starts_with("if become_err=$(sudo -n true"));
+
+        host.apply_group_var("ansible_become", "yes");
+        host.apply_group_var("ansible_become_user", "monitor");
+        assert!(script(&host, &test_args()).contains("sudo -n -u 'monitor'"));
+    }
+
+    #[test]
+    fn test_control_path_is_short_and_distinct() {
+        let long = Host::new(&format!("{}.example.com", "a".repeat(200)));
+        let path = control_path(&long, &test_args());
+        assert_eq!(path.file_name().unwrap().len(), 16);
+
+        let mut other_port = long.clone();
+        other_port.apply_host_var("ansible_port", "2222");
+        assert_ne!(path, control_path(&other_port, &test_args()));
+    }
+
+    #[test]
+    fn test_jump_host_precedence() {
+        let proxy_jump = |host: &Host, args: &ResolvedArgs| {
+            cmd_args(&build_command(host, args, true).unwrap())
+                .into_iter()
+                .find(|a| a.starts_with("ProxyJump="))
+        };
+        let args = ResolvedArgs {
+            jump_host: Some("admin@bastion:2222".to_string()),
+            ..test_args()
+        };
+
+        let mut host = Host::new("web01");
+        assert_eq!(proxy_jump(&host, &test_args()), None);
+        assert_eq!(proxy_jump(&host, &args).as_deref(), Some("ProxyJump=admin@bastion:2222"));
+
+        host.apply_group_var("jump_host", "jump1,jump2");
+        assert_eq!(proxy_jump(&host, &args).as_deref(), Some("ProxyJump=jump1,jump2"));
+
+        host.apply_host_var("ansimon_jump_host", "");
+        assert_eq!(proxy_jump(&host, &args), None);
+    }
+
+    #[test]
+    fn test_failed_jump() {
+        let jump = "admin@bastion:2222,jump2";
+        assert_eq!(
+            failed_jump("ssh: connect to host bastion port 2222: Connection refused", jump),
+            Some("admin@bastion:2222")
+        );
+        assert_eq!(
+            failed_jump("ssh: Could not resolve hostname jump2: Name or service not known", jump),
+            Some("jump2")
+        );
+        assert_eq!(
+            failed_jump("ssh: connect to host web01 port 22: Connection timed out", jump),
+            None
+        );
+    }
+
+    #[test]
+    fn test_password_auth_uses_sshpass() {
+        let key_host = Host::new("web01");
+        let cmd = build_command(&key_host, &test_args(), true).unwrap();
+        assert_eq!(program(&cmd), "ssh");
+        assert!(cmd_args(&cmd).contains(&"BatchMode=yes".to_string()));
+
+        let mut pass_host = Host::new("switch01");
+        pass_host.apply_host_var("ansible_ssh_pass", "s3cret");
+        let cmd = build_command(&pass_host, &test_args(), true).unwrap();
+        assert_eq!(program(&cmd), "sshpass");
+        let args = cmd_args(&cmd);
+        assert_eq!(&args[..3], ["-v", "-e", "ssh"]);
+        assert!(!args.contains(&"BatchMode=yes".to_string()));
+        assert!(!args.iter().any(|a| a.contains("s3cret")));
+        let env: Vec<_> = cmd.as_std().get_envs().collect();
+        assert_eq!(env, vec![(std::ffi::OsStr::new("SSHPASS"), Some(std::ffi::OsStr::new("s3cret")))]);
+
+        let asked = ResolvedArgs {
+            ssh_password: Some("typed".to_string()),
+            ..test_args()
+        };
+        assert_eq!(program(&build_command(&key_host, &asked, true).unwrap()), "sshpass");
+    }
+
+    #[test]
+    fn test_invalid_ssh_args_error() {
+        let mut host = Host::new("web01");
+        host.apply_host_var("ansible_ssh_common_args", "-o 'ProxyJump=x");
+        assert!(build_command(&host, &test_args(), true).is_err());
+    }
+}
@@ -0,0 +1,594 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::os::fd::AsRawFd;
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::process::{ExitStatus, Output};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use ssh2::{BlockDirections, Channel, CheckResult, KnownHostFileKind, Session};
+use tokio::task;
+use tokio::time::Instant;
+
+use super::runner::{CommandRunner, OpenSsh};
+use crate::cli::ResolvedArgs;
+use crate::config::HostKeyChecking;
+use crate::inventory::types::Host;
+
+/// Longest wait before reconnecting to a host whose connects keep failing.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Runs scripts over in-process SSH sessions (libssh2) instead of an `ssh`
+/// process per poll: each host keeps one authenticated session, and every
+/// run opens an exec channel on it. Local hosts still run through `sh -c`.
+#[derive(Default)]
+pub struct Native {
+    slots: Mutex<HashMap<String, Arc<tokio::sync::Mutex<Slot>>>>,
+}
+
+/// A host's session, if it has one, and the backoff after failed connects.
+#[derive(Default)]
+struct Slot {
+    connection: Option<Connection>,
+    failures: u32,
+    /// Until when connects are skipped, and the failure they report meanwhile
+    backoff: Option<(Instant, Output)>,
+}
+
+struct Connection {
+    session: Session,
+    /// The session's socket, shut down to abort a blocked libssh2 call
+    socket: TcpStream,
+}
+
+impl Native {
+    fn slot(&self, key: &str) -> Arc<tokio::sync::Mutex<Slot>> {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        slots.entry(key.to_string()).or_default().clone()
+    }
+}
+
+impl CommandRunner for Native {
+    async fn run(
+        &self,
+        host: &Host,
+        args: &ResolvedArgs,
+        script: &str,
+    ) -> Result<io::Result<Output>> {
+        if !host.uses_ssh() {
+            return OpenSsh.run(host, args, script).await;
+        }
+        let target = Target::new(host, args)?;
+        let timeout = super::poll_timeout(host, args);
+        let slot = self.slot(&target.to_string());
+        // The poller runs one poll per host at a time, so this rarely waits
+        let mut slot = slot.lock().await;
+
+        // A session that has dropped since the last poll gets one fresh
+        // connect right away. A command that fails once it started ran on a
+        // live session, so it is this poll's failure and isn't run again.
+        if let Some(connection) = slot.connection.take() {
+            match exec(connection, script, timeout).await {
+                Ok((connection, output)) => {
+                    slot.connection = Some(connection);
+                    return Ok(Ok(output));
+                }
+                Err(ExecError::Closed(_)) => {}
+                Err(ExecError::Failed(e)) => return Ok(Err(e)),
+            }
+        }
+
+        if let Some((until, failure)) = &slot.backoff {
+            if Instant::now() < *until {
+                return Ok(Ok(failure.clone()));
+            }
+        }
+        let connection = match connect(target.clone(), timeout).await {
+            Ok(connection) => connection,
+            Err(failure) => {
+                let delay = super::retry_delay(args.retry_delay_ms, slot.failures);
+                slot.failures += 1;
+                slot.backoff = Some((Instant::now() + delay.min(MAX_BACKOFF), failure.clone()));
+                return Ok(Ok(failure));
+            }
+        };
+        slot.failures = 0;
+        slot.backoff = None;
+
+        match exec(connection, script, timeout).await {
+            Ok((connection, output)) => {
+                slot.connection = Some(connection);
+                Ok(Ok(output))
+            }
+            Err(ExecError::Closed(e)) => Ok(Ok(failure(format!(
+                "ssh: {}: {}",
+                target.address,
+                describe_io(&e)
+            )))),
+            Err(ExecError::Failed(e)) => Ok(Err(e)),
+        }
+    }
+}
+
+/// Where and how to connect to a host, resolved up front so the blocking
+/// half of a connect owns everything it needs.
+#[derive(Clone)]
+struct Target {
+    address: String,
+    port: u16,
+    user: String,
+    key: Option<PathBuf>,
+    password: Option<String>,
+    host_key_checking: HostKeyChecking,
+    known_hosts: Option<PathBuf>,
+    connect_timeout: Duration,
+}
+
+impl Target {
+    /// The connection settings for a host, the same ones the openssh backend
+    /// passes to ssh. Settings only ssh itself can honour are an error.
+    fn new(host: &Host, args: &ResolvedArgs) -> Result<Self> {
+        let unsupported = if super::jump_host(host, args).is_some() {
+            Some("jump hosts")
+        } else if args.proxy_command_for(host).is_some() {
+            Some("ProxyCommand")
+        } else if super::SSH_ARG_VARS.iter().any(|v| host.vars.contains_key(*v)) {
+            Some("ansible_ssh_*_args")
+        } else {
+            None
+        };
+        if let Some(what) = unsupported {
+            anyhow::bail!("ssh_backend native doesn't support {what}; use ssh_backend: openssh");
+        }
+
+        let home = std::env::var("HOME").ok();
+        let host_key_checking = host.host_key_checking().unwrap_or(args.host_key_checking);
+        let known_hosts = match (&args.known_hosts_file, host_key_checking) {
+            (Some(file), _) => Some(super::expand_home(file, home.as_deref())),
+            (None, HostKeyChecking::Off) => None,
+            (None, _) => home.as_deref().map(|h| PathBuf::from(h).join(".ssh/known_hosts")),
+        };
+        let user = args
+            .user_for(host)
+            .map(|u| u.to_string())
+            .or_else(|| std::env::var("USER").ok())
+            .unwrap_or_else(|| "root".to_string());
+        Ok(Self {
+            address: host.ssh_address().to_string(),
+            port: args.port_for(host),
+            user,
+            key: super::key_file(host, args)?,
+            password: super::password(host, args).map(|p| p.to_string()),
+            host_key_checking,
+            known_hosts,
+            connect_timeout: Duration::from_secs(host.ssh_timeout.unwrap_or(args.ssh_timeout)),
+        })
+    }
+
+    /// The entry for this host in a known_hosts file.
+    fn known_hosts_name(&self) -> String {
+        if self.port == 22 {
+            self.address.clone()
+        } else {
+            format!("[{}]:{}", self.address, self.port)
+        }
+    }
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}:{}", self.user, self.address, self.port)
+    }
+}
+
+/// Shuts a socket down when dropped, unless disarmed first, so a blocking
+/// libssh2 call whose poll was abandoned returns instead of running on.
+struct AbortOnDrop(Option<TcpStream>);
+
+impl AbortOnDrop {
+    fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        if let Some(socket) = &self.0 {
+            let _ = socket.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+/// Open a TCP connection, then handshake, check the host key and log in on
+/// a blocking thread. A failure comes back as ssh would report it.
+async fn connect(target: Target, timeout: Duration) -> Result<Connection, Output> {
+    let (address, port) = (target.address.clone(), target.port);
+    let socket = tokio::time::timeout(target.connect_timeout, async {
+        let addrs = tokio::net::lookup_host((address.as_str(), port)).await.map_err(|_| {
+            failure(format!(
+                "ssh: Could not resolve hostname {address}: Name or service not known"
+            ))
+        })?;
+        let mut last = None;
+        for addr in addrs {
+            match tokio::net::TcpStream::connect(addr).await {
+                Ok(socket) => return Ok(socket),
+                Err(e) => last = Some(e),
+            }
+        }
+        let reason = last.map_or("no address".to_string(), |e| e.to_string());
+        Err(failure(format!("ssh: connect to host {address} port {port}: {reason}")))
+    })
+    .await
+    .map_err(|_| {
+        failure(format!("ssh: connect to host {address} port {port}: Connection timed out"))
+    })??;
+
+    let socket = socket
+        .into_std()
+        .and_then(|s| s.set_nonblocking(false).map(|()| s))
+        .map_err(|e| failure(format!("ssh: {address}: {e}")))?;
+    let abort = AbortOnDrop(socket.try_clone().ok());
+    let established = task::spawn_blocking(move || establish(socket, &target, timeout)).await;
+    abort.disarm();
+    established.unwrap_or_else(|e| Err(failure(format!("ssh: {address}: {e}"))))
+}
+
+/// The blocking half of a connect: SSH handshake, host key check and login.
+fn establish(socket: TcpStream, target: &Target, timeout: Duration) -> Result<Connection, Output> {
+    let (address, port) = (&target.address, target.port);
+    let closed = || failure(format!("Connection closed by {address} port {port}"));
+
+    let mut session = Session::new().map_err(|_| closed())?;
+    session.set_timeout(millis(timeout));
+    session.set_tcp_stream(socket.try_clone().map_err(|_| closed())?);
+    session.handshake().map_err(|e| match io::Error::from(e).kind() {
+        io::ErrorKind::TimedOut => {
+            failure(format!("Connection timed out during banner exchange with {address}"))
+        }
+        _ => closed(),
+    })?;
+    check_host_key(&session, target)?;
+    authenticate(&session, target).map_err(|method| {
+        failure(format!("{}@{address}: Permission denied ({method}).", target.user))
+    })?;
+    Ok(Connection { session, socket })
+}
+
+/// Check the server's key against known_hosts as ssh would for the host's
+/// checking mode, recording the key of a new host under accept-new. Changed
+/// keys are reported in ssh's words, so they classify the same way.
+fn check_host_key(session: &Session, target: &Target) -> Result<(), Output> {
+    let Some(file) = &target.known_hosts else {
+        return Ok(());
+    };
+    if target.host_key_checking == HostKeyChecking::Off {
+        return Ok(());
+    }
+    let address = &target.address;
+    let unverified = || failure(format!("Host key verification failed for {address}."));
+    let (key, key_type) = session.host_key().ok_or_else(unverified)?;
+    let mut known = session.known_hosts().map_err(|_| unverified())?;
+    // A missing file just has no keys in it yet
+    let _ = known.read_file(file, KnownHostFileKind::OpenSSH);
+
+    match known.check_port(address, target.port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::Mismatch => Err(failure(format!(
+            "WARNING: REMOTE HOST IDENTIFICATION HAS CHANGED!\n\
+             Host key for {address} has changed and you have requested strict checking.\n\
+             Host key verification failed."
+        ))),
+        CheckResult::NotFound if target.host_key_checking == HostKeyChecking::AcceptNew => {
+            // Append one line, as ssh does; rewriting the whole file would
+            // drop its comments and any lines libssh2 can't parse
+            let mut new = session.known_hosts().map_err(|_| unverified())?;
+            new.add(&target.known_hosts_name(), key, "", key_type.into())
+                .map_err(|_| unverified())?;
+            let line = new
+                .iter()
+                .ok()
+                .and_then(|hosts| new.write_string(hosts.first()?, KnownHostFileKind::OpenSSH).ok())
+                .ok_or_else(unverified)?;
+            append_line(file, &line).map_err(|e| {
+                failure(format!("Failed to add the host key to {}: {e}", file.display()))
+            })
+        }
+        _ => Err(failure(format!(
+            "No host key is known for {address} and you have requested strict checking.\n\
+             Host key verification failed."
+        ))),
+    }
+}
+
+fn append_line(file: &std::path::Path, line: &str) -> io::Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut out = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .open(file)?;
+    writeln!(out, "{}", line.trim_end())
+}
+
+/// Log in with the host's password, else its key file, else (as ssh does)
+/// the agent's keys and then the default identity files. Returns the
+/// method that was refused.
+fn authenticate(session: &Session, target: &Target) -> Result<(), &'static str> {
+    let user = &target.user;
+    if let Some(password) = &target.password {
+        return session.userauth_password(user, password).map_err(|_| "password");
+    }
+    if let Some(key) = &target.key {
+        return session.userauth_pubkey_file(user, None, key, None).map_err(|_| "publickey");
+    }
+    if session.userauth_agent(user).is_ok() {
+        return Ok(());
+    }
+    let home = std::env::var("HOME").map(PathBuf::from).map_err(|_| "publickey")?;
+    for name in ["id_ed25519", "id_ecdsa", "id_rsa"] {
+        let key = home.join(".ssh").join(name);
+        if key.exists() && session.userauth_pubkey_file(user, None, &key, None).is_ok() {
+            return Ok(());
+        }
+    }
+    Err("publickey")
+}
+
+/// Why a run on a session failed. Either way the session is dropped.
+enum ExecError {
+    /// No channel could be opened: the session is gone
+    Closed(io::Error),
+    /// The command started but didn't finish: it timed out or its output
+    /// couldn't be read
+    Failed(io::Error),
+}
+
+/// Run `script` in a new exec channel on a blocking thread, for at most
+/// `timeout`. The connection comes back with the output.
+async fn exec(
+    connection: Connection,
+    script: &str,
+    timeout: Duration,
+) -> Result<(Connection, Output), ExecError> {
+    let abort = AbortOnDrop(connection.socket.try_clone().ok());
+    let script = script.to_string();
+    let result = task::spawn_blocking(move || {
+        connection.session.set_timeout(millis(timeout));
+        let output = run_channel(&connection.session, &connection.socket, &script, timeout)?;
+        Ok((connection, output))
+    })
+    .await;
+    abort.disarm();
+    result.map_err(|e| ExecError::Failed(io::Error::other(e)))?
+}
+
+fn run_channel(
+    session: &Session,
+    socket: &TcpStream,
+    script: &str,
+    timeout: Duration,
+) -> Result<Output, ExecError> {
+    let deadline = std::time::Instant::now() + timeout;
+    let mut channel = session.channel_session().map_err(|e| ExecError::Closed(e.into()))?;
+    let failed = |e: ssh2::Error| ExecError::Failed(e.into());
+    channel.exec(script).map_err(failed)?;
+
+    // Read both streams as data arrives: draining stdout first would stall
+    // a command that fills stderr's window before closing stdout
+    session.set_blocking(false);
+    let read = read_streams(session, socket, &channel, deadline);
+    session.set_blocking(true);
+    let (stdout, stderr) = read.map_err(ExecError::Failed)?;
+
+    channel.wait_close().map_err(failed)?;
+    let code = channel.exit_status().map_err(failed)?;
+    Ok(Output {
+        status: ExitStatus::from_raw((code & 0xff) << 8),
+        stdout,
+        stderr,
+    })
+}
+
+/// Read a non-blocking channel's stdout and stderr until the remote end
+/// closes them, waiting on the socket whenever neither has data.
+fn read_streams(
+    session: &Session,
+    socket: &TcpStream,
+    channel: &Channel,
+    deadline: std::time::Instant,
+) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+    let mut buf = [0; 8192];
+    loop {
+        let mut progress = false;
+        for (stream, out) in [(0, &mut stdout), (1, &mut stderr)] {
+            match channel.stream(stream).read(&mut buf) {
+                Ok(0) => {}
+                Ok(n) => {
+                    out.extend_from_slice(&buf[..n]);
+                    progress = true;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if progress {
+            continue;
+        }
+        // Only true once no data is left queued for either stream
+        if channel.eof() {
+            return Ok((stdout, stderr));
+        }
+        wait_socket(session, socket, deadline)?;
+    }
+}
+
+/// Wait until the socket is ready in the direction the session is blocked
+/// on, failing with `TimedOut` once `deadline` has passed.
+fn wait_socket(
+    session: &Session,
+    socket: &TcpStream,
+    deadline: std::time::Instant,
+) -> io::Result<()> {
+    let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+    if remaining.is_zero() {
+        return Err(io::Error::new(io::ErrorKind::TimedOut, "command timed out"));
+    }
+    let events = match session.block_directions() {
+        BlockDirections::Outbound => libc::POLLOUT,
+        BlockDirections::Both => libc::POLLIN | libc::POLLOUT,
+        BlockDirections::Inbound | BlockDirections::None => libc::POLLIN,
+    };
+    let mut fd = libc::pollfd {
+        fd: socket.as_raw_fd(),
+        events,
+        revents: 0,
+    };
+    let wait = millis(remaining).min(i32::MAX as u32) as libc::c_int;
+    // SAFETY: `fd` is one valid pollfd that outlives the call
+    if unsafe { libc::poll(&mut fd, 1, wait) } < 0 {
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// A connection failure as ssh reports it: exit status 255 and the message
+/// on stderr, so both backends' failures are classified and retried alike.
+fn failure(message: String) -> Output {
+    Output {
+        status: ExitStatus::from_raw(255 << 8),
+        stdout: Vec::new(),
+        stderr: message.into_bytes(),
+    }
+}
+
+fn describe_io(e: &io::Error) -> String {
+    match e.kind() {
+        io::ErrorKind::TimedOut => "Connection timed out".to_string(),
+        _ => format!("Connection closed: {e}"),
+    }
+}
+
+fn millis(duration: Duration) -> u32 {
+    duration.as_millis().try_into().unwrap_or(u32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::SshErrorKind;
+
+    fn test_args() -> ResolvedArgs {
+        ResolvedArgs {
+            ssh_timeout: 1,
+            command_timeout: 1,
+            retry_delay_ms: 500,
+            host_key_checking: HostKeyChecking::Off,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_failures_classify_like_ssh() {
+        let kind = |message: &str| super::super::failure_kind(&failure(message.into()));
+        assert_eq!(
+            kind("ssh: connect to host web01 port 22: Connection refused (os error 111)"),
+            Some(SshErrorKind::Refused)
+        );
+        assert_eq!(
+            kind("ssh: connect to host web01 port 22: Connection timed out"),
+            Some(SshErrorKind::Timeout)
+        );
+        assert_eq!(
+            kind("ssh: Could not resolve hostname web01: Name or service not known"),
+            Some(SshErrorKind::Dns)
+        );
+        assert_eq!(
+            kind("deploy@web01: Permission denied (publickey)."),
+            Some(SshErrorKind::Auth)
+        );
+        assert!(super::super::is_transient(&failure("Connection closed by web01 port 22".into())));
+    }
+
+    #[test]
+    fn test_unsupported_options_are_errors() {
+        let mut host = Host::new("web01");
+        host.vars.insert("ansible_ssh_extra_args".into(), "-o Foo=bar".into());
+        let err = Target::new(&host, &test_args()).err().unwrap();
+        assert!(err.to_string().contains("use ssh_backend: openssh"));
+
+        let args = ResolvedArgs {
+            jump_host: Some("bastion".into()),
+            ..test_args()
+        };
+        assert!(Target::new(&Host::new("web01"), &args).is_err());
+    }
+
+    #[test]
+    fn test_target() {
+        let mut host = Host::new("web01");
+        host.ansible_user = Some("deploy".into());
+        host.ansible_port = Some(2222);
+        let target = Target::new(&host, &test_args()).unwrap();
+        assert_eq!(target.to_string(), "deploy@web01:2222");
+        assert_eq!(target.known_hosts_name(), "[web01]:2222");
+        // Checking is off and no file is configured, so none is touched
+        assert_eq!(target.known_hosts, None);
+    }
+
+    #[tokio::test]
+    async fn test_refused_connect_backs_off() {
+        // Grab a free port, then close it so connects are refused
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut host = Host::new("web01");
+        host.ansible_host = Some("127.0.0.1".into());
+        host.ansible_port = Some(port);
+        let args = test_args();
+        let native = Native::default();
+
+        let output = native.run(&host, &args, "true").await.unwrap().unwrap();
+        assert_eq!(output.status.code(), Some(255));
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        assert!(stderr.contains("Connection refused"), "{stderr}");
+
+        // Within the backoff the same failure comes back without a connect
+        let slot = native.slot(&Target::new(&host, &args).unwrap().to_string());
+        assert_eq!(slot.lock().await.failures, 1);
+        let again = native.run(&host, &args, "true").await.unwrap().unwrap();
+        assert_eq!(again.stderr, output.stderr);
+        assert_eq!(slot.lock().await.failures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_hangup_during_handshake() {
+        // A server that accepts and hangs up before the SSH banner
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || drop(listener.accept()));
+        let mut host = Host::new("web01");
+        host.ansible_host = Some("127.0.0.1".into());
+        host.ansible_port = Some(port);
+
+        let output = Native::default().run(&host, &test_args(), "true").await.unwrap().unwrap();
+        assert_eq!(output.status.code(), Some(255));
+        assert_eq!(
+            String::from_utf8_lossy(&output.stderr),
+            format!("Connection closed by 127.0.0.1 port {port}")
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Token bucket for new SSH connections: it holds one token and refills at
+/// `per_sec` tokens a second, so connection starts are spaced at least
+/// `1 / per_sec` apart however many forks are free.
+#[derive(Debug)]
+pub struct RateLimiter {
+    spacing: Duration,
+    /// When the next token is available
+    next: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    /// A limiter for `per_sec` connections a second; 0 means no limit.
+    pub fn new(per_sec: u32) -> Option<Self> {
+        (per_sec > 0).then(|| Self {
+            spacing: Duration::from_secs(1) / per_sec,
+            next: Mutex::new(None),
+        })
+    }
+
+    /// Wait for a token. Tokens are handed out in the order callers ask.
+    pub async fn acquire(&self) {
+        let slot = {
+            let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let slot = next.map_or(now, |at| at.max(now));
+            *next = Some(slot + self.spacing);
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zero_means_unlimited() {
+        assert!(RateLimiter::new(0).is_none());
+        assert_eq!(RateLimiter::new(4).unwrap().spacing, Duration::from_millis(250));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_tokens_are_spaced() {
+        let limiter = RateLimiter::new(2).unwrap();
+        let begin = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire().await;
+        }
+        assert_eq!(begin.elapsed(), Duration::from_millis(1000));
+
+        // An idle bucket doesn't save up tokens for a burst
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        let begin = Instant::now();
+        limiter.acquire().await;
+        limiter.acquire().await;
+        assert_eq!(begin.elapsed(), Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,41 @@
+use std::future::Future;
+use std::process::Output;
+
+use anyhow::Result;
+
+use crate::cli::ResolvedArgs;
+use crate::inventory::types::Host;
+
+/// Runs a shell script on a host. Polls and checks go through a runner, so
+/// their handling of exit statuses, errors and timeouts can be tested
+/// without a real host to connect to.
+pub trait CommandRunner: Send + Sync + 'static {
+    /// Run `script` on `host` and collect its output. The outer error is a
+    /// command that couldn't be built (say, unparsable ssh args); the inner
+    /// one a command that couldn't be started. Dropping the future must stop
+    /// the command.
+    fn run(
+        &self,
+        host: &Host,
+        args: &ResolvedArgs,
+        script: &str,
+    ) -> impl Future<Output = Result<std::io::Result<Output>>> + Send;
+}
+
+/// Runs scripts with the OpenSSH client, or `sh -c` for local hosts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpenSsh;
+
+impl CommandRunner for OpenSsh {
+    async fn run(
+        &self,
+        host: &Host,
+        args: &ResolvedArgs,
+        script: &str,
+    ) -> Result<std::io::Result<Output>> {
+        let mut cmd = super::remote_command(host, args, script)?;
+        // Quitting mid-poll drops this future; don't leave ssh running
+        cmd.kill_on_drop(true);
+        Ok(cmd.output().await)
+    }
+}
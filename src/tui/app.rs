@@ -1,14 +1,18 @@
-use std::collections::HashMap;
-use std::time::Instant;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
 
+use ratatui::layout::Rect;
 use ratatui::widgets::TableState;
 
+use crate::cli::ResolvedArgs;
+use crate::config::CustomMetric;
 use crate::inventory::types::Host;
-use crate::metrics::{HostMetrics, HostStatus};
+use crate::metrics::{HostMetrics, HostStatus, Metrics, Severity};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum SortColumn {
     Name,
+    Address,
     Group,
     Status,
     Cpu,
@@ -16,25 +20,35 @@ pub enum SortColumn {
     Disk,
     IoWait,
     Swap,
+    Load,
+    NetIO,
+    Latency,
+    Uptime,
 }
 
 impl SortColumn {
     pub fn next(self) -> Self {
         match self {
-            SortColumn::Name => SortColumn::Group,
+            SortColumn::Name => SortColumn::Address,
+            SortColumn::Address => SortColumn::Group,
             SortColumn::Group => SortColumn::Status,
             SortColumn::Status => SortColumn::Cpu,
             SortColumn::Cpu => SortColumn::Memory,
             SortColumn::Memory => SortColumn::Disk,
             SortColumn::Disk => SortColumn::IoWait,
             SortColumn::IoWait => SortColumn::Swap,
-            SortColumn::Swap => SortColumn::Name,
+            SortColumn::Swap => SortColumn::Load,
+            SortColumn::Load => SortColumn::NetIO,
+            SortColumn::NetIO => SortColumn::Latency,
+            SortColumn::Latency => SortColumn::Uptime,
+            SortColumn::Uptime => SortColumn::Name,
         }
     }
 
     pub fn label(self) -> &'static str {
         match self {
             SortColumn::Name => "Host",
+            SortColumn::Address => "Address",
             SortColumn::Group => "Group",
             SortColumn::Status => "Status",
             SortColumn::Cpu => "CPU",
@@ -42,60 +56,621 @@ impl SortColumn {
             SortColumn::Disk => "Disk",
             SortColumn::IoWait => "IOw",
             SortColumn::Swap => "Swap",
+            SortColumn::Load => "Load",
+            SortColumn::NetIO => "Net",
+            SortColumn::Latency => "Latency",
+            SortColumn::Uptime => "Uptime",
         }
     }
 }
 
+/// A row of the host table: a host, or a group header when grouping is on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VisibleRow {
+    Group(String),
+    Host(String),
+}
+
+/// Which way a metric moved between the two latest snapshots.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetricTrend {
+    pub rising: bool,
+    pub falling: bool,
+}
+
+impl MetricTrend {
+    /// Compare two percentages; moves within `TREND_THRESHOLD` are steady.
+    fn between(before: f64, after: f64) -> Self {
+        let delta = after - before;
+        Self {
+            rising: delta > TREND_THRESHOLD,
+            falling: delta < -TREND_THRESHOLD,
+        }
+    }
+}
+
+/// Trends of a host's CPU, memory and disk usage, shown as arrows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HostTrends {
+    pub cpu: MetricTrend,
+    pub mem: MetricTrend,
+    pub disk: MetricTrend,
+}
+
+/// Table columns, declared in display order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Column {
+    Status,
+    Host,
+    Address,
+    Group,
+    Cpu,
+    Memory,
+    Disk,
+    IoWait,
+    Swap,
+    Load,
+    Temp,
+    Failed,
+    Net,
+    Tcp,
+    Latency,
+    Uptime,
+}
+
+impl Column {
+    /// Every column, in display order.
+    pub const ALL: &[Column] = &[
+        Column::Status,
+        Column::Host,
+        Column::Address,
+        Column::Group,
+        Column::Cpu,
+        Column::Memory,
+        Column::Disk,
+        Column::IoWait,
+        Column::Swap,
+        Column::Load,
+        Column::Temp,
+        Column::Failed,
+        Column::Net,
+        Column::Tcp,
+        Column::Latency,
+        Column::Uptime,
+    ];
+
+    /// The column with this header label, ignoring case.
+    pub fn from_label(label: &str) -> Option<Column> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|col| col.label().eq_ignore_ascii_case(label.trim()))
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Column::Status => "St",
+            Column::Host => "Host",
+            Column::Address => "Address",
+            Column::Group => "Group",
+            Column::Cpu => "CPU",
+            Column::Memory => "Mem",
+            Column::Disk => "Disk",
+            Column::IoWait => "IOw",
+            Column::Swap => "Swap",
+            Column::Load => "Load",
+            Column::Temp => "Temp",
+            Column::Failed => "Fail",
+            Column::Net => "Net",
+            Column::Tcp => "TCP",
+            Column::Latency => "Lat",
+            Column::Uptime => "Up",
+        }
+    }
+
+    /// The sort order selected by this column, if it is sortable.
+    pub fn sort_column(self) -> Option<SortColumn> {
+        match self {
+            Column::Status => Some(SortColumn::Status),
+            Column::Host => Some(SortColumn::Name),
+            Column::Address => Some(SortColumn::Address),
+            Column::Group => Some(SortColumn::Group),
+            Column::Cpu => Some(SortColumn::Cpu),
+            Column::Memory => Some(SortColumn::Memory),
+            Column::Disk => Some(SortColumn::Disk),
+            Column::IoWait => Some(SortColumn::IoWait),
+            Column::Swap => Some(SortColumn::Swap),
+            Column::Load => Some(SortColumn::Load),
+            Column::Net => Some(SortColumn::NetIO),
+            Column::Latency => Some(SortColumn::Latency),
+            Column::Uptime => Some(SortColumn::Uptime),
+            Column::Temp | Column::Failed | Column::Tcp => None,
+        }
+    }
+}
+
+/// Columns shown by default, in display order.
+pub const DEFAULT_COLUMNS: &[Column] = &[
+    Column::Status,
+    Column::Host,
+    Column::Group,
+    Column::Cpu,
+    Column::Memory,
+    Column::Disk,
+    Column::IoWait,
+    Column::Swap,
+];
+
+/// Columns of the compact preset: just the headline metrics.
+const COMPACT_COLUMNS: &[Column] = &[
+    Column::Status,
+    Column::Host,
+    Column::Cpu,
+    Column::Memory,
+    Column::Disk,
+];
+
+/// Columns wide mode adds to the displayed ones.
+const WIDE_COLUMNS: &[Column] = &[Column::Load, Column::Net, Column::Tcp, Column::Latency];
+
+/// Columns compact mode hides.
+const COMPACT_HIDDEN: &[Column] = &[Column::Group, Column::IoWait, Column::Swap];
+
 pub struct App {
     pub hosts: Vec<Host>,
     pub host_metrics: HashMap<String, HostMetrics>,
+    /// Earlier copies of `host_metrics`, newest first, one per poll interval
+    pub history: VecDeque<(Instant, HashMap<String, HostMetrics>)>,
+    /// Snapshot being replayed; 0 shows live metrics
+    pub history_offset: usize,
+    /// Global poll interval, also the spacing of history snapshots
+    poll_interval: Duration,
     pub table_state: TableState,
     pub sort_column: SortColumn,
     pub sort_ascending: bool,
     pub filter_text: String,
     pub filter_mode: bool,
+    /// Configured filter presets, sorted by name
+    pub filter_presets: Vec<(String, String)>,
+    /// Preset picked with Tab in filter mode, applied on Enter
+    pub preset_index: Option<usize>,
     pub show_detail: bool,
+    /// Detail panel drawn in place of the table, toggled with `F`
+    pub detail_fullscreen: bool,
     pub show_help: bool,
+    /// Problems found while loading the inventory
+    pub inventory_warnings: Vec<String>,
+    pub show_warnings: bool,
+    /// Hosts that went down or critical, newest first: host, what
+    /// happened, and when
+    pub recent_alerts: VecDeque<(String, String, Instant)>,
+    pub show_alerts: bool,
+    /// Table columns currently displayed, in order
+    pub columns: Vec<Column>,
+    /// Column sets cycled through with `c`: the configured (or default)
+    /// columns, all of them, and a compact set
+    column_presets: Vec<(&'static str, Vec<Column>)>,
+    column_preset: usize,
+    /// Add the network, TCP, load and latency columns, toggled with `w`
+    pub wide_mode: bool,
+    /// Wide mode is on but the table was too narrow for it at the last draw,
+    /// so the normal columns were shown instead
+    pub wide_fallback: bool,
+    /// Hide the less critical columns and shrink the header, toggled with
+    /// `C`, to fit more on small terminals
+    pub compact_mode: bool,
+    /// Show how many other groups a host is in next to its primary group
+    pub show_group_count: bool,
     pub last_poll: Option<Instant>,
+    /// When the current round of polls began: the first host starting a
+    /// poll once the previous round's interval had run out
+    pub last_poll_started: Option<Instant>,
     pub should_quit: bool,
-    /// Sorted+filtered host names for current view
-    pub visible_hosts: Vec<String>,
+    /// Sorted+filtered table rows for current view
+    pub visible_rows: Vec<VisibleRow>,
+    /// Trends between the newest two snapshots (or the replayed one and the
+    /// one before it), for hosts with metrics in both
+    pub trends: HashMap<String, HostTrends>,
+    /// Group hosts by primary group under summary header rows
+    pub show_group_headers: bool,
+    /// Hosts marked with Space, for bulk operations
+    pub selected_hosts: HashSet<String>,
+    /// Hosts frozen with `f`: not polled, and their last status kept
+    pub paused_hosts: HashSet<String>,
     /// Severity thresholds
     pub warning_threshold: f64,
     pub critical_threshold: f64,
+    /// Load average thresholds, per CPU
+    pub load_warning_per_cpu: f64,
+    pub load_critical_per_cpu: f64,
+    /// Temperature thresholds, in °C
+    pub temp_warning: f64,
+    pub temp_critical: f64,
+    /// Entropy thresholds, in bits (lower is worse)
+    pub entropy_warning: u32,
+    pub entropy_critical: u32,
+    /// Transient message shown in the footer, with the time it was set
+    pub status_message: Option<(String, Instant)>,
+    /// Rows moved per mouse wheel notch
+    pub scroll_lines: usize,
+    /// Draw CPU and memory usage as bars
+    pub bar_charts: bool,
+    /// Configured custom metrics, in display order
+    pub custom_metrics: Vec<CustomMetric>,
+    /// Resolved settings, for showing the port and user a host is reached with
+    pub args: ResolvedArgs,
+    /// Screen area of the host table from the last draw, for mouse hit-testing
+    pub table_area: Rect,
+    /// Screen area of the detail panel from the last draw (empty when hidden)
+    pub detail_area: Rect,
+    /// Vertical scroll offset of the detail panel content
+    pub detail_scroll: u16,
+    /// Detail panel content height and largest useful scroll offset, from
+    /// the last draw
+    pub detail_lines: u16,
+    pub detail_max_scroll: u16,
+    /// Whether the detail panel has focus, so j/k scroll it instead of
+    /// moving the selection
+    pub detail_focused: bool,
+    /// Time and row index of the last click, for double-click detection
+    last_click: Option<(Instant, usize)>,
 }
 
+/// Whether a host's status matches the value of a `status:` filter term.
+fn status_matches(status: HostStatus, name: &str) -> bool {
+    match name {
+        "up" => status == HostStatus::Up,
+        "down" => status == HostStatus::Down,
+        "connecting" => status == HostStatus::Connecting,
+        "unknown" => status == HostStatus::Unknown,
+        "disabled" => status == HostStatus::Disabled,
+        _ => false,
+    }
+}
+
+/// Two clicks on the same row within this window count as a double-click.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// Number of snapshots kept for replay.
+const HISTORY_LEN: usize = 10;
+
+/// Percentage points a metric has to move between snapshots to show as
+/// rising or falling.
+const TREND_THRESHOLD: f64 = 2.0;
+
+/// Number of alerts kept for the alerts overlay.
+const ALERTS_LEN: usize = 50;
+
+/// How long a footer status message stays visible.
+const STATUS_MESSAGE_TTL: Duration = Duration::from_secs(3);
+
 impl App {
-    pub fn new(hosts: Vec<Host>, warning_threshold: f64, critical_threshold: f64) -> Self {
+    pub fn new(hosts: Vec<Host>, args: &ResolvedArgs) -> Self {
         let host_names: Vec<String> = hosts.iter().map(|h| h.name.clone()).collect();
         let mut host_metrics = HashMap::new();
         for h in &hosts {
             host_metrics.insert(h.name.clone(), HostMetrics::new(&h.name));
         }
 
+        // Configured columns, in display order; unknown labels were warned
+        // about at startup
+        let mut configured: Vec<Column> =
+            args.visible_columns.iter().filter_map(|l| Column::from_label(l)).collect();
+        configured.sort();
+        configured.dedup();
+        let configured = if configured.is_empty() {
+            ("default", DEFAULT_COLUMNS.to_vec())
+        } else {
+            ("configured", configured)
+        };
+
         let mut app = Self {
             hosts,
             host_metrics,
+            history: VecDeque::new(),
+            history_offset: 0,
+            poll_interval: Duration::from_secs(args.interval),
             table_state: TableState::default(),
             sort_column: SortColumn::Name,
             sort_ascending: true,
             filter_text: String::new(),
             filter_mode: false,
+            filter_presets: args.filter_presets.clone(),
+            preset_index: None,
             show_detail: false,
+            detail_fullscreen: false,
             show_help: false,
+            inventory_warnings: Vec::new(),
+            show_warnings: false,
+            recent_alerts: VecDeque::new(),
+            show_alerts: false,
+            columns: configured.1.clone(),
+            column_presets: vec![
+                configured,
+                ("all", Column::ALL.to_vec()),
+                ("compact", COMPACT_COLUMNS.to_vec()),
+            ],
+            column_preset: 0,
+            wide_mode: false,
+            wide_fallback: false,
+            compact_mode: false,
+            show_group_count: false,
             last_poll: None,
+            last_poll_started: None,
             should_quit: false,
-            visible_hosts: host_names,
-            warning_threshold,
-            critical_threshold,
+            visible_rows: host_names.into_iter().map(VisibleRow::Host).collect(),
+            trends: HashMap::new(),
+            show_group_headers: false,
+            selected_hosts: HashSet::new(),
+            paused_hosts: HashSet::new(),
+            warning_threshold: args.warning_threshold,
+            critical_threshold: args.critical_threshold,
+            load_warning_per_cpu: args.load_warning_per_cpu,
+            load_critical_per_cpu: args.load_critical_per_cpu,
+            temp_warning: args.temp_warning,
+            temp_critical: args.temp_critical,
+            entropy_warning: args.entropy_warning,
+            entropy_critical: args.entropy_critical,
+            status_message: None,
+            scroll_lines: args.scroll_lines,
+            bar_charts: args.bar_charts,
+            custom_metrics: args.custom_metrics.clone(),
+            args: args.clone(),
+            table_area: Rect::default(),
+            detail_area: Rect::default(),
+            detail_scroll: 0,
+            detail_lines: 0,
+            detail_max_scroll: 0,
+            detail_focused: false,
+            last_click: None,
         };
-        if !app.visible_hosts.is_empty() {
+        if !app.visible_rows.is_empty() {
             app.table_state.select(Some(0));
         }
         app
     }
 
+    /// Replace the host list after an inventory reload. Metrics for hosts that
+    /// remain are kept; returns the number of (added, removed) hosts.
+    pub fn reload_hosts(&mut self, hosts: Vec<Host>) -> (usize, usize) {
+        let removed: Vec<String> = self
+            .hosts
+            .iter()
+            .filter(|old| !hosts.iter().any(|h| h.name == old.name))
+            .map(|h| h.name.clone())
+            .collect();
+        for name in &removed {
+            self.host_metrics.remove(name);
+            self.selected_hosts.remove(name);
+            self.paused_hosts.remove(name);
+        }
+
+        let mut added = 0;
+        for h in &hosts {
+            if !self.host_metrics.contains_key(&h.name) {
+                self.host_metrics.insert(h.name.clone(), HostMetrics::new(&h.name));
+                added += 1;
+            }
+        }
+
+        self.hosts = hosts;
+        self.refresh_visible();
+        (added, removed.len())
+    }
+
+    /// Show or hide an optional column, keeping columns in declaration order.
+    pub fn toggle_column(&mut self, col: Column) {
+        match self.columns.binary_search(&col) {
+            Ok(pos) => {
+                self.columns.remove(pos);
+            }
+            Err(pos) => self.columns.insert(pos, col),
+        }
+    }
+
+    /// The columns to draw, in declaration order: the displayed ones plus,
+    /// with `wide`, the wide mode ones, less those compact mode hides.
+    pub fn table_columns(&self, wide: bool) -> Vec<Column> {
+        let mut columns = self.columns.clone();
+        if wide {
+            for col in WIDE_COLUMNS {
+                if let Err(pos) = columns.binary_search(col) {
+                    columns.insert(pos, *col);
+                }
+            }
+        }
+        if self.compact_mode {
+            columns.retain(|col| !COMPACT_HIDDEN.contains(col));
+        }
+        columns
+    }
+
+    /// Switch to the next column preset. Returns its name.
+    pub fn cycle_column_preset(&mut self) -> &'static str {
+        self.column_preset = (self.column_preset + 1) % self.column_presets.len();
+        let (name, columns) = &self.column_presets[self.column_preset];
+        self.columns = columns.clone();
+        name
+    }
+
+    pub fn set_status_message(&mut self, message: impl Into<String>) {
+        self.status_message = Some((message.into(), Instant::now()));
+    }
+
+    /// The footer status message, if it hasn't expired yet.
+    pub fn current_status_message(&self) -> Option<&str> {
+        match &self.status_message {
+            Some((msg, at)) if at.elapsed() < STATUS_MESSAGE_TTL => Some(msg),
+            _ => None,
+        }
+    }
+
+    /// Metrics to display: live, or the snapshot being replayed.
+    pub fn displayed_metrics(&self) -> &HashMap<String, HostMetrics> {
+        match self.history_offset {
+            0 => &self.host_metrics,
+            offset => self.history.get(offset).map_or(&self.host_metrics, |(_, m)| m),
+        }
+    }
+
+    /// How old the replayed snapshot is, when not showing live metrics.
+    pub fn history_age(&self) -> Option<Duration> {
+        match self.history_offset {
+            0 => None,
+            offset => self.history.get(offset).map(|(at, _)| at.elapsed()),
+        }
+    }
+
+    /// Take a snapshot of the metrics once per poll interval. A replayed
+    /// snapshot stays on screen as newer ones are added, until it is dropped.
+    pub fn snapshot_if_due(&mut self) {
+        if self.last_poll.is_none() {
+            return;
+        }
+        if let Some((at, _)) = self.history.front() {
+            if at.elapsed() < self.poll_interval {
+                return;
+            }
+        }
+        self.history.push_front((Instant::now(), self.host_metrics.clone()));
+        self.history.truncate(HISTORY_LEN);
+        if self.history_offset > 0 {
+            self.history_offset = (self.history_offset + 1).min(self.history.len() - 1);
+        }
+        // Trends compare against the snapshots, so they move on too
+        self.refresh_visible();
+    }
+
+    /// Trends of each host between the snapshot shown (the newest one for
+    /// live metrics) and the one before it.
+    fn compute_trends(&self) -> HashMap<String, HostTrends> {
+        let (Some((_, after)), Some((_, before))) = (
+            self.history.get(self.history_offset),
+            self.history.get(self.history_offset + 1),
+        ) else {
+            return HashMap::new();
+        };
+        after
+            .iter()
+            .filter_map(|(name, hm)| {
+                let now = hm.metrics.as_ref()?;
+                let then = before.get(name)?.metrics.as_ref()?;
+                let trends = HostTrends {
+                    cpu: MetricTrend::between(then.cpu_percent, now.cpu_percent),
+                    mem: MetricTrend::between(then.mem_percent(), now.mem_percent()),
+                    disk: MetricTrend::between(then.disk_percent, now.disk_percent),
+                };
+                Some((name.clone(), trends))
+            })
+            .collect()
+    }
+
+    /// Step back to an older snapshot.
+    pub fn history_back(&mut self) {
+        if self.history_offset + 1 < self.history.len() {
+            self.history_offset += 1;
+            self.refresh_visible();
+        }
+    }
+
+    /// Step forward to a newer snapshot, or back to live metrics.
+    pub fn history_forward(&mut self) {
+        if self.history_offset > 0 {
+            self.history_offset -= 1;
+            self.refresh_visible();
+        }
+    }
+
+    /// Freeze or unfreeze the highlighted host. Returns its name and whether
+    /// it is now frozen.
+    pub fn toggle_freeze(&mut self) -> Option<(String, bool)> {
+        let name = self.selected_host()?.to_string();
+        let frozen = !self.paused_hosts.remove(&name);
+        if frozen {
+            self.paused_hosts.insert(name.clone());
+        }
+        Some((name, frozen))
+    }
+
+    /// Metrics of a host at or above their critical threshold.
+    pub fn critical_metrics(&self, m: &Metrics) -> Vec<&'static str> {
+        let (warn, crit) = (self.warning_threshold, self.critical_threshold);
+        [
+            ("CPU", m.cpu_severity(warn, crit)),
+            ("memory", m.mem_severity(warn, crit)),
+            ("disk", m.disk_severity(warn, crit)),
+            ("load", m.load_severity(self.load_warning_per_cpu, self.load_critical_per_cpu)),
+            ("temperature", m.temp_severity(self.temp_warning, self.temp_critical)),
+            ("entropy", m.entropy_severity(self.entropy_warning, self.entropy_critical)),
+        ]
+        .into_iter()
+        .filter(|(_, severity)| *severity == Severity::Critical)
+        .map(|(name, _)| name)
+        .collect()
+    }
+
+    /// What a poll result changes that is worth an alert: a host that was up
+    /// going down, or a host turning critical. A host's first result only
+    /// sets its baseline, so starting ansimon doesn't alert on every host
+    /// that is already critical.
+    pub fn transition_alert(&self, new: &HostMetrics) -> Option<String> {
+        if self.paused_hosts.contains(&new.host_name) {
+            return None;
+        }
+        let previous = self.host_metrics.get(&new.host_name)?;
+        previous.last_updated?;
+
+        // A refresh shows the host as connecting, so look at its history
+        let was_up = previous.last_success.is_some() && previous.consecutive_failures == 0;
+        if new.status == HostStatus::Down && was_up {
+            let error = new.error.as_deref().and_then(|e| e.lines().next()).unwrap_or("");
+            return Some(format!("went down: {error}"));
+        }
+
+        let critical = new.metrics.as_ref().map(|m| self.critical_metrics(m))?;
+        let was_critical = previous
+            .metrics
+            .as_ref()
+            .is_some_and(|m| !self.critical_metrics(m).is_empty());
+        (!critical.is_empty() && !was_critical)
+            .then(|| format!("is critical: {}", critical.join(", ")))
+    }
+
+    /// Remember an alert for the alerts overlay and flash it in the footer.
+    pub fn push_alert(&mut self, host_name: &str, message: String) {
+        self.set_status_message(format!("⚠ {host_name} {message}"));
+        self.recent_alerts
+            .push_front((host_name.to_string(), message, Instant::now()));
+        self.recent_alerts.truncate(ALERTS_LEN);
+    }
+
+    /// Store a poll result, keeping the host's failure history. Results for
+    /// frozen hosts (from a poll already running) are dropped.
+    pub fn record_result(&mut self, mut metrics: HostMetrics) {
+        if self.paused_hosts.contains(&metrics.host_name) {
+            return;
+        }
+        if let Some(previous) = self.host_metrics.get(&metrics.host_name) {
+            metrics.track_history(previous);
+        } else {
+            metrics.track_history(&HostMetrics::new(&metrics.host_name));
+        }
+        self.host_metrics.insert(metrics.host_name.clone(), metrics);
+    }
+
+    /// Time left until the next round of polls, `None` before the first.
+    pub fn next_poll_in(&self) -> Option<Duration> {
+        self.last_poll_started
+            .map(|started| self.poll_interval.saturating_sub(started.elapsed()))
+    }
+
     pub fn set_connecting(&mut self, host_name: &str) {
+        if self.paused_hosts.contains(host_name) {
+            return;
+        }
+        if self.next_poll_in().map_or(true, |left| left.is_zero()) {
+            self.last_poll_started = Some(Instant::now());
+        }
         if let Some(m) = self.host_metrics.get_mut(host_name) {
             if m.status != HostStatus::Up {
                 m.status = HostStatus::Connecting;
@@ -103,39 +678,99 @@ impl App {
         }
     }
 
+    /// Undo `set_connecting` for a poll that ended without a result. The
+    /// status before it isn't kept, so the host shows as Unknown until its
+    /// next poll rather than connecting forever.
+    pub fn cancel_poll(&mut self, host_name: &str) {
+        if let Some(m) = self.host_metrics.get_mut(host_name) {
+            if m.status == HostStatus::Connecting {
+                m.status = HostStatus::Unknown;
+            }
+        }
+    }
+
+    /// Show a host as Connecting right away when it is refreshed on demand,
+    /// even if it is up. Disabled hosts are never polled, so they stay as is.
+    pub fn mark_refreshing(&mut self, host_name: &str) {
+        if self.paused_hosts.contains(host_name) {
+            return;
+        }
+        if let Some(m) = self.host_metrics.get_mut(host_name) {
+            if m.status != HostStatus::Disabled {
+                m.status = HostStatus::Connecting;
+            }
+        }
+    }
+
     pub fn refresh_visible(&mut self) {
+        let previous = self.selected_host().map(str::to_string);
         let filter_lower = self.filter_text.to_lowercase();
+        // Comma-separated terms must all match; `status:X` terms match the
+        // host's poll status, `group:X` a group name (exactly or as a glob),
+        // anything else its name, address or groups
+        let terms: Vec<&str> = filter_lower
+            .split(',')
+            .map(|t| t.trim())
+            .filter(|t| !t.is_empty())
+            .collect();
+        let metrics = self.displayed_metrics();
         let mut visible: Vec<String> = self
             .hosts
             .iter()
             .filter(|h| {
-                if filter_lower.is_empty() {
-                    return true;
-                }
-                h.name.to_lowercase().contains(&filter_lower)
-                    || h.groups.iter().any(|g| g.to_lowercase().contains(&filter_lower))
+                terms.iter().all(|term| {
+                    if let Some(status) = term.strip_prefix("status:") {
+                        metrics
+                            .get(&h.name)
+                            .is_some_and(|m| status_matches(m.status, status))
+                    } else if let Some(group) = term.strip_prefix("group:") {
+                        h.groups
+                            .iter()
+                            .any(|g| glob_match::glob_match(group, &g.to_lowercase()))
+                    } else {
+                        h.name.to_lowercase().contains(term)
+                            || h.effective_host().to_lowercase().contains(term)
+                            || h.display_address().contains(term)
+                            || h.groups.iter().any(|g| g.to_lowercase().contains(term))
+                    }
+                })
             })
             .map(|h| h.name.clone())
             .collect();
 
         let sort_col = self.sort_column;
         let ascending = self.sort_ascending;
-        let metrics = &self.host_metrics;
         let hosts_map: HashMap<String, &Host> =
             self.hosts.iter().map(|h| (h.name.clone(), h)).collect();
 
         visible.sort_by(|a, b| {
             let cmp = match sort_col {
                 SortColumn::Name => a.cmp(b),
+                SortColumn::Address => {
+                    let ha = hosts_map.get(a);
+                    let hb = hosts_map.get(b);
+                    // Numeric order for IPs, text order for names
+                    match (ha.and_then(|h| h.ip_address()), hb.and_then(|h| h.ip_address())) {
+                        (Some(ia), Some(ib)) => ia.cmp(&ib),
+                        _ => {
+                            let aa = ha.map(|h| h.effective_host()).unwrap_or_default();
+                            let ab = hb.map(|h| h.effective_host()).unwrap_or_default();
+                            aa.cmp(ab)
+                        }
+                    }
+                }
                 SortColumn::Group => {
-                    let ga = hosts_map.get(a).map(|h| h.groups.first().cloned().unwrap_or_default()).unwrap_or_default();
-                    let gb = hosts_map.get(b).map(|h| h.groups.first().cloned().unwrap_or_default()).unwrap_or_default();
-                    ga.cmp(&gb)
+                    let ga = hosts_map.get(a).map(|h| h.display_group()).unwrap_or_default();
+                    let gb = hosts_map.get(b).map(|h| h.display_group()).unwrap_or_default();
+                    ga.cmp(gb)
                 }
                 SortColumn::Status => {
                     let sa = metrics.get(a).map(|m| m.status as u8).unwrap_or(0);
                     let sb = metrics.get(b).map(|m| m.status as u8).unwrap_or(0);
-                    sa.cmp(&sb)
+                    // Down hosts: the longest down first
+                    let da = metrics.get(a).and_then(|m| m.down_since);
+                    let db = metrics.get(b).and_then(|m| m.down_since);
+                    sa.cmp(&sb).then(da.cmp(&db))
                 }
                 SortColumn::Cpu => {
                     let ca = metrics.get(a).and_then(|m| m.metrics.as_ref()).map(|m| m.cpu_percent).unwrap_or(-1.0);
@@ -162,77 +797,267 @@ impl App {
                     let sb = metrics.get(b).and_then(|m| m.metrics.as_ref()).map(|m| m.swap_used_gb).unwrap_or(-1.0);
                     sa.partial_cmp(&sb).unwrap_or(std::cmp::Ordering::Equal)
                 }
+                SortColumn::Load => {
+                    let la = metrics.get(a).and_then(|m| m.metrics.as_ref()).map(|m| m.load_1).unwrap_or(-1.0);
+                    let lb = metrics.get(b).and_then(|m| m.metrics.as_ref()).map(|m| m.load_1).unwrap_or(-1.0);
+                    la.partial_cmp(&lb).unwrap_or(std::cmp::Ordering::Equal)
+                }
+                SortColumn::NetIO => {
+                    let net = |name: &String| {
+                        metrics
+                            .get(name)
+                            .and_then(|m| m.metrics.as_ref())
+                            .map(|m| m.net_rx_bytes_sec + m.net_tx_bytes_sec)
+                    };
+                    // Hosts without metrics sort last either way
+                    match (net(a), net(b)) {
+                        (Some(na), Some(nb)) => na.cmp(&nb),
+                        (Some(_), None) => return std::cmp::Ordering::Less,
+                        (None, Some(_)) => return std::cmp::Ordering::Greater,
+                        (None, None) => std::cmp::Ordering::Equal,
+                    }
+                }
+                SortColumn::Latency => {
+                    let la = metrics.get(a).and_then(|m| m.ssh_latency_ms);
+                    let lb = metrics.get(b).and_then(|m| m.ssh_latency_ms);
+                    // Hosts without a latency sort last either way
+                    match (la, lb) {
+                        (Some(la), Some(lb)) => la.cmp(&lb),
+                        (Some(_), None) => return std::cmp::Ordering::Less,
+                        (None, Some(_)) => return std::cmp::Ordering::Greater,
+                        (None, None) => std::cmp::Ordering::Equal,
+                    }
+                }
+                SortColumn::Uptime => {
+                    let uptime = |name: &String| {
+                        metrics.get(name).and_then(|m| m.metrics.as_ref()).map(|m| m.uptime_secs)
+                    };
+                    // Hosts without metrics sort last either way
+                    match (uptime(a), uptime(b)) {
+                        (Some(ua), Some(ub)) => ua.cmp(&ub),
+                        (Some(_), None) => return std::cmp::Ordering::Less,
+                        (None, Some(_)) => return std::cmp::Ordering::Greater,
+                        (None, None) => std::cmp::Ordering::Equal,
+                    }
+                }
             };
             if ascending { cmp } else { cmp.reverse() }
         });
 
-        self.visible_hosts = visible;
+        self.visible_rows = if self.show_group_headers {
+            // Stable sort, so hosts keep the chosen order within a group
+            visible.sort_by_key(|name| {
+                hosts_map.get(name).map(|h| h.display_group()).unwrap_or_default()
+            });
+            let mut rows = Vec::new();
+            let mut current: Option<&str> = None;
+            for name in visible {
+                let group = hosts_map.get(&name).map(|h| h.display_group()).unwrap_or_default();
+                if current != Some(group) {
+                    rows.push(VisibleRow::Group(group.to_string()));
+                    current = Some(group);
+                }
+                rows.push(VisibleRow::Host(name));
+            }
+            rows
+        } else {
+            visible.into_iter().map(VisibleRow::Host).collect()
+        };
 
-        // Fix selection
+        // Fix selection, keeping it off group headers
         let selected = self.table_state.selected().unwrap_or(0);
-        if self.visible_hosts.is_empty() {
-            self.table_state.select(None);
-        } else if selected >= self.visible_hosts.len() {
-            self.table_state.select(Some(self.visible_hosts.len() - 1));
+        self.table_state.select(self.host_row_near(selected, true));
+        if self.selected_host() != previous.as_deref() {
+            self.detail_scroll = 0;
+        }
+        self.trends = self.compute_trends();
+    }
+
+    /// Select a table row. Another host's detail panel starts at the top.
+    fn select_row(&mut self, row: Option<usize>) {
+        let previous = self.selected_host().map(str::to_string);
+        self.table_state.select(row);
+        if self.selected_host() != previous.as_deref() {
+            self.detail_scroll = 0;
         }
     }
 
+    /// Index of the host row nearest to `idx` (clamped to the table),
+    /// looking ahead first when `forward`, else behind first.
+    fn host_row_near(&self, idx: usize, forward: bool) -> Option<usize> {
+        let is_host = |i: &usize| matches!(self.visible_rows[*i], VisibleRow::Host(_));
+        let idx = idx.min(self.visible_rows.len().checked_sub(1)?);
+        let ahead = || (idx..self.visible_rows.len()).find(is_host);
+        let behind = || (0..=idx).rev().find(is_host);
+        if forward {
+            ahead().or_else(behind)
+        } else {
+            behind().or_else(ahead)
+        }
+    }
+
+    /// Show or hide group header rows.
+    pub fn toggle_group_headers(&mut self) {
+        self.show_group_headers = !self.show_group_headers;
+        // Stay on the same host
+        let selected = self.selected_host().map(str::to_string);
+        self.refresh_visible();
+        if let Some(name) = selected {
+            let row = VisibleRow::Host(name);
+            if let Some(idx) = self.visible_rows.iter().position(|r| *r == row) {
+                self.select_row(Some(idx));
+            }
+        }
+    }
+
+    /// Pick the next filter preset, wrapping around.
+    pub fn next_preset(&mut self) {
+        if self.filter_presets.is_empty() {
+            return;
+        }
+        self.preset_index = Some(match self.preset_index {
+            Some(i) => (i + 1) % self.filter_presets.len(),
+            None => 0,
+        });
+    }
+
+    /// Name and filter string of the preset picked in filter mode.
+    pub fn selected_preset(&self) -> Option<&(String, String)> {
+        self.filter_presets.get(self.preset_index?)
+    }
+
     pub fn selected_host(&self) -> Option<&str> {
-        let idx = self.table_state.selected()?;
-        self.visible_hosts.get(idx).map(|s| s.as_str())
+        match self.visible_rows.get(self.table_state.selected()?)? {
+            VisibleRow::Host(name) => Some(name),
+            VisibleRow::Group(_) => None,
+        }
+    }
+
+    /// Mark or unmark the highlighted host.
+    pub fn toggle_select(&mut self) {
+        let Some(name) = self.selected_host().map(str::to_string) else {
+            return;
+        };
+        if !self.selected_hosts.remove(&name) {
+            self.selected_hosts.insert(name);
+        }
     }
 
     pub fn move_down(&mut self) {
-        if self.visible_hosts.is_empty() {
+        if self.visible_rows.is_empty() {
             return;
         }
         let i = self.table_state.selected().unwrap_or(0);
-        let next = if i >= self.visible_hosts.len() - 1 {
-            self.visible_hosts.len() - 1
-        } else {
-            i + 1
-        };
-        self.table_state.select(Some(next));
+        self.select_row(self.host_row_near(i + 1, true));
     }
 
     pub fn move_up(&mut self) {
-        if self.visible_hosts.is_empty() {
+        if self.visible_rows.is_empty() {
             return;
         }
         let i = self.table_state.selected().unwrap_or(0);
-        let next = if i == 0 { 0 } else { i - 1 };
-        self.table_state.select(Some(next));
+        self.select_row(self.host_row_near(i.saturating_sub(1), false));
     }
 
     pub fn page_down(&mut self, page_size: usize) {
-        if self.visible_hosts.is_empty() {
+        if self.visible_rows.is_empty() {
             return;
         }
         let i = self.table_state.selected().unwrap_or(0);
-        let next = (i + page_size).min(self.visible_hosts.len() - 1);
-        self.table_state.select(Some(next));
+        self.select_row(self.host_row_near(i + page_size, true));
     }
 
     pub fn page_up(&mut self, page_size: usize) {
         let i = self.table_state.selected().unwrap_or(0);
-        let next = i.saturating_sub(page_size);
-        self.table_state.select(Some(next));
+        self.select_row(self.host_row_near(i.saturating_sub(page_size), false));
     }
 
     pub fn go_home(&mut self) {
-        if !self.visible_hosts.is_empty() {
-            self.table_state.select(Some(0));
-        }
+        self.select_row(self.host_row_near(0, true));
     }
 
     pub fn go_end(&mut self) {
-        if !self.visible_hosts.is_empty() {
-            self.table_state.select(Some(self.visible_hosts.len() - 1));
+        self.select_row(self.host_row_near(usize::MAX, false));
+    }
+
+    /// Handle a left click at a screen position. Selects the clicked host row
+    /// and toggles the detail panel on double-click.
+    pub fn click(&mut self, column: u16, row: u16) {
+        let area = self.table_area;
+        // Rows start below the top border and the header line
+        let first_row = area.y + 2;
+        let inside = contains(area, column, row)
+            && row >= first_row
+            && row < area.y + area.height.saturating_sub(1);
+        if !inside {
+            return;
+        }
+
+        // A click on a group header selects the group's first host
+        let clicked = self.table_state.offset() + (row - first_row) as usize;
+        let Some(idx) = self.host_row_near(clicked, true) else {
+            return;
+        };
+        self.select_row(Some(idx));
+
+        let now = Instant::now();
+        match self.last_click {
+            Some((at, prev)) if prev == idx && now.duration_since(at) < DOUBLE_CLICK => {
+                self.toggle_detail();
+                self.last_click = None;
+            }
+            _ => self.last_click = Some((now, idx)),
         }
     }
 
+    /// Handle a mouse wheel tick. Scrolls the detail panel when the pointer
+    /// is over it, otherwise moves the table selection.
+    pub fn scroll(&mut self, column: u16, row: u16, down: bool) {
+        let lines = self.scroll_lines;
+        if self.detail_visible() && contains(self.detail_area, column, row) {
+            self.scroll_detail(lines as u16, down);
+        } else if down {
+            self.page_down(lines);
+        } else {
+            self.page_up(lines);
+        }
+    }
+
+    /// Scroll the detail panel, stopping once its last line is in view.
+    pub fn scroll_detail(&mut self, lines: u16, down: bool) {
+        self.detail_scroll = if down {
+            self.detail_scroll
+                .saturating_add(lines)
+                .min(self.detail_max_scroll)
+        } else {
+            self.detail_scroll.saturating_sub(lines)
+        };
+    }
+
+    /// Show or hide the detail panel. Hiding it hands focus back to the table.
+    pub fn toggle_detail(&mut self) {
+        self.show_detail = !self.show_detail;
+        self.detail_focused &= self.show_detail;
+    }
+
+    /// Whether the detail panel is on screen, split or full-screen.
+    pub fn detail_visible(&self) -> bool {
+        self.show_detail || self.detail_fullscreen
+    }
+
+    /// Whether j/k scroll the detail panel: it has focus, or is the only
+    /// thing on screen.
+    pub fn detail_has_keys(&self) -> bool {
+        self.detail_focused || self.detail_fullscreen
+    }
+
+    /// Move keyboard focus between the table and the detail panel.
+    pub fn toggle_detail_focus(&mut self) {
+        self.detail_focused = self.show_detail && !self.detail_focused;
+    }
+
     pub fn hosts_up(&self) -> usize {
-        self.host_metrics
+        self.displayed_metrics()
             .values()
             .filter(|m| m.status == HostStatus::Up)
             .count()
@@ -242,3 +1067,10 @@ impl App {
         self.hosts.len()
     }
 }
+
+fn contains(area: Rect, column: u16, row: u16) -> bool {
+    column >= area.x
+        && column < area.x + area.width
+        && row >= area.y
+        && row < area.y + area.height
+}
@@ -0,0 +1,107 @@
+use std::io::Write;
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Clipboard tools to try, with the environment variable that must be set
+/// for each to have a clipboard to talk to.
+const TOOLS: &[(&str, &[&str], Option<&str>)] = &[
+    ("wl-copy", &[], Some("WAYLAND_DISPLAY")),
+    ("xclip", &["-selection", "clipboard"], Some("DISPLAY")),
+    ("xsel", &["--clipboard", "--input"], Some("DISPLAY")),
+    ("pbcopy", &[], None),
+];
+
+/// Longest a clipboard tool may take before the next one is tried.
+const TOOL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Copy text to the system clipboard with the first clipboard tool that
+/// works. Returns false if none did.
+pub async fn copy_with_tool(text: &str) -> bool {
+    for (tool, args, env) in TOOLS {
+        if env.is_some_and(|var| std::env::var_os(var).is_none()) {
+            continue;
+        }
+        if run_tool(tool, args, text).await {
+            return true;
+        }
+    }
+    false
+}
+
+/// Ask the terminal to copy text by writing OSC 52 to stdout, which only
+/// works if the terminal supports it. Over SSH there is usually no local
+/// clipboard tool, but the terminal can still reach the user's clipboard.
+pub fn copy_with_terminal(text: &str) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", base64(text.as_bytes()))?;
+    stdout.flush()
+}
+
+/// Pipe text into a clipboard tool. A tool that hangs (say, on a display
+/// that doesn't answer) is killed after `TOOL_TIMEOUT`.
+async fn run_tool(tool: &str, args: &[&str], text: &str) -> bool {
+    let child = Command::new(tool)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn();
+    let Ok(mut child) = child else {
+        return false;
+    };
+    let run = async {
+        // Dropping stdin closes it, so the tool sees the end of the text
+        let written = match child.stdin.take() {
+            Some(mut stdin) => stdin.write_all(text.as_bytes()).await.is_ok(),
+            None => false,
+        };
+        matches!(child.wait().await, Ok(status) if status.success()) && written
+    };
+    tokio::time::timeout(TOOL_TIMEOUT, run).await.unwrap_or(false)
+}
+
+/// Standard base64 with padding, as OSC 52 expects.
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        // RFC 4648 test vectors
+        let cases = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(base64(input.as_bytes()), expected, "{input:?}");
+        }
+    }
+}
@@ -12,6 +12,24 @@ pub enum AppAction {
     Home,
     End,
     ToggleDetail,
+    /// Show the detail panel in place of the table, or go back to the split
+    FullScreenDetail,
+    /// Move keyboard focus between the table and the detail panel
+    ToggleDetailFocus,
+    /// Scroll the detail panel by one line
+    ScrollDetailDown,
+    ScrollDetailUp,
+    /// Mark or unmark the highlighted host
+    ToggleSelect,
+    /// Stop or resume polling the highlighted host
+    ToggleFreeze,
+    /// Copy the highlighted host's name to the clipboard
+    CopyHostname,
+    /// Open an interactive SSH session to the highlighted host
+    OpenSsh,
+    /// Show the previous / next metrics snapshot
+    HistoryBack,
+    HistoryForward,
     CycleSort,
     ReverseSort,
     StartFilter,
@@ -19,8 +37,36 @@ pub enum AppAction {
     FilterBackspace,
     CancelFilter,
     ConfirmFilter,
+    /// Pick the next filter preset
+    NextPreset,
     ForceRefresh,
+    /// Poll the selected host now
+    RefreshHost(String),
+    ReloadInventory,
+    ToggleAddressColumn,
+    ToggleFailedColumn,
+    ToggleLoadColumn,
+    ToggleTempColumn,
+    ToggleNetColumn,
+    ToggleLatencyColumn,
+    ToggleUptimeColumn,
+    /// Switch to the next set of columns
+    CycleColumns,
+    /// Add or remove the wide mode columns
+    ToggleWideMode,
+    /// Switch between the normal and compact layout
+    ToggleCompactMode,
+    ToggleGroupCount,
+    ToggleGroupHeaders,
+    ToggleWarnings,
+    /// Show or hide the list of recent alerts
+    ToggleAlerts,
     ToggleHelp,
+    /// Left mouse button pressed at (column, row)
+    Click(u16, u16),
+    /// Mouse wheel scrolled down/up at (column, row)
+    ScrollDown(u16, u16),
+    ScrollUp(u16, u16),
     None,
 }
 
@@ -30,6 +76,7 @@ pub fn map_key_for_filter(key: KeyEvent) -> AppAction {
         KeyCode::Esc => AppAction::CancelFilter,
         KeyCode::Enter => AppAction::ConfirmFilter,
         KeyCode::Backspace => AppAction::FilterBackspace,
+        KeyCode::Tab => AppAction::NextPreset,
         KeyCode::Char(c) => AppAction::FilterInput(c),
         _ => AppAction::None,
     }
@@ -1,4 +1,5 @@
 pub mod app;
+pub mod clipboard;
 pub mod event;
 pub mod ui;
 
@@ -8,24 +9,32 @@ use std::time::Duration;
 
 use anyhow::Result;
 use crossterm::event as ct_event;
+use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
 use crossterm::terminal::{
     disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
 };
 use crossterm::ExecutableCommand;
 use ratatui::backend::CrosstermBackend;
 use ratatui::Terminal;
+use tokio::signal::unix::{signal, SignalKind};
 
 use crate::cli::ResolvedArgs;
+use crate::config::SshBackend;
 use crate::inventory::types::Host;
-use crate::ssh::{self, SshMessage};
+use crate::ssh::{self, PollerCommand, SshMessage};
 
-use app::App;
+use app::{App, Column};
 use event::{map_key_for_filter, AppAction};
 
-pub async fn run(hosts: Vec<Host>, args: Arc<ResolvedArgs>) -> Result<()> {
+pub async fn run(hosts: Vec<Host>, warnings: Vec<String>, args: Arc<ResolvedArgs>) -> Result<()> {
+    if args.ssh_multiplexing {
+        ssh::prepare_control_dir()?;
+    }
+
     // Setup terminal
     enable_raw_mode()?;
     io::stdout().execute(EnterAlternateScreen)?;
+    io::stdout().execute(EnableMouseCapture)?;
 
     let backend = CrosstermBackend::new(io::stdout());
     let mut terminal = Terminal::new(backend)?;
@@ -35,14 +44,16 @@ pub async fn run(hosts: Vec<Host>, args: Arc<ResolvedArgs>) -> Result<()> {
     let original_hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |panic_info| {
         let _ = disable_raw_mode();
+        let _ = io::stdout().execute(DisableMouseCapture);
         let _ = io::stdout().execute(LeaveAlternateScreen);
         original_hook(panic_info);
     }));
 
-    let result = run_app(&mut terminal, hosts, args).await;
+    let result = run_app(&mut terminal, hosts, warnings, args).await;
 
     // Restore terminal
     disable_raw_mode()?;
+    io::stdout().execute(DisableMouseCapture)?;
     io::stdout().execute(LeaveAlternateScreen)?;
 
     result
@@ -51,13 +62,56 @@ pub async fn run(hosts: Vec<Host>, args: Arc<ResolvedArgs>) -> Result<()> {
 async fn run_app(
     terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
     hosts: Vec<Host>,
+    warnings: Vec<String>,
     args: Arc<ResolvedArgs>,
 ) -> Result<()> {
-    let mut app = App::new(hosts.clone(), args.warning_threshold, args.critical_threshold);
+    let mut app = App::new(hosts.clone(), &args);
+    app.inventory_warnings = warnings;
     let interval = args.interval;
 
     // Spawn SSH poller
-    let mut rx = ssh::spawn_poller(hosts, args, interval);
+    let (poller, mut rx, poller_task) = match args.ssh_backend {
+        SshBackend::Openssh => {
+            ssh::spawn_poller(hosts, args.clone(), interval, Arc::new(ssh::OpenSsh))
+        }
+        #[cfg(feature = "native-ssh")]
+        SshBackend::Native => {
+            ssh::spawn_poller(hosts, args.clone(), interval, Arc::new(ssh::Native::default()))
+        }
+        #[cfg(not(feature = "native-ssh"))]
+        SshBackend::Native => unreachable!("ssh::backend refuses native in this build"),
+    };
+
+    // SIGHUP reloads the inventory, like the `R` key
+    let (hup_tx, mut hup_rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut hangup = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            if hup_tx.send(()).is_err() {
+                break;
+            }
+        }
+    });
+
+    // Clipboard copies report back here: (host name, whether a tool took it)
+    let (copied_tx, mut copied_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    // SIGTERM and SIGINT (e.g. `kill -INT`; in raw mode Ctrl-C is a key)
+    // quit like `q`, so the terminal is restored and ssh processes killed
+    let (quit_tx, mut quit_rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = terminate.recv() => {}
+                _ = interrupt.recv() => {}
+            }
+            if quit_tx.send(()).is_err() {
+                break;
+            }
+        }
+    });
 
     loop {
         // Draw
@@ -65,18 +119,30 @@ async fn run_app(
 
         // Handle events (non-blocking with 50ms timeout for responsiveness)
         let action = if ct_event::poll(Duration::from_millis(50))? {
-            if let ct_event::Event::Key(key) = ct_event::read()? {
-                if app.show_help {
-                    app.show_help = false;
-                    AppAction::None
-                } else if app.filter_mode {
-                    map_key_for_filter(key)
-                } else {
-                    map_key_normal(key, &mut app)
+            match ct_event::read()? {
+                ct_event::Event::Key(key) => {
+                    if app.show_help {
+                        app.show_help = false;
+                        AppAction::None
+                    } else if app.show_warnings {
+                        app.show_warnings = false;
+                        AppAction::None
+                    } else if app.show_alerts {
+                        app.show_alerts = false;
+                        AppAction::None
+                    } else if app.filter_mode {
+                        map_key_for_filter(key)
+                    } else {
+                        map_key_normal(key, &mut app)
+                    }
                 }
-            } else {
-                AppAction::None
+                ct_event::Event::Mouse(mouse) => map_mouse(mouse),
+                _ => AppAction::None,
             }
+        } else if quit_rx.try_recv().is_ok() {
+            AppAction::Quit
+        } else if hup_rx.try_recv().is_ok() {
+            AppAction::ReloadInventory
         } else {
             AppAction::None
         };
@@ -93,7 +159,61 @@ async fn run_app(
             AppAction::PageUp => app.page_up(10),
             AppAction::Home => app.go_home(),
             AppAction::End => app.go_end(),
-            AppAction::ToggleDetail => app.show_detail = !app.show_detail,
+            AppAction::ToggleDetail => app.toggle_detail(),
+            AppAction::FullScreenDetail => app.detail_fullscreen = !app.detail_fullscreen,
+            AppAction::ToggleDetailFocus => app.toggle_detail_focus(),
+            AppAction::ScrollDetailDown => app.scroll_detail(1, true),
+            AppAction::ScrollDetailUp => app.scroll_detail(1, false),
+            AppAction::ToggleSelect => app.toggle_select(),
+            AppAction::ToggleFreeze => {
+                if let Some((name, frozen)) = app.toggle_freeze() {
+                    let _ = poller.send(PollerCommand::Freeze(name.clone(), frozen));
+                    app.set_status_message(if frozen {
+                        format!("{name} frozen")
+                    } else {
+                        format!("{name} unfrozen")
+                    });
+                }
+            }
+            AppAction::HistoryBack => app.history_back(),
+            AppAction::HistoryForward => app.history_forward(),
+            AppAction::OpenSsh => {
+                let host = app
+                    .selected_host()
+                    .and_then(|name| app.hosts.iter().find(|h| h.name == name))
+                    .cloned();
+                if let Some(host) = host {
+                    match ssh::interactive_command(&host, &args) {
+                        Ok(cmd) => {
+                            let status = run_interactive(terminal, cmd).await?;
+                            // Ctrl-C while ssh was connecting reached us too;
+                            // it was meant for ssh
+                            while quit_rx.try_recv().is_ok() {}
+                            match status {
+                                Ok(status) if !status.success() => app.set_status_message(
+                                    format!("Session to {} ended: {status}", host.name),
+                                ),
+                                Ok(_) => {}
+                                Err(e) => {
+                                    app.set_status_message(format!("Failed to start ssh: {e}"))
+                                }
+                            }
+                        }
+                        Err(e) => app.set_status_message(format!("{}: {e:#}", host.name)),
+                    }
+                }
+            }
+            AppAction::CopyHostname => {
+                if let Some(name) = app.selected_host().map(str::to_string) {
+                    // A tool can hang on a display that doesn't answer, so
+                    // the tools run off the loop and report back
+                    let copied_tx = copied_tx.clone();
+                    tokio::spawn(async move {
+                        let copied = clipboard::copy_with_tool(&name).await;
+                        let _ = copied_tx.send((name, copied));
+                    });
+                }
+            }
             AppAction::CycleSort => {
                 app.sort_column = app.sort_column.next();
                 app.refresh_visible();
@@ -106,28 +226,118 @@ async fn run_app(
                 app.filter_mode = true;
             }
             AppAction::FilterInput(c) => {
+                app.preset_index = None;
                 app.filter_text.push(c);
                 app.refresh_visible();
             }
             AppAction::FilterBackspace => {
+                app.preset_index = None;
                 app.filter_text.pop();
                 app.refresh_visible();
             }
             AppAction::CancelFilter => {
                 app.filter_mode = false;
+                app.preset_index = None;
                 app.filter_text.clear();
                 app.refresh_visible();
             }
             AppAction::ConfirmFilter => {
                 app.filter_mode = false;
+                if let Some((_, filter)) = app.selected_preset().cloned() {
+                    app.filter_text = filter;
+                    app.preset_index = None;
+                    app.refresh_visible();
+                }
+            }
+            AppAction::NextPreset => {
+                if app.filter_presets.is_empty() {
+                    app.set_status_message("No filter presets configured");
+                } else {
+                    app.next_preset();
+                }
+            }
+            AppAction::ForceRefresh => {
+                let _ = poller.send(PollerCommand::RefreshAll);
+                // The refresh starts a new round, and the countdown with it
+                app.last_poll_started = None;
+                app.set_status_message("Refreshing all hosts");
+            }
+            AppAction::RefreshHost(name) => {
+                app.mark_refreshing(&name);
+                app.refresh_visible();
+                app.set_status_message(format!("Refreshing {name}"));
+                let _ = poller.send(PollerCommand::RefreshHost(name));
+            }
+            AppAction::ReloadInventory => {
+                match crate::inventory::load_hosts(
+                    &args.inventory,
+                    args.limit.as_deref(),
+                    &args.groups,
+                    args.primary_group,
+                    args.strict,
+                ) {
+                    Ok((hosts, warnings)) => {
+                        let (added, removed) = app.reload_hosts(hosts.clone());
+                        app.inventory_warnings = warnings.clone();
+                        let _ = poller.send(PollerCommand::SetHosts(hosts));
+                        let mut message =
+                            format!("Inventory reloaded: +{added} / -{removed} hosts");
+                        if let Some(warning) = warnings.first() {
+                            message.push_str(&format!(" (warning: {warning})"));
+                        }
+                        app.set_status_message(message);
+                    }
+                    Err(e) => app.set_status_message(format!("Inventory reload failed: {e:#}")),
+                }
+            }
+            AppAction::ToggleAddressColumn => app.toggle_column(Column::Address),
+            AppAction::ToggleFailedColumn => app.toggle_column(Column::Failed),
+            AppAction::ToggleLoadColumn => app.toggle_column(Column::Load),
+            AppAction::ToggleTempColumn => app.toggle_column(Column::Temp),
+            AppAction::ToggleNetColumn => app.toggle_column(Column::Net),
+            AppAction::ToggleLatencyColumn => app.toggle_column(Column::Latency),
+            AppAction::ToggleUptimeColumn => app.toggle_column(Column::Uptime),
+            AppAction::CycleColumns => {
+                let preset = app.cycle_column_preset();
+                app.set_status_message(format!("Columns: {preset}"));
             }
-            AppAction::ForceRefresh => {}
+            AppAction::ToggleWideMode => {
+                app.wide_mode = !app.wide_mode;
+                let mode = if app.wide_mode { "on" } else { "off" };
+                app.set_status_message(format!("Wide mode {mode}"));
+            }
+            AppAction::ToggleCompactMode => {
+                app.compact_mode = !app.compact_mode;
+                let mode = if app.compact_mode { "on" } else { "off" };
+                app.set_status_message(format!("Compact mode {mode}"));
+            }
+            AppAction::ToggleGroupCount => app.show_group_count = !app.show_group_count,
+            AppAction::ToggleGroupHeaders => app.toggle_group_headers(),
+            AppAction::ToggleWarnings => app.show_warnings = !app.show_warnings,
+            AppAction::ToggleAlerts => app.show_alerts = !app.show_alerts,
             AppAction::ToggleHelp => {
                 app.show_help = !app.show_help;
             }
+            AppAction::Click(column, row) => app.click(column, row),
+            AppAction::ScrollDown(column, row) => app.scroll(column, row, true),
+            AppAction::ScrollUp(column, row) => app.scroll(column, row, false),
             AppAction::None => {}
         }
 
+        while let Ok((name, copied)) = copied_rx.try_recv() {
+            if copied {
+                app.set_status_message(format!("{name} copied"));
+                continue;
+            }
+            // Written here, between draws, so it can't land inside a frame
+            match clipboard::copy_with_terminal(&name) {
+                Ok(()) => app.set_status_message(format!(
+                    "No clipboard tool found: sent {name} to the terminal's clipboard"
+                )),
+                Err(e) => app.set_status_message(format!("Copy failed: {e}")),
+            }
+        }
+
         // Drain SSH messages in batch — insert directly, refresh once
         {
             let mut need_refresh = false;
@@ -135,10 +345,21 @@ async fn run_app(
                 match msg {
                     SshMessage::Connecting(host_name) => {
                         app.set_connecting(&host_name);
+                        need_refresh = true;
+                    }
+                    SshMessage::Cancelled(host_name) => {
+                        app.cancel_poll(&host_name);
+                        need_refresh = true;
                     }
                     SshMessage::Result(metrics) => {
                         app.last_poll = Some(std::time::Instant::now());
-                        app.host_metrics.insert(metrics.host_name.clone(), metrics);
+                        if let Some(alert) = app.transition_alert(&metrics) {
+                            if args.alert_on_transition {
+                                ring_bell();
+                            }
+                            app.push_alert(&metrics.host_name, alert);
+                        }
+                        app.record_result(*metrics);
                         need_refresh = true;
                     }
                 }
@@ -147,11 +368,39 @@ async fn run_app(
                 app.refresh_visible();
             }
         }
+        app.snapshot_if_due();
+    }
+
+    let _ = poller.send(PollerCommand::Shutdown);
+    let _ = poller_task.await;
+
+    if app.should_quit && args.ssh_multiplexing {
+        ssh::close_masters(&app.hosts, &args).await;
     }
 
     Ok(())
 }
 
+/// Hand the terminal to an interactive command (an SSH session) until it
+/// exits, then restore the TUI. The outer result is a terminal error, the
+/// inner one a failure to run the command.
+async fn run_interactive(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    mut cmd: tokio::process::Command,
+) -> Result<io::Result<std::process::ExitStatus>> {
+    disable_raw_mode()?;
+    io::stdout().execute(DisableMouseCapture)?;
+    io::stdout().execute(LeaveAlternateScreen)?;
+
+    let status = cmd.status().await;
+
+    enable_raw_mode()?;
+    io::stdout().execute(EnterAlternateScreen)?;
+    io::stdout().execute(EnableMouseCapture)?;
+    terminal.clear()?;
+    Ok(status)
+}
+
 fn map_key_normal(key: ct_event::KeyEvent, app: &mut App) -> AppAction {
     use ct_event::{KeyCode, KeyModifiers};
 
@@ -164,6 +413,14 @@ fn map_key_normal(key: ct_event::KeyEvent, app: &mut App) -> AppAction {
         };
     }
 
+    if app.detail_has_keys() {
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down => return AppAction::ScrollDetailDown,
+            KeyCode::Char('k') | KeyCode::Up => return AppAction::ScrollDetailUp,
+            _ => {}
+        }
+    }
+
     match key.code {
         KeyCode::Char('q') => AppAction::Quit,
         KeyCode::Char('j') | KeyCode::Down => AppAction::MoveDown,
@@ -171,11 +428,39 @@ fn map_key_normal(key: ct_event::KeyEvent, app: &mut App) -> AppAction {
         KeyCode::Char('g') => AppAction::Home,
         KeyCode::Char('G') => AppAction::End,
         KeyCode::Enter => AppAction::ToggleDetail,
+        KeyCode::Tab => AppAction::ToggleDetailFocus,
+        KeyCode::Char('F') => AppAction::FullScreenDetail,
+        KeyCode::Char(' ') => AppAction::ToggleSelect,
+        KeyCode::Char('y') => AppAction::CopyHostname,
+        KeyCode::Char('o') => AppAction::OpenSsh,
+        KeyCode::Char('f') => AppAction::ToggleFreeze,
+        KeyCode::Char('[') => AppAction::HistoryBack,
+        KeyCode::Char(']') => AppAction::HistoryForward,
         KeyCode::Char('s') => AppAction::CycleSort,
         KeyCode::Char('S') => AppAction::ReverseSort,
         KeyCode::Char('/') => AppAction::StartFilter,
         KeyCode::Char('r') => AppAction::ForceRefresh,
+        KeyCode::F(5) => match app.selected_host() {
+            Some(name) => AppAction::RefreshHost(name.to_string()),
+            None => AppAction::None,
+        },
+        KeyCode::Char('R') => AppAction::ReloadInventory,
+        KeyCode::Char('a') => AppAction::ToggleAddressColumn,
+        KeyCode::Char('u') => AppAction::ToggleFailedColumn,
+        KeyCode::Char('l') => AppAction::ToggleLoadColumn,
+        KeyCode::Char('t') => AppAction::ToggleTempColumn,
+        KeyCode::Char('n') => AppAction::ToggleNetColumn,
+        KeyCode::Char('L') => AppAction::ToggleLatencyColumn,
+        KeyCode::Char('U') => AppAction::ToggleUptimeColumn,
+        KeyCode::Char('c') => AppAction::CycleColumns,
+        KeyCode::Char('w') => AppAction::ToggleWideMode,
+        KeyCode::Char('C') => AppAction::ToggleCompactMode,
+        KeyCode::Char('m') => AppAction::ToggleGroupCount,
+        KeyCode::Char('H') => AppAction::ToggleGroupHeaders,
+        KeyCode::Char('!') => AppAction::ToggleWarnings,
+        KeyCode::Char('A') => AppAction::ToggleAlerts,
         KeyCode::Char('?') => AppAction::ToggleHelp,
+        KeyCode::Esc if app.detail_fullscreen => AppAction::FullScreenDetail,
         KeyCode::Esc => {
             if !app.filter_text.is_empty() {
                 app.filter_text.clear();
@@ -190,3 +475,23 @@ fn map_key_normal(key: ct_event::KeyEvent, app: &mut App) -> AppAction {
         _ => AppAction::None,
     }
 }
+
+/// Ring the terminal bell. Terminals and tmux can turn this into a visual or
+/// desktop notification.
+fn ring_bell() {
+    use std::io::Write;
+
+    let mut stdout = std::io::stdout();
+    let _ = stdout.write_all(b"\x07").and_then(|()| stdout.flush());
+}
+
+fn map_mouse(mouse: ct_event::MouseEvent) -> AppAction {
+    use ct_event::{MouseButton, MouseEventKind};
+
+    match mouse.kind {
+        MouseEventKind::Down(MouseButton::Left) => AppAction::Click(mouse.column, mouse.row),
+        MouseEventKind::ScrollDown => AppAction::ScrollDown(mouse.column, mouse.row),
+        MouseEventKind::ScrollUp => AppAction::ScrollUp(mouse.column, mouse.row),
+        _ => AppAction::None,
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span};
@@ -6,38 +8,59 @@ use ratatui::widgets::{
 };
 use ratatui::Frame;
 
-use super::app::{App, SortColumn};
-use crate::metrics::{HostStatus, Severity};
-
-/// 8 column headers in display order.
-const COLUMN_HEADERS: &[(&str, SortColumn)] = &[
-    ("St", SortColumn::Status),
-    ("Host", SortColumn::Name),
-    ("Group", SortColumn::Group),
-    ("CPU", SortColumn::Cpu),
-    ("Mem", SortColumn::Memory),
-    ("Disk", SortColumn::Disk),
-    ("IOw", SortColumn::IoWait),
-    ("Swap", SortColumn::Swap),
-];
-
-/// Column width constraints matching COLUMN_HEADERS order.
-const COLUMN_WIDTHS: &[Constraint] = &[
-    Constraint::Length(4),   // St
-    Constraint::Min(15),     // Host
-    Constraint::Length(12),  // Group
-    Constraint::Length(10),  // CPU
-    Constraint::Length(14),  // Mem
-    Constraint::Length(10),  // Disk
-    Constraint::Length(6),   // IOw
-    Constraint::Length(12),  // Swap
-];
+use super::app::{App, Column, MetricTrend, VisibleRow};
+use crate::inventory::types::{LocalFormat, DEFAULT_SWAP_CRITICAL, DEFAULT_SWAP_WARNING};
+use crate::metrics::{HostStatus, Metrics, Severity, SshErrorKind};
+
+/// Cells a trend arrow (` ↑`) takes after a CPU, Mem or Disk value.
+const TREND_WIDTH: u16 = 2;
+
+/// Width constraint for each table column. Wide mode trims the widest
+/// columns to make room for the extra ones; compact mode trims the usage
+/// columns further, to 8 cells for the value plus the trend arrow.
+fn column_width(col: Column, wide: bool, compact: bool) -> Constraint {
+    match (col, wide) {
+        (Column::Cpu | Column::Memory | Column::Disk, _) if compact => {
+            Constraint::Length(8 + TREND_WIDTH)
+        }
+        (Column::Host, true) => Constraint::Min(12),
+        (Column::Address, true) => Constraint::Length(15),
+        (Column::Group, true) => Constraint::Length(10),
+        (Column::Cpu | Column::Disk, true) => Constraint::Length(9),
+        (Column::Memory, true) => Constraint::Length(13),
+        (Column::Swap, true) => Constraint::Length(11),
+        (Column::Uptime, true) => Constraint::Length(10),
+        (col, _) => normal_width(col),
+    }
+}
+
+fn normal_width(col: Column) -> Constraint {
+    match col {
+        Column::Status => Constraint::Length(4),
+        Column::Host => Constraint::Min(15),
+        Column::Address => Constraint::Length(16),
+        Column::Group => Constraint::Length(12),
+        Column::Cpu => Constraint::Length(12),
+        Column::Memory => Constraint::Length(16),
+        Column::Disk => Constraint::Length(12),
+        Column::IoWait => Constraint::Length(6),
+        Column::Swap => Constraint::Length(12),
+        Column::Load => Constraint::Length(7),
+        Column::Temp => Constraint::Length(7),
+        Column::Failed => Constraint::Length(6),
+        Column::Net => Constraint::Length(8),
+        Column::Tcp => Constraint::Length(5),
+        Column::Latency => Constraint::Length(7),
+        Column::Uptime => Constraint::Length(12),
+    }
+}
 
 pub fn draw(f: &mut Frame, app: &mut App) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(3), // Header
+            // Header, a bare line in compact mode
+            Constraint::Length(if app.compact_mode { 1 } else { 3 }),
             Constraint::Min(5),   // Table
             Constraint::Length(1), // Footer
         ])
@@ -45,21 +68,36 @@ pub fn draw(f: &mut Frame, app: &mut App) {
 
     draw_header(f, app, chunks[0]);
 
-    if app.show_detail {
+    if app.detail_fullscreen {
+        // The table isn't drawn, so clicks must not land on its old rows
+        app.table_area = Rect::default();
+        app.detail_area = chunks[1];
+    } else if app.show_detail {
         let table_detail = Layout::default()
             .direction(Direction::Horizontal)
             .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
             .split(chunks[1]);
         draw_table(f, app, table_detail[0]);
-        draw_detail(f, app, table_detail[1]);
+        app.detail_area = table_detail[1];
     } else {
+        app.detail_area = Rect::default();
         draw_table(f, app, chunks[1]);
     }
+    if app.detail_visible() {
+        let (lines, max_scroll) = draw_detail(f, app, app.detail_area);
+        app.detail_lines = lines;
+        app.detail_max_scroll = max_scroll;
+        app.detail_scroll = app.detail_scroll.min(max_scroll);
+    }
 
     draw_footer(f, app, chunks[2]);
 
     if app.show_help {
         draw_help_overlay(f);
+    } else if app.show_warnings {
+        draw_warnings_overlay(f, app);
+    } else if app.show_alerts {
+        draw_alerts_overlay(f, app);
     }
 }
 
@@ -72,7 +110,7 @@ fn draw_header(f: &mut Frame, app: &App, area: Rect) {
         })
         .unwrap_or_else(|| "never".to_string());
 
-    let title = Line::from(vec![
+    let mut title = Line::from(vec![
         Span::styled(
             " Ansimon v0.1.0 ",
             Style::default()
@@ -91,6 +129,20 @@ fn draw_header(f: &mut Frame, app: &App, area: Rect) {
         Span::raw(" │ "),
         Span::styled(format!("Last poll: {elapsed}"), Style::default().fg(Color::DarkGray)),
         Span::raw(" │ "),
+    ]);
+    // Yellow once the countdown runs out, until the next round starts
+    if let Some(left) = app.next_poll_in() {
+        // Round up, so the countdown reads 1s rather than 0s in its last second
+        let secs = left.as_millis().div_ceil(1000);
+        let style = if left.is_zero() {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+        title.spans.push(Span::styled(format!("Next: {secs}s"), style));
+        title.spans.push(Span::raw(" │ "));
+    }
+    title.spans.extend([
         Span::styled(
             format!("Sort: {} {}", app.sort_column.label(), if app.sort_ascending { "▲" } else { "▼" }),
             Style::default().fg(Color::DarkGray),
@@ -98,30 +150,78 @@ fn draw_header(f: &mut Frame, app: &App, area: Rect) {
         Span::raw(" │ "),
         Span::styled("[?] Help", Style::default().fg(Color::DarkGray)),
     ]);
+    if let Some(age) = app.history_age() {
+        title.spans.push(Span::raw(" │ "));
+        title.spans.push(Span::styled(
+            format!("[HISTORY -{}s]", age.as_secs()),
+            Style::default().fg(Color::Black).bg(Color::Magenta).add_modifier(Modifier::BOLD),
+        ));
+    }
+    if !app.inventory_warnings.is_empty() {
+        title.spans.push(Span::raw(" │ "));
+        title.spans.push(Span::styled(
+            format!("⚠ {} inventory warning(s) [!]", app.inventory_warnings.len()),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
 
-    let block = Block::default()
-        .borders(Borders::ALL)
-        .border_style(Style::default().fg(Color::DarkGray));
-
-    let header = Paragraph::new(title).block(block);
+    let mut header = Paragraph::new(title);
+    if !app.compact_mode {
+        header = header.block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::DarkGray)),
+        );
+    }
     f.render_widget(header, area);
 }
 
+/// Narrowest table that fits `widths`: each column at its minimum, the
+/// spaces between them, the highlight symbol and the borders.
+fn min_table_width(widths: &[Constraint]) -> u16 {
+    let columns: u16 = widths
+        .iter()
+        .map(|w| match w {
+            Constraint::Length(n) | Constraint::Min(n) => *n,
+            _ => 0,
+        })
+        .sum();
+    columns + widths.len().saturating_sub(1) as u16 + 2 + 2
+}
+
 fn draw_table(f: &mut Frame, app: &mut App, area: Rect) {
-    let header_cells = COLUMN_HEADERS.iter().map(|(label, col)| {
-        let style = if *col == app.sort_column {
+    app.table_area = area;
+
+    // Wide mode falls back to the normal columns when they wouldn't fit
+    let compact = app.compact_mode;
+    let layout = |wide: bool| {
+        let columns = app.table_columns(wide);
+        let widths: Vec<Constraint> =
+            columns.iter().map(|c| column_width(*c, wide, compact)).collect();
+        (columns, widths)
+    };
+    let (mut columns, mut widths) = layout(app.wide_mode);
+    let fallback = app.wide_mode && min_table_width(&widths) > area.width;
+    if fallback {
+        (columns, widths) = layout(false);
+    }
+    app.wide_fallback = fallback;
+
+    let header_cells = columns.iter().map(|col| {
+        let sorted = col.sort_column() == Some(app.sort_column);
+        let style = if sorted {
             Style::default()
                 .fg(Color::Cyan)
                 .add_modifier(Modifier::BOLD)
         } else {
             Style::default().fg(Color::White)
         };
-        let indicator = if *col == app.sort_column {
+        let indicator = if sorted {
             if app.sort_ascending { " ▲" } else { " ▼" }
         } else {
             ""
         };
-        Cell::from(format!("{label}{indicator}")).style(style)
+        Cell::from(format!("{}{indicator}", col.label())).style(style)
     });
 
     let header = Row::new(header_cells)
@@ -130,92 +230,166 @@ fn draw_table(f: &mut Frame, app: &mut App, area: Rect) {
 
     let warn = app.warning_threshold;
     let crit = app.critical_threshold;
+    let load_warn = app.load_warning_per_cpu;
+    let load_crit = app.load_critical_per_cpu;
+    let temp_warn = app.temp_warning;
+    let temp_crit = app.temp_critical;
 
     let rows: Vec<Row> = app
-        .visible_hosts
+        .visible_rows
         .iter()
-        .map(|host_name| {
-            let hm = app.host_metrics.get(host_name);
+        .enumerate()
+        .map(|(index, row)| {
+            let host_name = match row {
+                VisibleRow::Host(name) => name,
+                VisibleRow::Group(group) => {
+                    // The group's hosts are the rows up to the next header
+                    let members: Vec<&str> = app.visible_rows[index + 1..]
+                        .iter()
+                        .map_while(|r| match r {
+                            VisibleRow::Host(name) => Some(name.as_str()),
+                            VisibleRow::Group(_) => None,
+                        })
+                        .collect();
+                    return group_row(app, &columns, group, &members);
+                }
+            };
+            let hm = app.displayed_metrics().get(host_name);
             let host = app.hosts.iter().find(|h| h.name == *host_name);
 
             let status = hm
                 .map(|m| m.status)
                 .unwrap_or(HostStatus::Unknown);
-            let status_indicator = status.indicator();
+            let status_indicator = if app.paused_hosts.contains(host_name) {
+                "[F]".to_string()
+            } else {
+                hm.map(|m| m.status_indicator())
+                    .unwrap_or_else(|| status.indicator().to_string())
+            };
             let status_color = match status {
                 HostStatus::Up => Color::Green,
                 HostStatus::Down => Color::Red,
                 HostStatus::Connecting => Color::Yellow,
                 HostStatus::Unknown => Color::DarkGray,
+                HostStatus::Disabled => Color::Blue,
             };
 
-            let group = host
-                .and_then(|h| h.groups.first())
-                .cloned()
-                .unwrap_or_default();
-
-            let severity_color = |sev: &Severity| match sev {
-                Severity::Ok => Color::Green,
-                Severity::Warning => Color::Yellow,
-                Severity::Critical => Color::Red,
+            let group = match host {
+                Some(h) if app.show_group_count && h.other_group_count() > 0 => {
+                    format!("{},+{}", h.display_group(), h.other_group_count())
+                }
+                Some(h) => h.display_group().to_string(),
+                None => String::new(),
             };
 
-            let row_style = match hm.map(|m| m.status) {
-                Some(HostStatus::Down) => Style::default().fg(Color::DarkGray),
+            let mut row_style = match hm.map(|m| m.status) {
+                Some(HostStatus::Down | HostStatus::Disabled) => Style::default().fg(Color::DarkGray),
                 Some(HostStatus::Connecting) => Style::default().fg(Color::Yellow),
                 _ => Style::default(),
             };
+            if app.selected_hosts.contains(host_name) {
+                row_style = row_style.bg(Color::Blue);
+            }
 
-            match hm.and_then(|m| m.metrics.as_ref()) {
-                Some(m) => {
-                    let cpu_sev = m.cpu_severity(warn, crit);
-                    let mem_sev = m.mem_severity(warn, crit);
-                    let disk_sev = m.disk_severity(warn, crit);
-                    let iow_sev = m.iowait_severity();
+            let placeholder = match hm.map(|m| m.status) {
+                Some(HostStatus::Connecting) => "...",
+                _ => "--",
+            };
+            let metrics = hm.and_then(|m| m.metrics.as_ref());
+            let trends = app.trends.get(host_name);
+            let bar = |col: Column, pct: f64| {
+                let width = match columns.iter().position(|c| *c == col).map(|i| widths[i]) {
+                    Some(Constraint::Length(n)) => n,
+                    _ => 0,
+                };
+                // Leave room for the trend arrow
+                usage_bar(pct, width.saturating_sub(if trends.is_some() { 2 } else { 0 }))
+            };
+            let cpu_style = |m: &Metrics| {
+                Style::default().fg(severity_color(&m.cpu_severity(warn, crit)))
+            };
+            let mem_style = |m: &Metrics| {
+                Style::default().fg(severity_color(&m.mem_severity(warn, crit)))
+            };
 
+            let cells: Vec<Cell> = columns
+                .iter()
+                .map(|col| match (col, metrics) {
+                    (Column::Status, _) => Cell::from(status_indicator.clone())
+                        .style(Style::default().fg(status_color)),
+                    (Column::Host, _) => Cell::from(host_name.clone()),
+                    (Column::Address, _) => {
+                        Cell::from(host.map(|h| h.display_address()).unwrap_or_default())
+                    }
+                    (Column::Group, _) => Cell::from(group.clone()),
+                    (Column::Latency, _) => match hm.and_then(|m| m.ssh_latency_ms) {
+                        Some(ms) => Cell::from(format!("{ms}ms")),
+                        None => Cell::from(placeholder.to_string()),
+                    },
+                    (_, None) => Cell::from(placeholder.to_string()),
+                    (Column::Cpu, Some(m)) if app.bar_charts => {
+                        trend_cell(bar(*col, m.cpu_percent), cpu_style(m), trends.map(|t| t.cpu))
+                    }
+                    (Column::Memory, Some(m)) if app.bar_charts => {
+                        trend_cell(bar(*col, m.mem_percent()), mem_style(m), trends.map(|t| t.mem))
+                    }
+                    (Column::Cpu, Some(m)) => {
+                        trend_cell(m.cpu_display(warn, crit), cpu_style(m), trends.map(|t| t.cpu))
+                    }
+                    // Compact mode has no room for used/total
+                    (Column::Memory, Some(m)) if compact => {
+                        let indicator = m.mem_severity(warn, crit).indicator();
+                        let text = format!("{indicator} {:.0}%", m.mem_percent());
+                        trend_cell(text, mem_style(m), trends.map(|t| t.mem))
+                    }
+                    (Column::Memory, Some(m)) => {
+                        trend_cell(m.mem_display(warn, crit), mem_style(m), trends.map(|t| t.mem))
+                    }
+                    (Column::Disk, Some(m)) => trend_cell(
+                        m.disk_display(warn, crit),
+                        Style::default().fg(severity_color(&m.disk_severity(warn, crit))),
+                        trends.map(|t| t.disk),
+                    ),
+                    (Column::IoWait, Some(m)) => Cell::from(m.iowait_display())
+                        .style(Style::default().fg(severity_color(&m.iowait_severity()))),
                     // Swap: N/A in white when not present, severity color otherwise
-                    let swap_cell = if m.has_swap() {
-                        let swap_sev = m.swap_severity();
-                        Cell::from(m.swap_display()).style(Style::default().fg(severity_color(&swap_sev)))
-                    } else {
-                        Cell::from("N/A").style(Style::default().fg(Color::White))
-                    };
-
-                    Row::new(vec![
-                        Cell::from(status_indicator.to_string()).style(Style::default().fg(status_color)),
-                        Cell::from(host_name.clone()),
-                        Cell::from(group),
-                        Cell::from(m.cpu_display(warn, crit)).style(Style::default().fg(severity_color(&cpu_sev))),
-                        Cell::from(m.mem_display(warn, crit)).style(Style::default().fg(severity_color(&mem_sev))),
-                        Cell::from(m.disk_display(warn, crit)).style(Style::default().fg(severity_color(&disk_sev))),
-                        Cell::from(m.iowait_display()).style(Style::default().fg(severity_color(&iow_sev))),
-                        swap_cell,
-                    ])
-                    .style(row_style)
-                }
-                None => {
-                    let placeholder = match hm.map(|m| m.status) {
-                        Some(HostStatus::Connecting) => "...",
-                        _ => "--",
-                    };
-                    let p = placeholder.to_string();
-                    Row::new(vec![
-                        Cell::from(status_indicator.to_string()).style(Style::default().fg(status_color)),
-                        Cell::from(host_name.clone()),
-                        Cell::from(group),
-                        Cell::from(p.clone()),
-                        Cell::from(p.clone()),
-                        Cell::from(p.clone()),
-                        Cell::from(p.clone()),
-                        Cell::from(p),
-                    ])
-                    .style(row_style)
-                }
-            }
+                    (Column::Swap, Some(m)) => {
+                        if m.has_swap() {
+                            let (swap_warn, swap_crit) = host
+                                .map(|h| h.swap_thresholds())
+                                .unwrap_or((DEFAULT_SWAP_WARNING, DEFAULT_SWAP_CRITICAL));
+                            Cell::from(m.swap_display(swap_warn, swap_crit)).style(Style::default().fg(
+                                severity_color(&m.swap_severity(swap_warn, swap_crit)),
+                            ))
+                        } else {
+                            Cell::from("N/A").style(Style::default().fg(Color::White))
+                        }
+                    }
+                    (Column::Load, Some(m)) => Cell::from(format!("{:.2}", m.load_1)).style(
+                        Style::default().fg(severity_color(&m.load_severity(load_warn, load_crit))),
+                    ),
+                    (Column::Temp, Some(m)) => match m.max_temp_celsius {
+                        Some(t) => Cell::from(format!("{t:.0}°C")).style(Style::default().fg(
+                            severity_color(&m.temp_severity(temp_warn, temp_crit)),
+                        )),
+                        None => Cell::from("N/A").style(Style::default().fg(Color::White)),
+                    },
+                    (Column::Net, Some(m)) => Cell::from(format!(
+                        "{}/s",
+                        crate::metrics::human_bytes(m.net_rx_bytes_sec + m.net_tx_bytes_sec)
+                    )),
+                    (Column::Tcp, Some(m)) => Cell::from(m.tcp_established.to_string()),
+                    (Column::Uptime, Some(m)) => Cell::from(format_uptime(m.uptime_secs)),
+                    (Column::Failed, Some(m)) => Cell::from(m.systemd_failed_units.to_string())
+                        .style(failed_units_style(m.systemd_failed_units)),
+                })
+                .collect();
+
+            Row::new(cells).style(row_style)
         })
         .collect();
 
-    let table = Table::new(rows, COLUMN_WIDTHS.to_vec())
+    let table = Table::new(rows, widths)
         .header(header)
         .block(
             Block::default()
@@ -233,13 +407,119 @@ fn draw_table(f: &mut Frame, app: &mut App, area: Rect) {
     f.render_stateful_widget(table, area, &mut app.table_state);
 }
 
-fn draw_detail(f: &mut Frame, app: &App, area: Rect) {
+/// Summary row for a group header: host count and aggregates (average CPU,
+/// memory, IO wait and load, fullest disk) over its hosts with metrics.
+fn group_row<'a>(app: &App, columns: &[Column], group: &str, members: &[&str]) -> Row<'a> {
+    let (warn, crit) = (app.warning_threshold, app.critical_threshold);
+    let metrics: Vec<&Metrics> = members
+        .iter()
+        .filter_map(|name| app.displayed_metrics().get(*name)?.metrics.as_ref())
+        .collect();
+    let up = members
+        .iter()
+        .filter(|name| {
+            app.displayed_metrics().get(**name).is_some_and(|m| m.status == HostStatus::Up)
+        })
+        .count();
+    let average = |value: fn(&Metrics) -> f64| {
+        (!metrics.is_empty())
+            .then(|| metrics.iter().map(|m| value(m)).sum::<f64>() / metrics.len() as f64)
+    };
+    let percent_cell = |label: &str, pct: Option<f64>| match pct {
+        Some(pct) => Cell::from(format!("{label} {pct:.0}%")).style(
+            Style::default().fg(severity_color(&Severity::from_percent(pct, warn, crit))),
+        ),
+        None => Cell::from(""),
+    };
+
+    let cells: Vec<Cell> = columns
+        .iter()
+        .map(|col| match col {
+            Column::Host => Cell::from(format!("▾ {group} ({up}/{} up)", members.len())),
+            Column::Cpu => percent_cell("avg", average(|m| m.cpu_percent)),
+            Column::Memory => percent_cell("avg", average(|m| m.mem_percent())),
+            Column::Disk => percent_cell(
+                "max",
+                metrics.iter().map(|m| m.disk_percent).reduce(f64::max),
+            ),
+            Column::IoWait => match average(|m| m.iowait_percent) {
+                Some(pct) => Cell::from(format!("{pct:.1}%")),
+                None => Cell::from(""),
+            },
+            Column::Load => match average(|m| m.load_1) {
+                Some(load) => Cell::from(format!("{load:.2}")),
+                None => Cell::from(""),
+            },
+            _ => Cell::from(""),
+        })
+        .collect();
+    Row::new(cells).style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+}
+
+/// A usage bar for a `width` column cell: the percentage right-aligned in
+/// the last five columns and a bar filling the rest (`████░░  45%`).
+fn usage_bar(pct: f64, width: u16) -> String {
+    let bar_width = width.saturating_sub(5) as usize;
+    let filled = (pct.clamp(0.0, 100.0) / 100.0 * bar_width as f64).round() as usize;
+    format!("{}{} {pct:>3.0}%", "█".repeat(filled), "░".repeat(bar_width - filled))
+}
+
+/// A usage cell with its trend appended: ↑ in yellow when rising, ↓ in
+/// green when falling, → when steady. No arrow without a trend to show.
+fn trend_cell<'a>(text: String, style: Style, trend: Option<MetricTrend>) -> Cell<'a> {
+    let arrow = match trend {
+        None => return Cell::from(text).style(style),
+        Some(t) if t.rising => Span::styled(" ↑", Style::default().fg(Color::Yellow)),
+        Some(t) if t.falling => Span::styled(" ↓", Style::default().fg(Color::Green)),
+        Some(_) => Span::raw(" →"),
+    };
+    Cell::from(Line::from(vec![Span::styled(text, style), arrow]))
+}
+
+/// Format an uptime as days, hours and minutes (`3d 4h 12m`).
+fn format_uptime(secs: u64) -> String {
+    let days = secs / 86400;
+    let hours = (secs % 86400) / 3600;
+    let mins = (secs % 3600) / 60;
+    format!("{}d {}h {}m", days, hours, mins)
+}
+
+/// Format an elapsed time compactly, in its two largest units (`3h 12m`).
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h {}m", secs / 3600, (secs % 3600) / 60),
+        _ => format!("{}d {}h", secs / 86400, (secs % 86400) / 3600),
+    }
+}
+
+fn failed_units_style(count: u32) -> Style {
+    if count > 0 {
+        Style::default().fg(Color::Red)
+    } else {
+        Style::default().fg(Color::Green)
+    }
+}
+
+fn severity_color(sev: &Severity) -> Color {
+    match sev {
+        Severity::Ok => Color::Green,
+        Severity::Warning => Color::Yellow,
+        Severity::Critical => Color::Red,
+    }
+}
+
+/// Draw the detail panel, returning the content's height in rows and the
+/// largest scroll offset that still fills the panel.
+fn draw_detail(f: &mut Frame, app: &App, area: Rect) -> (u16, u16) {
     let warn = app.warning_threshold;
     let crit = app.critical_threshold;
 
     let content = if let Some(host_name) = app.selected_host() {
         let host = app.hosts.iter().find(|h| h.name == host_name);
-        let hm = app.host_metrics.get(host_name);
+        let hm = app.displayed_metrics().get(host_name);
 
         let mut lines = vec![
             Line::from(vec![
@@ -249,22 +529,42 @@ fn draw_detail(f: &mut Frame, app: &App, area: Rect) {
         ];
 
         if let Some(host) = host {
-            lines.push(Line::from(vec![
-                Span::styled("Address: ", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(host.effective_host()),
-            ]));
-            lines.push(Line::from(vec![
-                Span::styled("Port: ", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(host.effective_port().to_string()),
-            ]));
+            if host.is_local() {
+                lines.push(Line::from(vec![
+                    Span::styled("Connection: ", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw("local"),
+                ]));
+            } else if host.collects_locally() {
+                let command = host.local_command().unwrap_or_else(|| "(not set)".to_string());
+                lines.push(Line::from(vec![
+                    Span::styled("Collector: ", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(match host.local_format() {
+                        LocalFormat::Ansimon => "local command",
+                        LocalFormat::Prometheus => "local command (Prometheus format)",
+                    }),
+                ]));
+                lines.push(Line::from(vec![
+                    Span::styled("Command: ", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(command),
+                ]));
+            } else {
+                lines.push(Line::from(vec![
+                    Span::styled("Address: ", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(host.display_address()),
+                ]));
+                lines.push(Line::from(vec![
+                    Span::styled("Port: ", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(app.args.port_for(host).to_string()),
+                ]));
+            }
             lines.push(Line::from(vec![
                 Span::styled("Groups: ", Style::default().add_modifier(Modifier::BOLD)),
                 Span::raw(host.groups.join(", ")),
             ]));
-            if let Some(user) = &host.ansible_user {
+            if let Some(user) = app.args.user_for(host) {
                 lines.push(Line::from(vec![
                     Span::styled("User: ", Style::default().add_modifier(Modifier::BOLD)),
-                    Span::raw(user.clone()),
+                    Span::raw(user.to_string()),
                 ]));
             }
         }
@@ -277,6 +577,7 @@ fn draw_detail(f: &mut Frame, app: &App, area: Rect) {
                 HostStatus::Down => Color::Red,
                 HostStatus::Connecting => Color::Yellow,
                 HostStatus::Unknown => Color::DarkGray,
+                HostStatus::Disabled => Color::Blue,
             };
             lines.push(Line::from(vec![
                 Span::styled("Status: ", Style::default().add_modifier(Modifier::BOLD)),
@@ -286,6 +587,35 @@ fn draw_detail(f: &mut Frame, app: &App, area: Rect) {
                 ),
             ]));
 
+            if let (HostStatus::Down, Some(since)) = (hm.status, hm.down_since) {
+                let last_ok = match hm.last_success {
+                    Some(t) => format!("last OK {} ago", format_age(t.elapsed())),
+                    None => "never OK".to_string(),
+                };
+                let polls = if hm.consecutive_failures == 1 { "poll" } else { "polls" };
+                lines.push(Line::from(Span::styled(
+                    format!(
+                        "Down for {} ({} failed {polls}), {last_ok}",
+                        format_age(since.elapsed()),
+                        hm.consecutive_failures,
+                    ),
+                    Style::default().fg(Color::Red),
+                )));
+            }
+
+            if let Some(kind) = hm.error_kind {
+                // A changed host key is security-relevant: make it stand out
+                let style = if kind == SshErrorKind::HostKeyChanged {
+                    Style::default().fg(Color::White).bg(Color::Red).add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(Color::Red)
+                };
+                lines.push(Line::from(vec![
+                    Span::styled("Failure: ", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
+                    Span::styled(kind.label(), style),
+                ]));
+            }
+
             if let Some(ref err) = hm.error {
                 lines.push(Line::from(vec![
                     Span::styled("Error: ", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
@@ -293,6 +623,16 @@ fn draw_detail(f: &mut Frame, app: &App, area: Rect) {
                 ]));
             }
 
+            if let Some(err) = hm.metrics.as_ref().and_then(|m| m.become_error.as_ref()) {
+                lines.push(Line::from(vec![
+                    Span::styled("Become: ", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                    Span::styled(
+                        format!("{err} (running unprivileged)"),
+                        Style::default().fg(Color::Yellow),
+                    ),
+                ]));
+            }
+
             if let Some(ref m) = hm.metrics {
                 lines.push(Line::from(""));
                 lines.push(Line::from(vec![
@@ -311,14 +651,39 @@ fn draw_detail(f: &mut Frame, app: &App, area: Rect) {
                     Span::styled("Disk:     ", Style::default().add_modifier(Modifier::BOLD)),
                     Span::raw(m.disk_display(warn, crit)),
                 ]));
+                for mount in &m.inode_mounts {
+                    let sev = mount.inode_severity(warn, crit);
+                    lines.push(Line::from(vec![
+                        Span::styled("  Inodes: ", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::styled(
+                            format!("{} {:.0}%", sev.indicator(), mount.used_pct),
+                            Style::default().fg(severity_color(&sev)),
+                        ),
+                        Span::raw(format!(" {}", mount.mount)),
+                    ]));
+                }
+                let fd_sev = m.fd_severity(warn, crit);
+                lines.push(Line::from(vec![
+                    Span::styled("FDs:      ", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled(
+                        format!("{} {} / {} ({:.1}%)", fd_sev.indicator(), m.fd_allocated, m.fd_max, m.fd_used_pct),
+                        Style::default().fg(severity_color(&fd_sev)),
+                    ),
+                ]));
                 lines.push(Line::from(vec![
                     Span::styled("IO Wait:  ", Style::default().add_modifier(Modifier::BOLD)),
                     Span::raw(m.iowait_display()),
                 ]));
                 if m.has_swap() {
+                    let (swap_warn, swap_crit) = host
+                        .map(|h| h.swap_thresholds())
+                        .unwrap_or((DEFAULT_SWAP_WARNING, DEFAULT_SWAP_CRITICAL));
                     lines.push(Line::from(vec![
                         Span::styled("Swap:     ", Style::default().add_modifier(Modifier::BOLD)),
-                        Span::raw(m.swap_display()),
+                        Span::styled(
+                            m.swap_display(swap_warn, swap_crit),
+                            Style::default().fg(severity_color(&m.swap_severity(swap_warn, swap_crit))),
+                        ),
                     ]));
                 } else {
                     lines.push(Line::from(vec![
@@ -328,7 +693,13 @@ fn draw_detail(f: &mut Frame, app: &App, area: Rect) {
                 }
                 lines.push(Line::from(vec![
                     Span::styled("Load:     ", Style::default().add_modifier(Modifier::BOLD)),
-                    Span::raw(format!("{:.2} / {:.2} / {:.2}", m.load_1, m.load_5, m.load_15)),
+                    Span::styled(
+                        format!("{:.2} / {:.2} / {:.2}", m.load_1, m.load_5, m.load_15),
+                        Style::default().fg(severity_color(&m.load_severity(
+                            app.load_warning_per_cpu,
+                            app.load_critical_per_cpu,
+                        ))),
+                    ),
                 ]));
                 lines.push(Line::from(vec![
                     Span::styled("Net I/O:  ", Style::default().add_modifier(Modifier::BOLD)),
@@ -336,39 +707,173 @@ fn draw_detail(f: &mut Frame, app: &App, area: Rect) {
                         crate::metrics::human_bytes(m.net_rx_bytes_sec),
                         crate::metrics::human_bytes(m.net_tx_bytes_sec))),
                 ]));
+                for iface in m.top_interfaces(3) {
+                    lines.push(Line::from(vec![
+                        Span::raw(format!("  {:<8}", iface.name)),
+                        Span::raw(format!("RX {} / TX {}",
+                            crate::metrics::human_bytes(iface.rx_bytes_sec),
+                            crate::metrics::human_bytes(iface.tx_bytes_sec))),
+                    ]));
+                }
+                lines.push(Line::from(vec![
+                    Span::styled("Net Err:  ", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled(
+                        format!(
+                            "RX {} err, {} drop / TX {} err, {} drop",
+                            m.net_rx_errors, m.net_rx_drops, m.net_tx_errors, m.net_tx_drops
+                        ),
+                        if m.has_net_faults() {
+                            Style::default().fg(Color::Red)
+                        } else {
+                            Style::default().fg(Color::Green)
+                        },
+                    ),
+                ]));
                 lines.push(Line::from(vec![
                     Span::styled("TCP:      ", Style::default().add_modifier(Modifier::BOLD)),
                     Span::raw(m.tcp_display()),
-                    Span::raw(" connections"),
+                    Span::raw(format!(" {}", m.tcp_states_display())),
+                ]));
+                lines.push(Line::from(vec![
+                    Span::styled("Sockets:  ", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(format!(
+                        "UDP {} / RAW {} / FRAG {}",
+                        m.udp_conns, m.raw_sockets, m.frag_inuse
+                    )),
                 ]));
                 lines.push(Line::from(vec![
                     Span::styled("Procs:    ", Style::default().add_modifier(Modifier::BOLD)),
                     Span::raw(format!("{} running / {} total", m.procs_running, m.procs_total)),
                 ]));
+                let state_style = |n: u32| {
+                    if n > 0 {
+                        Style::default().fg(Color::Red)
+                    } else {
+                        Style::default()
+                    }
+                };
+                lines.push(Line::from(vec![
+                    Span::styled("  Zombie: ", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled(m.procs_zombie.to_string(), state_style(m.procs_zombie)),
+                    Span::styled("  D-state: ", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled(m.procs_dstate.to_string(), state_style(m.procs_dstate)),
+                ]));
                 lines.push(Line::from(vec![
                     Span::styled("Disk I/O: ", Style::default().add_modifier(Modifier::BOLD)),
                     Span::raw(format!("R {} / W {}",
                         crate::metrics::human_bytes(m.disk_read_bytes_sec),
                         crate::metrics::human_bytes(m.disk_write_bytes_sec))),
                 ]));
+                if let Some(temp) = m.max_temp_celsius {
+                    lines.push(Line::from(vec![
+                        Span::styled("Temp:     ", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::styled(
+                            format!("{temp:.1}°C (max of {} zones)", m.temps_celsius.len()),
+                            Style::default().fg(severity_color(
+                                &m.temp_severity(app.temp_warning, app.temp_critical),
+                            )),
+                        ),
+                    ]));
+                }
+                if let Some(entropy) = m.entropy_avail {
+                    lines.push(Line::from(vec![
+                        Span::styled("Entropy:  ", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::styled(
+                            format!("{entropy} bits"),
+                            Style::default().fg(severity_color(
+                                &m.entropy_severity(app.entropy_warning, app.entropy_critical),
+                            )),
+                        ),
+                    ]));
+                }
+                lines.push(Line::from(vec![
+                    Span::styled("Failed:   ", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled(
+                        format!("{} systemd unit(s)", m.systemd_failed_units),
+                        failed_units_style(m.systemd_failed_units),
+                    ),
+                ]));
                 lines.push(Line::from(vec![
                     Span::styled("CPUs:     ", Style::default().add_modifier(Modifier::BOLD)),
                     Span::raw(m.num_cpus.to_string()),
                 ]));
 
-                let days = m.uptime_secs / 86400;
-                let hours = (m.uptime_secs % 86400) / 3600;
-                let mins = (m.uptime_secs % 3600) / 60;
                 lines.push(Line::from(vec![
                     Span::styled("Uptime:   ", Style::default().add_modifier(Modifier::BOLD)),
-                    Span::raw(format!("{}d {}h {}m", days, hours, mins)),
+                    Span::raw(format_uptime(m.uptime_secs)),
+                ]));
+
+                if let Some(ref kernel) = m.kernel_version {
+                    lines.push(Line::from(vec![
+                        Span::styled("Kernel:   ", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(kernel.clone()),
+                    ]));
+                }
+                let os = match (&m.os_info, &m.os_type) {
+                    (Some(info), Some(kind)) => Some(format!("{info} ({kind})")),
+                    (info, kind) => info.as_ref().or(kind.as_ref()).cloned(),
+                };
+                if let Some(os) = os {
+                    lines.push(Line::from(vec![
+                        Span::styled("OS:       ", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(os),
+                    ]));
+                }
+
+                if !app.custom_metrics.is_empty() {
+                    lines.push(Line::from(""));
+                    for custom in &app.custom_metrics {
+                        let value = m.custom.get(&custom.name).map(|v| v.as_str()).unwrap_or("");
+                        let mut values = value.lines();
+                        let first = match values.next() {
+                            Some(line) if custom.unit.is_empty() => line.to_string(),
+                            Some(line) => format!("{line} {}", custom.unit),
+                            None => "--".to_string(),
+                        };
+                        lines.push(Line::from(vec![
+                            Span::styled(
+                                format!("{}: ", custom.name),
+                                Style::default().add_modifier(Modifier::BOLD),
+                            ),
+                            Span::raw(first),
+                        ]));
+                        // Multi-line output (e.g. queue listings) is shown indented
+                        for line in values {
+                            lines.push(Line::from(format!("  {line}")));
+                        }
+                    }
+                }
+            }
+
+            if let Some(jump) = &hm.jump_host {
+                lines.push(Line::from(vec![
+                    Span::styled("Via:      ", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(jump.clone()),
+                ]));
+            }
+
+            if let Some(file) = &hm.ssh_config {
+                lines.push(Line::from(vec![
+                    Span::styled("SSH cfg:  ", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(file.clone()),
                 ]));
             }
 
             if let Some(latency) = hm.ssh_latency_ms {
                 lines.push(Line::from(vec![
                     Span::styled("SSH Lat:  ", Style::default().add_modifier(Modifier::BOLD)),
-                    Span::raw(format!("{}ms", latency)),
+                    Span::raw(if hm.multiplexed {
+                        format!("{}ms (multiplexed)", latency)
+                    } else {
+                        format!("{}ms", latency)
+                    }),
+                ]));
+            }
+
+            if let Some(runtime) = hm.metrics.as_ref().and_then(|m| m.command_runtime_ms) {
+                lines.push(Line::from(vec![
+                    Span::styled("Cmd Time: ", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(format!("{}ms", runtime)),
                 ]));
             }
 
@@ -389,28 +894,54 @@ fn draw_detail(f: &mut Frame, app: &App, area: Rect) {
         vec![Line::from("No host selected")]
     };
 
+    // Rows the content takes once wrapped. Word wrapping can take a row
+    // more than this on long lines, which only costs some blank space.
+    let inner_width = usize::from(area.width.saturating_sub(2).max(1));
+    let height: usize = content
+        .iter()
+        .map(|line| line.width().div_ceil(inner_width).max(1))
+        .sum();
+    let height = u16::try_from(height).unwrap_or(u16::MAX);
+    let max_scroll = height.saturating_sub(area.height.saturating_sub(2));
+
+    let border = if app.detail_focused { Color::Cyan } else { Color::DarkGray };
     let detail = Paragraph::new(content)
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .border_style(Style::default().fg(Color::DarkGray))
+                .border_style(Style::default().fg(border))
                 .title(" Details "),
         )
-        .wrap(Wrap { trim: true });
+        .wrap(Wrap { trim: true })
+        .scroll((app.detail_scroll.min(max_scroll), 0));
 
     f.render_widget(detail, area);
+    (height, max_scroll)
 }
 
 fn draw_footer(f: &mut Frame, app: &App, area: Rect) {
-    let footer = if app.filter_mode {
+    let footer = if let (true, Some((name, filter))) = (app.filter_mode, app.selected_preset()) {
+        Line::from(vec![
+            Span::styled(" preset: ", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+            Span::styled(name.as_str(), Style::default().fg(Color::Yellow)),
+            Span::styled(format!(" ({filter})"), Style::default().fg(Color::DarkGray)),
+            Span::styled(
+                "  (Tab next, Enter apply, Esc cancel)",
+                Style::default().fg(Color::DarkGray),
+            ),
+        ])
+    } else if app.filter_mode {
         Line::from(vec![
             Span::styled(" /", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
             Span::raw(&app.filter_text),
             Span::styled("█", Style::default().fg(Color::Cyan)),
-            Span::styled("  (Enter confirm, Esc cancel)", Style::default().fg(Color::DarkGray)),
+            Span::styled(
+                "  (status:up|down|..., group:NAME, comma to combine; Enter confirm, Esc cancel)",
+                Style::default().fg(Color::DarkGray),
+            ),
         ])
     } else {
-        Line::from(vec![
+        let mut spans = vec![
             Span::styled(" q", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
             Span::raw(":Quit  "),
             Span::styled("j/k", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
@@ -425,15 +956,45 @@ fn draw_footer(f: &mut Frame, app: &App, area: Rect) {
             Span::raw(":Refresh  "),
             Span::styled("?", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
             Span::raw(":Help"),
-            if !app.filter_text.is_empty() {
-                Span::styled(
-                    format!("  [filter: {}]", app.filter_text),
-                    Style::default().fg(Color::Yellow),
-                )
-            } else {
-                Span::raw("")
-            },
-        ])
+        ];
+        if !app.filter_text.is_empty() {
+            spans.push(Span::styled(
+                format!("  [filter: {}]", app.filter_text),
+                Style::default().fg(Color::Yellow),
+            ));
+        }
+        if app.detail_visible() && app.detail_max_scroll > 0 {
+            let visible = app.detail_lines - app.detail_max_scroll;
+            let hint = if app.detail_has_keys() { "" } else { ", Tab to scroll" };
+            spans.push(Span::styled(
+                format!(
+                    "  [detail {}-{}/{}{hint}]",
+                    app.detail_scroll + 1,
+                    app.detail_scroll + visible,
+                    app.detail_lines
+                ),
+                Style::default().fg(Color::Cyan),
+            ));
+        }
+        if app.wide_fallback {
+            spans.push(Span::styled(
+                "  [too narrow for wide mode]",
+                Style::default().fg(Color::Yellow),
+            ));
+        }
+        if !app.selected_hosts.is_empty() {
+            spans.push(Span::styled(
+                format!("  [{} selected]", app.selected_hosts.len()),
+                Style::default().fg(Color::LightBlue),
+            ));
+        }
+        if let Some(msg) = app.current_status_message() {
+            spans.push(Span::styled(
+                format!("  {msg}"),
+                Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+            ));
+        }
+        Line::from(spans)
     };
 
     let footer_widget = Paragraph::new(footer)
@@ -473,6 +1034,38 @@ fn draw_help_overlay(f: &mut Frame) {
             Span::styled("  Enter       ", Style::default().fg(Color::Yellow)),
             Span::raw("Toggle detail panel"),
         ]),
+        Line::from(vec![
+            Span::styled("  Tab         ", Style::default().fg(Color::Yellow)),
+            Span::raw("Focus detail panel (j/k scroll it)"),
+        ]),
+        Line::from(vec![
+            Span::styled("  F           ", Style::default().fg(Color::Yellow)),
+            Span::raw("Full-screen detail panel (F/Esc back)"),
+        ]),
+        Line::from(vec![
+            Span::styled("  Space       ", Style::default().fg(Color::Yellow)),
+            Span::raw("Select/unselect host"),
+        ]),
+        Line::from(vec![
+            Span::styled("  y           ", Style::default().fg(Color::Yellow)),
+            Span::raw("Copy host name to clipboard"),
+        ]),
+        Line::from(vec![
+            Span::styled("  o           ", Style::default().fg(Color::Yellow)),
+            Span::raw("Open SSH session to host"),
+        ]),
+        Line::from(vec![
+            Span::styled("  f           ", Style::default().fg(Color::Yellow)),
+            Span::raw("Freeze/unfreeze host (stop polling it)"),
+        ]),
+        Line::from(vec![
+            Span::styled("  H           ", Style::default().fg(Color::Yellow)),
+            Span::raw("Toggle group summary rows"),
+        ]),
+        Line::from(vec![
+            Span::styled("  [ / ]       ", Style::default().fg(Color::Yellow)),
+            Span::raw("Replay older / newer poll snapshots"),
+        ]),
         Line::from(vec![
             Span::styled("  s / S       ", Style::default().fg(Color::Yellow)),
             Span::raw("Cycle sort / Reverse sort"),
@@ -481,10 +1074,78 @@ fn draw_help_overlay(f: &mut Frame) {
             Span::styled("  /           ", Style::default().fg(Color::Yellow)),
             Span::raw("Filter hosts by name/group"),
         ]),
+        Line::from(vec![
+            Span::styled("  Tab         ", Style::default().fg(Color::Yellow)),
+            Span::raw("Next filter preset (while filtering)"),
+        ]),
         Line::from(vec![
             Span::styled("  r           ", Style::default().fg(Color::Yellow)),
             Span::raw("Force refresh all hosts"),
         ]),
+        Line::from(vec![
+            Span::styled("  F5          ", Style::default().fg(Color::Yellow)),
+            Span::raw("Refresh selected host"),
+        ]),
+        Line::from(vec![
+            Span::styled("  R           ", Style::default().fg(Color::Yellow)),
+            Span::raw("Reload inventory"),
+        ]),
+        Line::from(vec![
+            Span::styled("  a           ", Style::default().fg(Color::Yellow)),
+            Span::raw("Toggle address column"),
+        ]),
+        Line::from(vec![
+            Span::styled("  u           ", Style::default().fg(Color::Yellow)),
+            Span::raw("Toggle failed systemd units column"),
+        ]),
+        Line::from(vec![
+            Span::styled("  l           ", Style::default().fg(Color::Yellow)),
+            Span::raw("Toggle load average column"),
+        ]),
+        Line::from(vec![
+            Span::styled("  t           ", Style::default().fg(Color::Yellow)),
+            Span::raw("Toggle temperature column"),
+        ]),
+        Line::from(vec![
+            Span::styled("  n           ", Style::default().fg(Color::Yellow)),
+            Span::raw("Toggle network I/O column"),
+        ]),
+        Line::from(vec![
+            Span::styled("  L           ", Style::default().fg(Color::Yellow)),
+            Span::raw("Toggle SSH latency column"),
+        ]),
+        Line::from(vec![
+            Span::styled("  U           ", Style::default().fg(Color::Yellow)),
+            Span::raw("Toggle uptime column"),
+        ]),
+        Line::from(vec![
+            Span::styled("  c           ", Style::default().fg(Color::Yellow)),
+            Span::raw("Cycle column sets (configured, all, compact)"),
+        ]),
+        Line::from(vec![
+            Span::styled("  w           ", Style::default().fg(Color::Yellow)),
+            Span::raw("Wide mode: add Load, Net, TCP and Lat columns"),
+        ]),
+        Line::from(vec![
+            Span::styled("  C           ", Style::default().fg(Color::Yellow)),
+            Span::raw("Compact mode (upper case: c cycles column sets)"),
+        ]),
+        Line::from(vec![
+            Span::raw("              "),
+            Span::raw("hides Group/IOw/Swap; CPU/Mem/Disk 8 + 2 arrow"),
+        ]),
+        Line::from(vec![
+            Span::styled("  m           ", Style::default().fg(Color::Yellow)),
+            Span::raw("Show other group count (web,+2)"),
+        ]),
+        Line::from(vec![
+            Span::styled("  !           ", Style::default().fg(Color::Yellow)),
+            Span::raw("Inventory warnings"),
+        ]),
+        Line::from(vec![
+            Span::styled("  A           ", Style::default().fg(Color::Yellow)),
+            Span::raw("Recent alerts (hosts down or critical)"),
+        ]),
         Line::from(vec![
             Span::styled("  ?           ", Style::default().fg(Color::Yellow)),
             Span::raw("Toggle this help"),
@@ -506,6 +1167,85 @@ fn draw_help_overlay(f: &mut Frame) {
     f.render_widget(help, area);
 }
 
+fn draw_warnings_overlay(f: &mut Frame, app: &App) {
+    let area = centered_rect(70, 60, f.area());
+    f.render_widget(Clear, area);
+
+    let mut lines = vec![
+        Line::from(Span::styled(
+            "Inventory Warnings",
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )),
+        Line::from(""),
+    ];
+    if app.inventory_warnings.is_empty() {
+        lines.push(Line::from("No warnings"));
+    }
+    for warning in &app.inventory_warnings {
+        lines.push(Line::from(format!("  {warning}")));
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        "  Press any key to close",
+        Style::default().fg(Color::DarkGray),
+    )));
+
+    let popup = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow))
+                .title(" Warnings "),
+        )
+        .wrap(Wrap { trim: false });
+    f.render_widget(popup, area);
+}
+
+fn draw_alerts_overlay(f: &mut Frame, app: &App) {
+    let area = centered_rect(70, 60, f.area());
+    f.render_widget(Clear, area);
+
+    let mut lines = vec![
+        Line::from(Span::styled(
+            "Recent Alerts",
+            Style::default()
+                .fg(Color::Red)
+                .add_modifier(Modifier::BOLD),
+        )),
+        Line::from(""),
+    ];
+    if app.recent_alerts.is_empty() {
+        lines.push(Line::from("No alerts"));
+    }
+    for (host, message, at) in &app.recent_alerts {
+        lines.push(Line::from(vec![
+            Span::styled(
+                format!("  {:>5} ago  ", format_age(at.elapsed())),
+                Style::default().fg(Color::DarkGray),
+            ),
+            Span::styled(host.as_str(), Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(format!(" {message}")),
+        ]));
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        "  Press any key to close",
+        Style::default().fg(Color::DarkGray),
+    )));
+
+    let popup = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Red))
+                .title(" Alerts "),
+        )
+        .wrap(Wrap { trim: false });
+    f.render_widget(popup, area);
+}
+
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)